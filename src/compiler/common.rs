@@ -1,3 +1,11 @@
 pub type Location = usize;
 
 pub type Id = usize;
+
+/// The one-based line and column of a location in the source text.
+pub fn line_column(text: &str, location: Location) -> (usize, usize) {
+    let before = &text[..location];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&ch| ch != '\n').count() + 1;
+    (line, column)
+}
@@ -1,13 +1,25 @@
 use super::common::Id;
 use super::generate::Arg;
 use super::generate::BinaryOp;
+use super::generate::Block;
+use super::generate::CallKind;
+use super::generate::CastOp;
+use super::generate::Declare;
 use super::generate::Def;
+use super::generate::GetElementPtr;
+use super::generate::Incoming;
 use super::generate::Instruction;
+use super::generate::Label;
 use super::generate::Param;
+use super::generate::StructDef;
+use super::generate::Switch;
 use super::generate::Target;
 use super::generate::UnaryOp;
 use super::generate::Val;
+use super::symbol::Func;
+use super::symbol::Signedness;
 use super::symbol::Terminal;
+use super::symbol::Type;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
@@ -18,48 +30,154 @@ pub fn emit(target: Target) -> String {
 
 impl Display for Target {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for s in &self.structs {
+            write!(f, "{s}")?;
+        }
+        if !self.structs.is_empty() {
+            writeln!(f)?;
+        }
+        for (id, string) in self.strings.iter().enumerate() {
+            writeln!(
+                f,
+                "@str.{} = private unnamed_addr constant [{} x i8] c\"{}\"",
+                id,
+                string.len(),
+                Escaped(string)
+            )?;
+        }
+        if !self.strings.is_empty() {
+            writeln!(f)?;
+        }
         for def in &self.defs {
             write!(f, "{}", def)?;
         }
+        for declare in &self.declares {
+            write!(f, "{declare}")?;
+        }
         Ok(())
     }
 }
 
+struct Escaped<'a>(&'a [u8]);
+
+impl Display for Escaped<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for &byte in self.0 {
+            if byte.is_ascii_graphic() && byte != b'"' && byte != b'\\' || byte == b' ' {
+                write!(f, "{}", byte as char)?;
+            } else {
+                write!(f, "\\{byte:02X}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Declare {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "declare {} @{}(", self.ret, self.name)?;
+        if let Some((first, rest)) = self.params.split_first() {
+            write!(f, "{first}")?;
+            for param in rest {
+                write!(f, ", {param}")?;
+            }
+        }
+        writeln!(f, ")")
+    }
+}
+
+impl Display for StructDef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "%{} = type {{", self.name)?;
+        if let Some((first, rest)) = self.fields.split_first() {
+            write!(f, " {first}")?;
+            for field in rest {
+                write!(f, ", {field}")?;
+            }
+            write!(f, " ")?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
 impl Display for Def {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         writeln!(
             f,
-            "define {} @{}({}) {{\n{}}}",
+            "define {}{} @{}({}) {{\n{}}}",
+            Ext(&self.ret, self.export),
             self.ret,
             IdName(&self.name.token, self.name.id),
-            Params(&self.params),
-            Instructions(&self.instructions),
+            Params(&self.params, self.export),
+            Blocks(&self.blocks),
         )
     }
 }
 
-struct Params<'a>(&'a Vec<Param>);
+struct Blocks<'a>(&'a Vec<Block>);
+
+impl Display for Blocks<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for block in self.0 {
+            match block.label {
+                Label::Entry(_) => (),
+                Label::Named(name, id) => writeln!(f, "{name}.{id}:")?,
+                Label::Indexed(name, id, index) => writeln!(f, "{name}.{id}.{index}:")?,
+            }
+            write!(f, "{}", Instructions(&block.instructions))?;
+        }
+        Ok(())
+    }
+}
+
+struct Params<'a>(&'a Vec<Param>, bool);
 
 impl Display for Params<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         if let Some((first, rest)) = self.0.split_first() {
-            write!(f, "{} %{}", first.typ, first.id)?;
+            write!(f, "{} {}%{}", first.typ, Ext(&first.typ, self.1), first.id)?;
             for param in rest {
-                write!(f, ", {} %{}", param.typ, param.id)?;
+                write!(
+                    f,
+                    ", {} {}%{}",
+                    param.typ,
+                    Ext(&param.typ, self.1),
+                    param.id
+                )?;
             }
         }
         Ok(())
     }
 }
 
-struct Args<'a>(&'a Vec<Arg>);
+/// C expects integers narrower than `int` to be extended by whoever passes
+/// them, so exported functions mark them the way a C compiler would, and so
+/// do calls to them.
+struct Ext<'a>(&'a Type, bool);
+
+impl Display for Ext<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if !self.1 {
+            return Ok(());
+        }
+        match self.0 {
+            Type::Terminal(Terminal::Bool | Terminal::U8 | Terminal::U16) => {
+                f.write_str("zeroext ")
+            }
+            Type::Terminal(Terminal::I8 | Terminal::I16) => f.write_str("signext "),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct Args<'a>(&'a Vec<Arg>, bool);
 
 impl Display for Args<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         if let Some((first, rest)) = self.0.split_first() {
-            write!(f, "{} {}", first.typ, first.val)?;
+            write!(f, "{} {}{}", first.typ, Ext(&first.typ, self.1), first.val)?;
             for arg in rest {
-                write!(f, ", {} {}", arg.typ, arg.val)?;
+                write!(f, ", {} {}{}", arg.typ, Ext(&arg.typ, self.1), arg.val)?;
             }
         }
         Ok(())
@@ -91,19 +209,23 @@ impl Display for Instruction {
                 if let Some(id) = &call.id {
                     writeln!(
                         f,
-                        "%{} = call {} @{}({})",
+                        "%{} = {} {}{} {}({})",
                         id,
+                        call.kind,
+                        Ext(&call.typ, call.export),
                         call.typ,
-                        IdName(&call.called_name.token, call.called_name.id),
-                        Args(&call.args)
+                        call.callee,
+                        Args(&call.args, call.export)
                     )
                 } else {
                     writeln!(
                         f,
-                        "call {} @{}({})",
+                        "{} {}{} {}({})",
+                        call.kind,
+                        Ext(&call.typ, call.export),
                         call.typ,
-                        IdName(&call.called_name.token, call.called_name.id),
-                        Args(&call.args)
+                        call.callee,
+                        Args(&call.args, call.export)
                     )
                 }
             }
@@ -126,18 +248,143 @@ impl Display for Instruction {
                     binary.arg2
                 )
             }
+            Self::Cast(cast) => writeln!(
+                f,
+                "%{} = {} {} {} to {}",
+                cast.id, cast.op, cast.from, cast.val, cast.to
+            ),
+            Self::Bitcast(bitcast) => writeln!(
+                f,
+                "%{} = bitcast {} {} to {}",
+                bitcast.id, bitcast.from, bitcast.val, bitcast.to
+            ),
+            Self::Br(br) => writeln!(f, "br label {}", br.label),
+            Self::CondBr(cond_br) => writeln!(
+                f,
+                "br i1 {}, label {}, label {}",
+                cond_br.cond, cond_br.then, cond_br.els
+            ),
+            Self::Phi(phi) => writeln!(
+                f,
+                "%{} = phi {} {}",
+                phi.id,
+                phi.typ,
+                IncomingList(&phi.incoming)
+            ),
+            Self::Alloca(alloca) => writeln!(f, "{} = alloca {}", Slot(alloca.slot), alloca.typ),
+            Self::Load(load) => writeln!(
+                f,
+                "%{} = load {}, {}* {}",
+                load.id, load.typ, load.typ, load.ptr
+            ),
+            Self::Store(store) => writeln!(
+                f,
+                "store {} {}, {}* {}",
+                store.typ, store.val, store.typ, store.ptr
+            ),
+            Self::InsertValue(insert) => writeln!(
+                f,
+                "%{} = insertvalue {} {}, {} {}, {}",
+                insert.id, insert.typ, insert.agg, insert.val_typ, insert.val, insert.index
+            ),
+            Self::ExtractValue(extract) => writeln!(
+                f,
+                "%{} = extractvalue {} {}, {}",
+                extract.id, extract.typ, extract.agg, extract.index
+            ),
+            Self::Switch(switch) => write!(f, "{switch}"),
+            Self::Unreachable => writeln!(f, "unreachable"),
+            Self::GetElementPtr(gep) => write!(f, "{gep}"),
+        }
+    }
+}
+
+impl Display for Switch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "switch i32 {}, label {} [", self.val, self.default)?;
+        for (tag, label) in &self.cases {
+            write!(f, " i32 {tag}, label {label}")?;
+        }
+        writeln!(f, " ]")
+    }
+}
+
+impl Display for GetElementPtr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "%{} = getelementptr {}, {}* {}",
+            self.id, self.typ, self.typ, self.ptr
+        )?;
+        for index in &self.indices {
+            write!(f, ", i64 {index}")?;
+        }
+        writeln!(f)
+    }
+}
+
+struct IncomingList<'a>(&'a Vec<Incoming>);
+
+impl Display for IncomingList<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if let Some((first, rest)) = self.0.split_first() {
+            write!(f, "[{}, {}]", first.val, first.label)?;
+            for incoming in rest {
+                write!(f, ", [{}, {}]", incoming.val, incoming.label)?;
+            }
         }
+        Ok(())
+    }
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Entry(id) => write!(f, "%{id}"),
+            Self::Named(name, id) => write!(f, "%{name}.{id}"),
+            Self::Indexed(name, id, index) => write!(f, "%{name}.{id}.{index}"),
+        }
+    }
+}
+
+impl Display for CallKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str(match self {
+            Self::Normal => "call",
+            Self::Tail => "tail call",
+            Self::MustTail => "musttail call",
+        })
+    }
+}
+
+struct Slot(Id);
+
+impl Display for Slot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "%slot.{}", self.0)
     }
 }
 
 struct IdName<'a>(&'a str, Id);
 
 impl Display for IdName<'_> {
+    /// A name LLVM would not accept bare, such as that of a def named `==`,
+    /// is quoted.
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let bare = self
+            .0
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '$'));
+        if !bare {
+            f.write_str("\"")?;
+        }
         f.write_str(self.0)?;
         if self.1 != 0 {
             write!(f, "{}", self.1)?;
         }
+        if !bare {
+            f.write_str("\"")?;
+        }
         Ok(())
     }
 }
@@ -147,23 +394,128 @@ impl Display for Val {
         match self {
             Self::Id(id) => write!(f, "%{}", *id),
             Self::Literal(literal) => f.write_str(literal),
+            Self::Float(value, terminal) => write!(f, "{}", Float(*value, *terminal)),
+            Self::Undef => f.write_str("undef"),
+            Self::Slot(slot) => write!(f, "{}", Slot(*slot)),
+            Self::Str(id, len) => write!(
+                f,
+                "getelementptr inbounds ([{len} x i8], [{len} x i8]* @str.{id}, i64 0, i64 0)"
+            ),
+            Self::Func(name) => write!(f, "@{}", IdName(&name.token, name.id)),
+            Self::SizeOf(typ) => write!(
+                f,
+                "ptrtoint ({typ}* getelementptr ({typ}, {typ}* null, i32 1) to i64)"
+            ),
         }
     }
 }
 
+impl Display for CastOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str(match self {
+            Self::ZExt => "zext",
+            Self::SExt => "sext",
+            Self::Trunc => "trunc",
+            Self::FpExt => "fpext",
+            Self::FpTrunc => "fptrunc",
+            Self::UiToFp => "uitofp",
+            Self::SiToFp => "sitofp",
+            Self::FpToUi => "fptoui",
+            Self::FpToSi => "fptosi",
+        })
+    }
+}
+
+/// LLVM only accepts a decimal float constant if it is exact for its type,
+/// so anything else is written in hex: as the bits of a `double` holding the
+/// rounded value, or as the bits of the `half` itself.
+struct Float(f64, Terminal);
+
+impl Display for Float {
+    #[allow(clippy::cast_possible_truncation)]
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let value = match self.1 {
+            Terminal::F16 => return write!(f, "0xH{:04X}", half_bits(self.0)),
+            Terminal::F32 => f64::from(self.0 as f32),
+            _ => self.0,
+        };
+        let decimal = format!("{value:?}");
+        if value.to_bits() == self.0.to_bits() && decimal.contains('.') && !decimal.contains('e') {
+            f.write_str(&decimal)
+        } else {
+            write!(f, "0x{:016X}", value.to_bits())
+        }
+    }
+}
+
+/// Rounds a value to the nearest `half`, ties to even, and returns its bits.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn half_bits(value: f64) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 48) & 0x8000) as u16;
+    let abs = value.abs();
+
+    if abs.is_nan() {
+        return sign | 0x7E00;
+    }
+    if abs >= 65520.0 {
+        return sign | 0x7C00;
+    }
+    if abs < 2.0_f64.powi(-14) {
+        // Subnormal, in units of the smallest one. Rounding up to 0x400 gives
+        // the smallest normal number, which is also right.
+        return sign | (abs * 2.0_f64.powi(24)).round_ties_even() as u16;
+    }
+
+    let exponent = ((bits >> 52) & 0x7FF) as u16 + 15 - 1023;
+    let mantissa = bits & ((1 << 52) - 1);
+    let kept = (mantissa >> 42) as u16;
+    let rest = mantissa & ((1 << 42) - 1);
+    let half = 1 << 41;
+    let round = u16::from(rest > half || rest == half && kept & 1 == 1);
+    // A carry out of the mantissa correctly moves on to the next exponent.
+    sign | ((exponent << 10) + kept + round)
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Terminal(terminal) => write!(f, "{terminal}"),
+            Self::Struct(name) | Self::Enum(name) => write!(f, "%{name}"),
+            Self::Ptr(elem) if **elem == Self::Terminal(Terminal::Void) => f.write_str("i8*"),
+            Self::Ptr(elem) => write!(f, "{elem}*"),
+            Self::Array(elem, len) => write!(f, "[{len} x {elem}]"),
+            Self::Slice(elem) => write!(f, "{{ {elem}*, i64 }}"),
+            Self::Overflow(terminal) => write!(f, "{{ {terminal}, i1 }}"),
+            Self::Func(func) => write!(f, "{{ {}, i8* }}", Code(func)),
+            Self::Code(func) => write!(f, "{}", Code(func)),
+            Self::Param(_) => panic!(),
+        }
+    }
+}
+
+/// A function value is its code and the environment the code takes first.
+struct Code<'a>(&'a Func);
+
+impl Display for Code<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{} (i8*", self.0.ret)?;
+        for param in &self.0.params {
+            write!(f, ", {param}")?;
+        }
+        f.write_str(")*")
+    }
+}
+
 impl Display for Terminal {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         f.write_str(match self {
             Self::Void => "void",
             Self::Bool => "i1",
-            Self::U8 => "u8",
-            Self::U16 => "u16",
-            Self::U32 => "u32",
-            Self::U64 => "u64",
-            Self::I8 => "i8",
-            Self::I16 => "i16",
-            Self::I32 => "i32",
-            Self::I64 => "i64",
+            Self::U8 | Self::I8 => "i8",
+            Self::U16 | Self::I16 => "i16",
+            Self::U32 | Self::I32 => "i32",
+            Self::U64 | Self::I64 => "i64",
             Self::F16 => "half",
             Self::F32 => "float",
             Self::F64 => "double",
@@ -175,49 +527,36 @@ struct Op(BinaryOp, Terminal);
 
 impl Display for Op {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        enum IntType {
-            Unsigned,
-            Signed,
-            Float,
-        }
-
-        const fn int_type(typ: Terminal) -> IntType {
-            match typ {
-                Terminal::I8 | Terminal::I16 | Terminal::I32 | Terminal::I64 => IntType::Signed,
-                Terminal::F16 | Terminal::F32 | Terminal::F64 => IntType::Float,
-                _ => IntType::Unsigned,
-            }
-        }
-
-        let typ = int_type(self.1);
+        let signedness = self.1.signedness();
 
         macro_rules! instruction {
-            ($int_type:ident, $unsigned:literal, $signed:literal, $float:literal) => {
-                match $int_type {
-                    IntType::Unsigned => $unsigned,
-                    IntType::Signed => $signed,
-                    IntType::Float => $float,
+            ($signedness:ident, $unsigned:literal, $signed:literal, $float:literal) => {
+                match $signedness {
+                    Signedness::Unsigned => $unsigned,
+                    Signedness::Signed => $signed,
+                    Signedness::Float => $float,
                 }
             };
         }
 
         f.write_str(match self.0 {
-            BinaryOp::Add => instruction!(typ, "add", "add", "fadd"),
-            BinaryOp::Sub => instruction!(typ, "sub", "sub", "fsub"),
-            BinaryOp::Mul => instruction!(typ, "mul", "mul", "fmul"),
-            BinaryOp::Div => instruction!(typ, "udiv", "sdiv", "fdiv"),
-            BinaryOp::Rem => instruction!(typ, "urem", "srem", "frem"),
-            BinaryOp::BitAnd | BinaryOp::And => "and",
-            BinaryOp::BitOr | BinaryOp::Or => "or",
+            BinaryOp::Add => instruction!(signedness, "add", "add", "fadd"),
+            BinaryOp::Sub => instruction!(signedness, "sub", "sub", "fsub"),
+            BinaryOp::Mul => instruction!(signedness, "mul", "mul", "fmul"),
+            BinaryOp::Div => instruction!(signedness, "udiv", "sdiv", "fdiv"),
+            BinaryOp::Rem => instruction!(signedness, "urem", "srem", "frem"),
+            BinaryOp::BitAnd => "and",
+            BinaryOp::BitOr => "or",
             BinaryOp::BitXor | BinaryOp::Xor => "xor",
             BinaryOp::LShift => "shl",
-            BinaryOp::RShift => "lshr",
-            BinaryOp::Equal => instruction!(typ, "icmp eq", "icmp eq", "fcmp oeq"),
-            BinaryOp::NEqual => instruction!(typ, "icmp ne", "icmp ne", "fcmp une"),
-            BinaryOp::LEqual => instruction!(typ, "icmp ule", "icmp sle", "fcmp ole"),
-            BinaryOp::GEqual => instruction!(typ, "icmp uge", "icmp sge", "fcmp oge"),
-            BinaryOp::Less => instruction!(typ, "icmp ult", "icmp slt", "fcmp olt"),
-            BinaryOp::Greater => instruction!(typ, "icmp ugt", "icmp sgt", "fcmp ogt"),
+            BinaryOp::RShift => instruction!(signedness, "lshr", "ashr", "lshr"),
+            BinaryOp::Equal => instruction!(signedness, "icmp eq", "icmp eq", "fcmp oeq"),
+            BinaryOp::NEqual => instruction!(signedness, "icmp ne", "icmp ne", "fcmp une"),
+            BinaryOp::LEqual => instruction!(signedness, "icmp ule", "icmp sle", "fcmp ole"),
+            BinaryOp::GEqual => instruction!(signedness, "icmp uge", "icmp sge", "fcmp oge"),
+            BinaryOp::Less => instruction!(signedness, "icmp ult", "icmp slt", "fcmp olt"),
+            BinaryOp::Greater => instruction!(signedness, "icmp ugt", "icmp sgt", "fcmp ogt"),
+            BinaryOp::ULess => "icmp ult",
         })
     }
 }
@@ -1,8 +1,10 @@
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Error {
     name: &'static str,
     location: Option<usize>,
     message: String,
+    notes: Vec<(Option<usize>, String)>,
+    warning: bool,
     compiler_file: &'static str,
     compiler_line: u32,
     compiler_column: u32,
@@ -21,67 +23,84 @@ impl Error {
             name,
             location,
             message,
+            notes: Vec::new(),
+            warning: false,
             compiler_file,
             compiler_line,
             compiler_column,
         }
     }
 
-    #[cfg(test)]
     pub const fn name(&self) -> &str {
         self.name
     }
 
-    pub fn print(&self, text: &str) {
-        enum State {
-            Looking,
-            Found,
-        }
+    /// Adds a note pointing at another place the error involves, such as a
+    /// definition it conflicts with. Builtins have no place to point at.
+    pub fn with_note(mut self, location: Option<usize>, message: String) -> Self {
+        self.notes.push((location, message));
+        self
+    }
 
-        let location = if let Some(location) = self.location {
-            location
-        } else {
-            eprint!(
-                "Error: {}\n{}:{}:{}\n",
-                self.message, self.compiler_file, self.compiler_line, self.compiler_column,
-            );
-            return;
-        };
-
-        let mut state = State::Looking;
-        let mut line = String::new();
-        let mut column = 0;
-
-        for (loc, ch) in text.char_indices() {
-            if let State::Looking = state {
-                if loc == location {
-                    state = State::Found;
-                    column = line.len();
-                }
-                if ch == '\n' {
-                    line.clear();
-                } else {
-                    line.push(ch);
-                }
-            } else {
-                if ch == '\n' {
-                    break;
-                } else {
-                    line.push(ch);
-                }
-            }
-        }
+    /// Makes this a warning, which is reported without stopping compilation.
+    pub const fn into_warning(mut self) -> Self {
+        self.warning = true;
+        self
+    }
 
+    pub fn print(&self, text: &str) {
+        if let Some(location) = self.location {
+            print_source(text, location);
+        }
         eprint!(
-            "{}\n{}^\nError: {}\n{}:{}:{}\n",
-            line,
-            " ".repeat(column),
+            "{}: {}\n{}:{}:{}\n",
+            if self.warning { "Warning" } else { "Error" },
             self.message,
             self.compiler_file,
             self.compiler_line,
             self.compiler_column,
         );
+        for (location, message) in &self.notes {
+            if let Some(location) = location {
+                print_source(text, *location);
+            }
+            eprintln!("Note: {message}");
+        }
+    }
+}
+
+/// Prints the line holding `location`, with a caret under it.
+fn print_source(text: &str, location: usize) {
+    enum State {
+        Looking,
+        Found,
+    }
+
+    let mut state = State::Looking;
+    let mut line = String::new();
+    let mut column = 0;
+
+    for (loc, ch) in text.char_indices() {
+        if let State::Looking = state {
+            if loc == location {
+                state = State::Found;
+                column = line.len();
+            }
+            if ch == '\n' {
+                line.clear();
+            } else {
+                line.push(ch);
+            }
+        } else {
+            if ch == '\n' {
+                break;
+            } else {
+                line.push(ch);
+            }
+        }
     }
+
+    eprint!("{}\n{}^\n", line, " ".repeat(column));
 }
 
 macro_rules! err {
@@ -166,6 +185,258 @@ macro_rules! error_impl {
         error_new!($name, $location, format!("Unexpected nesting, expected a type for a parameter."))
     };
 
+    (expected_if, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected `if` to have a condition, a then branch and an else branch."))
+    };
+
+    (expected_let, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected `let` to have a list of bindings and a body."))
+    };
+
+    (expected_binding, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected a binding consisting of a name, a type and an expression."))
+    };
+
+    (expected_binding_name, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Unexpected nesting, expected a name for a binding."))
+    };
+
+    (expected_binding_type, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Unexpected nesting, expected a type for a binding."))
+    };
+
+    (duplicate_type_param, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("The type parameter `{}` is declared more than once.", $token))
+    };
+
+    (duplicate_binding, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is bound more than once in this `let`. Use a nested `let` to shadow it.", $token))
+    };
+
+    (unexpected_void_binding, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Bindings cannot have type `void`."))
+    };
+
+    (expected_set, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected `set!` to have a variable name and an expression."))
+    };
+
+    (expected_lambda, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected `lambda` to have a function type and a body."))
+    };
+
+    (expected_while, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected `while` to have a condition and a body."))
+    };
+
+    (expected_do_expr, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected at least one expression inside `do`."))
+    };
+
+    (expected_mutable, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is not mutable. Bind it with `(mut {} ...)` to allow `set!`.", $token, $token))
+    };
+
+    (expected_tail_call, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected `tail` to be followed by a function and its arguments."))
+    };
+
+    (unexpected_tail, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("This call is marked `tail`, but it is not in tail position."))
+    };
+
+    (captured_set, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is captured by a lambda, which holds a copy of it, so it cannot be set there.", $token))
+    };
+
+    (unresolved_type_param, $name:tt, $location:expr, $param:expr) => {
+        error_new!($name, $location, format!("Cannot tell which type the type parameter `{}` stands for in this call.", $param))
+    };
+
+    (tail_type_mismatch, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("A `tail` call must have the same parameter and return types as the calling function."))
+    };
+
+    (expected_struct_name, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected a name to start struct definition."))
+    };
+
+    (expected_field, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected a field consisting of a name and a type."))
+    };
+
+    (duplicate_field, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("Field `{}` is defined more than once in this struct.", $token))
+    };
+
+    (duplicate_type, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is already defined, so it cannot name a new type.", $token))
+    };
+
+    (unexpected_void_field, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Fields cannot have type `void`."))
+    };
+
+    (recursive_type, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("Type `{}` contains itself, so it would have infinite size.", $token))
+    };
+
+    (expected_type_form, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected a type: a name, `(ptr T)`, `(array T N)` or `(slice T)`."))
+    };
+
+    (expected_array_len, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected the length of an array type to be a non-negative integer."))
+    };
+
+    (unexpected_void_element, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Arrays and slices cannot hold `void`."))
+    };
+
+    (expected_overflow_int, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Only integer types can overflow."))
+    };
+
+    (expected_enum_name, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected a name to start enum definition."))
+    };
+
+    (expected_variant, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected a variant consisting of a name followed by field types."))
+    };
+
+    (duplicate_variant, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("Variant `{}` is defined more than once in this enum.", $token))
+    };
+
+    (expected_class_name, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected a name to start class definition."))
+    };
+
+    (expected_class_param, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected the class name to be followed by its type parameter, as in `(T)`."))
+    };
+
+    (expected_method, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected a method consisting of a name and a function type."))
+    };
+
+    (duplicate_method, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("Method `{}` is declared more than once in this class.", $token))
+    };
+
+    (expected_instance, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected an instance to name a class and a type, followed by its methods."))
+    };
+
+    (expected_method_def, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("The methods of an instance cannot be exported or generic."))
+    };
+
+    (duplicate_class, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is already defined, so it cannot name a new class.", $token))
+    };
+
+    (expected_class, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is not a class.", $token))
+    };
+
+    (duplicate_instance, $name:tt, $location:expr, $typ:expr, $class:expr) => {
+        error_new!($name, $location, format!("`{}` is already an instance of `{}`.", $typ.syntax(), $class))
+    };
+
+    (missing_method, $name:tt, $location:expr, $token:expr, $func:expr) => {
+        error_new!($name, $location, format!("This instance must define method `{}` with type `{}`.", $token, $func.syntax()))
+    };
+
+    (unexpected_method, $name:tt, $location:expr, $token:expr, $class:expr) => {
+        error_new!($name, $location, format!("`{}` is not a method of `{}`.", $token, $class))
+    };
+
+    (missing_instance, $name:tt, $location:expr, $typ:expr, $class:expr) => {
+        error_new!($name, $location, format!("`{}` is not an instance of `{}`.", $typ.syntax(), $class))
+    };
+
+    (expected_match, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected `match` to have an expression and at least one arm."))
+    };
+
+    (expected_arm, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected an arm consisting of a pattern and a body."))
+    };
+
+    (expected_pattern, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected a pattern: `_`, a variant, or a variant followed by names for its fields."))
+    };
+
+    (expected_match_variant, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Expected at least one arm matching a variant, to decide which enum is matched."))
+    };
+
+    (expected_variant_pattern, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is not a variant of the enum being matched.", $token))
+    };
+
+    (pattern_field_count, $name:tt, $location:expr, $expected:expr, $got:expr) => {
+        error_new!($name, $location, format!("This variant has {} fields, but the pattern names {}.", $expected, $got))
+    };
+
+    (unreachable_arm, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("This arm can never match, because an earlier arm covers it."))
+    };
+
+    (non_exhaustive_match, $name:tt, $location:expr, $missing:expr) => {
+        error_new!($name, $location, format!("This `match` does not cover every variant. Missing: {}.", $missing))
+    };
+
+    (literal_out_of_range, $name:tt, $location:expr, $token:expr, $terminal:expr) => {
+        error_new!($name, $location, format!("Literal `{}` is out of range for type `{}`.", $token, $terminal.name()))
+    };
+
+    (invalid_number, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is not a valid number literal.", $token))
+    };
+
+    (unterminated_string, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Unterminated string literal. It must end with `\"`."))
+    };
+
+    (invalid_string, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Invalid string literal. It must end with `\"` and may only use the escapes `\\n`, `\\t`, `\\r`, `\\0`, `\\\\`, `\\\"` and `\\xNN`."))
+    };
+
+    (unexpected_extern_token, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Unexpected extra token. Extern declaration should be a name and a type."))
+    };
+
+    (duplicate_extern, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is already defined, so it cannot name an extern function.", $token))
+    };
+
+    (runtime_name, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is a C function the compiler calls, so a def cannot take its name.", $token))
+    };
+
+    (runtime_extern, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is a C function the compiler calls, so an extern of it must have the type the compiler expects.", $token))
+    };
+
+    (invalid_export_name, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` cannot be exported, because it is not a valid C identifier.", $token))
+    };
+
+    (overloaded_export, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` cannot be exported, because other functions share its name.", $token))
+    };
+
+    (generic_export, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` cannot be exported, because it is generic.", $token))
+    };
+
+    (unexported_type, $name:tt, $location:expr) => {
+        error_new!($name, $location, format!("Exported functions can only take and return numbers, `bool` and pointers."))
+    };
+
     (expected_name, $name:tt, $location:expr) => {
         error_new!($name, $location, format!("Expected a name to start definition."))
     };
@@ -183,7 +454,7 @@ macro_rules! error_impl {
     };
 
     (expected_terminal_type, $name:tt, $location:expr) => {
-        error_new!($name, $location, format!("Expected terminal type."))
+        error_new!($name, $location, format!("Expected a type, but this names something else."))
     };
 
     (unexpected_token, $name:tt, $location:expr) => {
@@ -194,20 +465,24 @@ macro_rules! error_impl {
         error_new!($name, $location, format!("Expected a literal, variable or function call."))
     };
 
+    (builtin_value, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("`{}` is a builtin, so it cannot be used as a function value.", $token))
+    };
+
     (expected_func, $name:tt, $location:expr) => {
         error_new!($name, $location, format!("Expected a function name in the beginning of function call."))
     };
 
     (type_mismatch, $name:tt, $location:expr, $expected:expr, $got:expr) => {
-        error_new!($name, $location, format!("This type cannot be used. Expected `{:?}`, but got `{:?}`.", $expected, $got))
+        error_new!($name, $location, format!("This type cannot be used. Expected `{}`, but got `{}`.", $expected.syntax(), $got.syntax()))
     };
 
     (type_mismatch, $name:tt, $location:expr, $expected:expr) => {
-        error_new!($name, $location, format!("This type cannot be used. Expected `{:?}`.", $expected))
+        error_new!($name, $location, format!("This type cannot be used. Expected `{}`.", $expected.syntax()))
     };
 
     (func_type_mismatch, $name:tt, $location:expr, $expected:expr, $got:expr) => {
-        error_new!($name, $location, format!("Function call gives wrong type. Expected `{:?}`, but this returns `{:?}`.", $expected, $got))
+        error_new!($name, $location, format!("Function call gives wrong type. Expected `{}`, but this returns `{}`.", $expected.syntax(), $got.syntax()))
     };
 
     (expected_argument, $name:tt, $location:expr) => {
@@ -233,4 +508,17 @@ macro_rules! error_impl {
     (no_type_match, $name:tt, $location:expr) => {
         error_new!($name, $location, format!("Functions with this name exist, but none are appropriate in this context."))
     };
+    (ambiguous_overload, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("More than one `{}` is equally appropriate in this context.", $token))
+    };
+    (duplicate_overload, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("Another `{}` has the same type, so calls could never tell them apart.", $token))
+    };
+    (shadowed_builtin, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("A builtin `{}` has the same type, so calls of that type are made to this extern instead.", $token))
+    };
+
+    (similar_overload, $name:tt, $location:expr, $token:expr) => {
+        error_new!($name, $location, format!("Another `{}` has the same parameter types, so only the type expected of a call can tell them apart.", $token))
+    };
 }
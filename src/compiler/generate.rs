@@ -1,47 +1,104 @@
+use super::common::line_column;
 use super::common::Id;
+use super::common::Location;
+use super::literalise;
+use super::literalise::Number;
 use super::namespace::Namespace;
 use super::parse;
 use super::parse::Expr;
 use super::parse::NameId;
 use super::parse::Program;
+use super::resolve;
 use super::symbol::Func;
+use super::symbol::Intrinsic;
+use super::symbol::Signedness;
 use super::symbol::Symbol;
 use super::symbol::Terminal;
 use super::symbol::Type;
+use super::symbol::Variant;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::slice;
+
+/// Settings that apply to the whole program.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Check arithmetic on every def, as if each carried the `checked`
+    /// attribute.
+    pub checked: bool,
+}
 
 #[derive(Debug)]
 pub struct Target {
+    pub structs: Vec<StructDef>,
+    pub strings: Vec<Vec<u8>>,
     pub defs: Vec<Def>,
+    pub declares: Vec<Declare>,
+}
+
+#[derive(Debug)]
+pub struct StructDef {
+    pub name: String,
+    pub fields: Vec<Type>,
+}
+
+#[derive(Debug)]
+pub struct Declare {
+    pub ret: Type,
+    pub name: String,
+    pub params: Vec<Type>,
 }
 
 #[derive(Debug)]
 pub struct Def {
-    pub ret: Terminal,
+    pub ret: Type,
     pub name: Name,
     pub params: Vec<Param>,
+    pub blocks: Vec<Block>,
+    pub export: bool,
+}
+
+#[derive(Debug)]
+pub struct Block {
+    pub label: Label,
     pub instructions: Vec<Instruction>,
 }
 
+#[derive(Debug, Copy, Clone)]
+pub enum Label {
+    Entry(Id),
+    Named(&'static str, Id),
+    Indexed(&'static str, Id, usize),
+}
+
 #[derive(Debug)]
 pub struct Param {
-    pub typ: Terminal,
+    pub typ: Type,
     pub id: Id,
 }
 
 #[derive(Debug)]
 pub struct Arg {
-    pub typ: Terminal,
+    pub typ: Type,
     pub val: Val,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Val {
     Id(Id),
     Literal(String),
+    Float(f64, Terminal),
+    Undef,
+    Slot(Id),
+    /// A pointer to the first byte of a string global, and its length.
+    Str(Id, usize),
+    /// A def, extern, lambda or wrapper, as a pointer to its code.
+    Func(Name),
+    /// The size of a type in bytes, as an `i64` constant.
+    SizeOf(Type),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Name {
     pub token: String,
     pub id: Id,
@@ -53,20 +110,47 @@ pub enum Instruction {
     Call(Call),
     Unary(Unary),
     Binary(Binary),
+    Cast(Cast),
+    Bitcast(Bitcast),
+    Br(Br),
+    CondBr(CondBr),
+    Phi(Phi),
+    Alloca(Alloca),
+    Load(Load),
+    Store(Store),
+    InsertValue(InsertValue),
+    ExtractValue(ExtractValue),
+    Switch(Switch),
+    Unreachable,
+    GetElementPtr(GetElementPtr),
 }
 
 #[derive(Debug)]
 pub struct Ret {
-    pub typ: Terminal,
+    pub typ: Type,
     pub val: Option<Val>,
 }
 
 #[derive(Debug)]
 pub struct Call {
     pub id: Option<Id>,
-    pub typ: Terminal,
-    pub called_name: Name,
+    pub kind: CallKind,
+    pub typ: Type,
+    pub callee: Val,
     pub args: Vec<Arg>,
+    /// Whether the callee is an exported def, whose narrow integers every
+    /// call must extend the way its prototype says.
+    pub export: bool,
+}
+
+/// Calls in tail position are marked `musttail` when the callee has the same
+/// prototype as the caller, which LLVM guarantees to lower without growing
+/// the stack. Other tail calls only get the `tail` hint.
+#[derive(Debug, Copy, Clone)]
+pub enum CallKind {
+    Normal,
+    Tail,
+    MustTail,
 }
 
 #[derive(Debug)]
@@ -86,10 +170,150 @@ pub struct Binary {
     pub arg2: Val,
 }
 
+/// Converts between pointer types.
+#[derive(Debug)]
+pub struct Bitcast {
+    pub id: Id,
+    pub from: Type,
+    pub val: Val,
+    pub to: Type,
+}
+
+#[derive(Debug)]
+pub struct Cast {
+    pub id: Id,
+    pub op: CastOp,
+    pub from: Terminal,
+    pub val: Val,
+    pub to: Terminal,
+}
+
+#[derive(Debug)]
+pub struct Br {
+    pub label: Label,
+}
+
+#[derive(Debug)]
+pub struct CondBr {
+    pub cond: Val,
+    pub then: Label,
+    pub els: Label,
+}
+
+#[derive(Debug)]
+pub struct Switch {
+    pub val: Val,
+    pub default: Label,
+    pub cases: Vec<(usize, Label)>,
+}
+
+#[derive(Debug)]
+pub struct Phi {
+    pub id: Id,
+    pub typ: Type,
+    pub incoming: Vec<Incoming>,
+}
+
+#[derive(Debug)]
+pub struct Incoming {
+    pub val: Val,
+    pub label: Label,
+}
+
+#[derive(Debug)]
+pub struct Alloca {
+    pub slot: Id,
+    pub typ: Type,
+}
+
+#[derive(Debug)]
+pub struct Load {
+    pub id: Id,
+    pub typ: Type,
+    pub ptr: Val,
+}
+
+#[derive(Debug)]
+pub struct Store {
+    pub typ: Type,
+    pub val: Val,
+    pub ptr: Val,
+}
+
+/// Indexes into memory of type `typ` pointed to by `ptr`, with `i64` indices.
+#[derive(Debug)]
+pub struct GetElementPtr {
+    pub id: Id,
+    pub typ: Type,
+    pub ptr: Val,
+    pub indices: Vec<Val>,
+}
+
+/// Builds a struct value one field at a time, starting from `undef`.
+#[derive(Debug)]
+pub struct InsertValue {
+    pub id: Id,
+    pub typ: Type,
+    pub agg: Val,
+    pub val_typ: Type,
+    pub val: Val,
+    pub index: usize,
+}
+
+#[derive(Debug)]
+pub struct ExtractValue {
+    pub id: Id,
+    pub typ: Type,
+    pub agg: Val,
+    pub index: usize,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum Op {
     UnaryOp(UnaryOp),
     BinaryOp(BinaryOp),
+    /// Integer arithmetic that wraps on overflow, even in checked code.
+    Wrapping(BinaryOp),
+    /// Integer arithmetic that clamps to the bounds of its type.
+    Saturating(BinaryOp),
+    /// Integer arithmetic that also reports whether it overflowed.
+    Overflowing(BinaryOp),
+    Math(MathOp),
+    Cast,
+    /// `&&` and `||`, which only evaluate their right operand when the left
+    /// one doesn't already decide the result.
+    And,
+    Or,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum CastOp {
+    ZExt,
+    SExt,
+    Trunc,
+    FpExt,
+    FpTrunc,
+    UiToFp,
+    SiToFp,
+    FpToUi,
+    FpToSi,
+}
+
+/// Builtins lowered to LLVM intrinsics.
+#[derive(Debug, Copy, Clone)]
+pub enum MathOp {
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+    Floor,
+    Ceil,
+    Fma,
+    Popcount,
+    Clz,
+    Ctz,
+    Rotl,
+    Rotr,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -98,15 +322,13 @@ pub enum UnaryOp {
     BitNot,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BinaryOp {
     Add,
     Sub,
     Mul,
     Div,
     Rem,
-    And,
-    Or,
     Xor,
     BitAnd,
     BitOr,
@@ -119,40 +341,84 @@ pub enum BinaryOp {
     GEqual,
     Less,
     Greater,
+    /// An unsigned comparison for bounds checks, which also rejects negative
+    /// indices. It has no operator of its own.
+    ULess,
+}
+
+#[derive(Clone)]
+enum Local {
+    Val(Val, Type),
+    Slot(Id, Type),
 }
 
 struct IdMap {
-    ids: HashMap<String, Id>,
+    scopes: Vec<HashMap<String, Local>>,
     id_count: Id,
+    label_count: Id,
+    slot_count: Id,
 }
 
 impl IdMap {
     fn new() -> Self {
         Self {
-            ids: HashMap::new(),
+            scopes: vec![HashMap::new()],
             id_count: 0,
+            label_count: 0,
+            slot_count: 0,
         }
     }
 
-    fn get(&self, key: &str) -> Id {
-        *self.ids.get(key).unwrap()
+    fn get(&self, key: &str) -> Option<Local> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(key))
+            .cloned()
     }
 
-    fn insert(&mut self, key: String) -> Id {
+    fn insert(&mut self, key: String, typ: Type) -> Id {
         let id = self.id_count;
-        self.ids.insert(key, id);
+        self.bind(key, Local::Val(Val::Id(id), typ));
         self.id_count += 1;
         id
     }
 
+    fn bind(&mut self, key: String, local: Local) {
+        self.scopes.last_mut().unwrap().insert(key, local);
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
     fn add(&mut self) -> Id {
         let id = self.id_count;
         self.id_count += 1;
         id
     }
 
+    const fn add_label(&mut self) -> Id {
+        let id = self.label_count;
+        self.label_count += 1;
+        id
+    }
+
+    const fn add_slot(&mut self) -> Id {
+        let id = self.slot_count;
+        self.slot_count += 1;
+        id
+    }
+
     fn reset(&mut self) {
+        self.scopes = vec![HashMap::new()];
         self.id_count = 0;
+        self.label_count = 0;
+        self.slot_count = 0;
     }
 }
 
@@ -169,6 +435,24 @@ fn ops() -> HashMap<String, Op> {
         };
     }
 
+    macro_rules! arith {
+        ($s:literal, $kind:tt, $op:tt) => {
+            ($s.to_string(), Op::$kind(BinaryOp::$op))
+        };
+    }
+
+    macro_rules! math {
+        ($s:literal, $op:tt) => {
+            ($s.to_string(), Op::Math(MathOp::$op))
+        };
+    }
+
+    macro_rules! cast {
+        ($s:literal) => {
+            ($s.to_string(), Op::Cast)
+        };
+    }
+
     vec![
         unary!("!", Not),
         unary!("~", BitNot),
@@ -177,13 +461,22 @@ fn ops() -> HashMap<String, Op> {
         binary!("*", Mul),
         binary!("/", Div),
         binary!("%", Rem),
+        arith!("+%", Wrapping, Add),
+        arith!("-%", Wrapping, Sub),
+        arith!("*%", Wrapping, Mul),
+        arith!("+|", Saturating, Add),
+        arith!("-|", Saturating, Sub),
+        arith!("*|", Saturating, Mul),
+        arith!("+?", Overflowing, Add),
+        arith!("-?", Overflowing, Sub),
+        arith!("*?", Overflowing, Mul),
         binary!("&", BitAnd),
         binary!("|", BitOr),
         binary!("^", BitXor),
         binary!("<<", LShift),
         binary!(">>", RShift),
-        binary!("&&", And),
-        binary!("||", Or),
+        ("&&".to_string(), Op::And),
+        ("||".to_string(), Op::Or),
         binary!("^^", Xor),
         binary!("==", Equal),
         binary!("!=", NEqual),
@@ -191,220 +484,2199 @@ fn ops() -> HashMap<String, Op> {
         binary!(">=", GEqual),
         binary!("<", Less),
         binary!(">", Greater),
+        math!("sqrt", Sqrt),
+        math!("abs", Abs),
+        math!("min", Min),
+        math!("max", Max),
+        math!("floor", Floor),
+        math!("ceil", Ceil),
+        math!("fma", Fma),
+        math!("popcount", Popcount),
+        math!("clz", Clz),
+        math!("ctz", Ctz),
+        math!("rotl", Rotl),
+        math!("rotr", Rotr),
+        cast!("u8"),
+        cast!("u16"),
+        cast!("u32"),
+        cast!("u64"),
+        cast!("i8"),
+        cast!("i16"),
+        cast!("i32"),
+        cast!("i64"),
+        cast!("f16"),
+        cast!("f32"),
+        cast!("f64"),
     ]
     .into_iter()
     .collect()
 }
 
 struct Info<'a> {
-    instructions: &'a mut Vec<Instruction>,
+    blocks: &'a mut Vec<Block>,
     id_map: &'a mut IdMap,
     namespace: &'a Namespace,
-    def_namespace: &'a Namespace,
+    def_symbol: &'a Symbol,
     ops: &'a HashMap<String, Op>,
+    strings: &'a mut Vec<Vec<u8>>,
+    text: &'a str,
+    trap: &'a mut bool,
+    checked: bool,
+    intrinsics: &'a mut Vec<Declare>,
+    structs: &'a mut Vec<StructDef>,
+    lambdas: &'a mut Vec<Def>,
+    lambda_count: &'a mut usize,
+    wrappers: &'a mut Vec<(Name, Func)>,
+    exports: &'a HashSet<&'a str>,
+    type_params: &'a [String],
+    type_args: &'a [Type],
+    instances: &'a mut Vec<(Name, Vec<Type>)>,
+}
+
+impl Info<'_> {
+    /// Types inside a generic def mention its type parameters, which are
+    /// replaced by the arguments of the instance being generated.
+    fn subst(&self, typ: &Type) -> Type {
+        typ.substitute(self.type_params, self.type_args)
+    }
+
+    fn subst_func(&self, func: &Func) -> Func {
+        func.substitute(self.type_params, self.type_args)
+    }
+
+    fn get_type(&self, typ: &parse::Type) -> Type {
+        let typ = resolve::get_generic_type(typ, self.type_params, self.namespace).unwrap();
+        self.subst(&typ)
+    }
+
+    fn push(&mut self, instruction: Instruction) {
+        self.blocks
+            .last_mut()
+            .unwrap()
+            .instructions
+            .push(instruction);
+    }
+
+    fn label(&self) -> Label {
+        self.blocks.last().unwrap().label
+    }
+
+    fn start_block(&mut self, label: Label) {
+        self.blocks.push(Block {
+            label,
+            instructions: Vec::new(),
+        });
+    }
+
+    /// Allocas all go to the top of the entry block, so a slot declared inside
+    /// a loop body is allocated once rather than on every iteration.
+    fn alloca(&mut self, typ: Type) -> Id {
+        let slot = self.id_map.add_slot();
+        let instruction = Instruction::Alloca(Alloca { slot, typ });
+        self.blocks[0].instructions.insert(slot, instruction);
+        slot
+    }
 }
 
-pub fn generate(program: Program, namespace: Namespace) -> Target {
+pub fn generate(program: Program, namespace: Namespace, text: &str, options: &Options) -> Target {
     let ops = ops();
 
+    let mut structs = generate_structs(&program, &namespace);
+
     let mut defs = Vec::new();
     let mut id_map = IdMap::new();
+    let mut strings = Vec::new();
+    let mut trap = false;
+    let mut intrinsics = Vec::new();
+    let mut lambdas = Vec::new();
+    let mut lambda_count = 0;
+    let mut wrappers = Vec::new();
+    let mut instances = Vec::new();
+    let exports: HashSet<_> = program
+        .defs
+        .iter()
+        .filter(|def| def.export)
+        .map(|def| def.name_id.token.as_str())
+        .collect();
 
-    for def in &program.defs {
-        let def_name_token = def.name_id.token.clone();
-        let def_namespace = namespace
-            .get_then(&def.name_id.token, def.name_id.id)
-            .unwrap();
-
-        let mut params = Vec::new();
-        for param in &def.func.params {
-            let (param_type_token, param_id) = match param {
-                parse::Param::Decl(parse::Decl { name, typ, .. }) => {
-                    (&typ.token, id_map.insert(name.token.clone()))
-                }
-                parse::Param::Type(parse::Type { token: typ, .. }) => (typ, id_map.add()),
-            };
-
-            let param_type = get_terminal(param_type_token, &namespace);
-
-            let param = Param {
-                typ: param_type,
-                id: param_id,
-            };
-            params.push(param);
-        }
+    // Generic defs are generated once for each instance a call asks for,
+    // after every other def.
+    let mut queue: Vec<_> = program
+        .defs
+        .iter()
+        .filter(|def| def.type_params.is_empty())
+        .map(|def| (def, Vec::new()))
+        .collect();
+    let mut queued = 0;
+    let mut index = 0;
+    while index < queue.len() {
+        let (def, type_args) = queue[index].clone();
+        index += 1;
 
-        let ret = get_terminal(&def.func.ret.token, &namespace);
+        let (name, func) = def_instance(def, &type_args, &namespace);
+        let def_symbol = Symbol::Var(Type::Func(func.clone()));
+        let type_params = resolve::type_param_names(def);
 
-        id_map.add();
+        let params = generate_params(&def.func.params, &func.params, &mut id_map);
+        let ret = (*func.ret).clone();
 
-        let mut instructions = Vec::new();
-        let ret_val = generate_expr(
-            &def.expr,
-            &mut Info {
-                instructions: &mut instructions,
-                id_map: &mut id_map,
-                namespace: &namespace,
-                def_namespace,
-                ops: &ops,
-            },
-        );
-        let ret_instruction = Instruction::Ret(Ret {
-            typ: ret,
-            val: ret_val,
-        });
-        instructions.push(ret_instruction);
+        let mut blocks = vec![Block {
+            label: Label::Entry(id_map.add()),
+            instructions: Vec::new(),
+        }];
+        let mut info = Info {
+            blocks: &mut blocks,
+            id_map: &mut id_map,
+            namespace: &namespace,
+            def_symbol: &def_symbol,
+            ops: &ops,
+            strings: &mut strings,
+            text,
+            trap: &mut trap,
+            checked: options.checked || def.checked,
+            intrinsics: &mut intrinsics,
+            structs: &mut structs,
+            lambdas: &mut lambdas,
+            lambda_count: &mut lambda_count,
+            wrappers: &mut wrappers,
+            exports: &exports,
+            type_params: &type_params,
+            type_args: &type_args,
+            instances: &mut instances,
+        };
+        generate_ret(&def.expr, &ret, &mut info);
 
-        let def = Def {
-            name: Name {
-                token: def_name_token,
-                id: def.name_id.id,
-            },
+        defs.push(Def {
+            name,
             params,
-            instructions,
+            blocks,
             ret,
-        };
-        defs.push(def);
+            export: def.export,
+        });
+        defs.append(&mut lambdas);
 
         id_map.reset();
+
+        for (name, type_args) in &instances[queued..] {
+            let def = program
+                .defs
+                .iter()
+                .find(|def| def.name_id.token == name.token && def.name_id.id == name.id)
+                .unwrap();
+            queue.push((def, type_args.clone()));
+        }
+        queued = instances.len();
     }
 
-    Target { defs }
-}
+    for (name, func) in &wrappers {
+        let export = exports.contains(name.token.as_str());
+        defs.push(generate_wrapper(name, func, export));
+    }
 
-fn get_terminal(type_token: &str, namespace: &Namespace) -> Terminal {
-    if let Symbol::Type(Type::Terminal(terminal)) =
-        namespace.get_then(type_token, 0).unwrap().symbol()
-    {
-        *terminal
-    } else {
-        panic!()
+    let mut declares = generate_declares(&program, &namespace);
+    if trap {
+        for declare in trap_declares() {
+            if declares.iter().all(|d| d.name != declare.name) {
+                declares.push(declare);
+            }
+        }
+    }
+    for declare in intrinsics {
+        if declares.iter().all(|d| d.name != declare.name) {
+            declares.push(declare);
+        }
     }
-}
 
-fn generate_expr(expr: &Expr, info: &mut Info) -> Option<Val> {
-    match expr {
-        Expr::Val(NameId { token, .. }) => Some(generate_val(token, info)),
-        Expr::Call(parse::Call { exprs, .. }) => Some(Val::Id(generate_call(exprs, info)?)),
+    Target {
+        structs,
+        strings,
+        defs,
+        declares,
     }
 }
 
-fn generate_val(token: &str, info: &mut Info) -> Val {
-    let symbol = info
-        .def_namespace
-        .get_or_then(info.namespace, token, 0)
-        .unwrap()
-        .symbol();
-
-    match symbol {
-        Symbol::Var(_) => Val::Id(info.id_map.get(token)),
-        Symbol::Literal(_) => Val::Literal(token.to_string()),
-        _ => panic!(),
+/// Struct types, followed by enums lowered to structs holding their tag and
+/// a payload large enough for any one variant. Each variant with fields also
+/// gets a struct of the tag and those fields, named after the enum and the
+/// variant, through which the enum is written and read as that variant.
+/// Headers only declare enums as opaque structs, so C code cannot come to
+/// depend on this layout.
+fn generate_structs(program: &Program, namespace: &Namespace) -> Vec<StructDef> {
+    let mut structs = Vec::new();
+    for s in &program.structs {
+        let fields = s
+            .fields
+            .iter()
+            .map(|field| get_type(&field.typ, namespace))
+            .collect();
+        structs.push(StructDef {
+            name: s.name.token.clone(),
+            fields,
+        });
     }
-}
 
-fn generate_call(exprs: &[Expr], info: &mut Info) -> Option<Id> {
-    let (parent, children) = exprs.split_first()?;
+    let mut views = Vec::new();
+    for e in &program.enums {
+        let tag = Type::Terminal(Terminal::I32);
+        let payload = enum_payload(e, program, namespace);
+        let fields = match payload {
+            Some((elem, len)) => vec![tag, Type::Array(Box::new(Type::Terminal(elem)), len)],
+            None => vec![tag],
+        };
+        structs.push(StructDef {
+            name: e.name.token.clone(),
+            fields,
+        });
 
-    let (parent_token, parent_id) = if let Expr::Val(NameId { token, id, .. }) = parent {
-        (token, *id)
-    } else {
-        panic!()
-    };
+        // An empty array of the payload's integers starts each variant's
+        // fields where the payload starts, rather than in the padding after
+        // the tag, which loading the enum as a whole does not keep.
+        for variant in e.variants.iter().filter(|v| !v.fields.is_empty()) {
+            let (elem, _) = payload.unwrap();
+            let start = Type::Array(Box::new(Type::Terminal(elem)), 0);
+            let mut fields = vec![Type::Terminal(Terminal::I32), start];
+            fields.extend(
+                variant
+                    .fields
+                    .iter()
+                    .map(|field| get_type(field, namespace)),
+            );
+            views.push(StructDef {
+                name: format!("{}.{}", e.name.token, variant.name.token),
+                fields,
+            });
+        }
+    }
+    structs.extend(views);
 
-    let typ = if let Symbol::Var(Type::Func(Func { ret, .. })) = info
-        .namespace
-        .get_then(parent_token, parent_id)
-        .unwrap()
-        .symbol()
-    {
-        *ret
-    } else {
-        panic!()
+    structs
+}
+
+/// The integer an enum's payload is made of, as aligned as the most aligned
+/// field of any variant so that each variant can be read through a cast, and
+/// how many of them hold the largest variant. An enum whose variants have no
+/// fields has no payload.
+fn enum_payload(
+    e: &parse::Enum,
+    program: &Program,
+    namespace: &Namespace,
+) -> Option<(Terminal, usize)> {
+    let variants: Vec<Vec<_>> = e
+        .variants
+        .iter()
+        .map(|variant| {
+            variant
+                .fields
+                .iter()
+                .map(|field| layout(&get_type(field, namespace), program, namespace))
+                .collect()
+        })
+        .collect();
+    let align = variants.iter().flatten().map(|(_, align)| *align).max()?;
+    let size = variants
+        .iter()
+        .map(|fields| struct_layout(fields).0)
+        .max()
+        .unwrap();
+    let elem = match align {
+        1 => Terminal::U8,
+        2 => Terminal::U16,
+        4 => Terminal::U32,
+        _ => Terminal::U64,
     };
+    Some((elem, size.div_ceil(align)))
+}
 
-    match info.ops.get(parent_token.as_str()) {
-        Some(op) => match op {
-            Op::UnaryOp(op) => Some(generate_unary(*op, typ, children, info)),
-            Op::BinaryOp(op) => Some(generate_binary(*op, typ, children, info)),
-        },
-        None => generate_func_call(parent_token, parent_id, children, info),
+/// The size and alignment LLVM gives a type on a 64-bit target, which enums
+/// need to size their payload without a data layout to ask.
+fn layout(typ: &Type, program: &Program, namespace: &Namespace) -> (usize, usize) {
+    match typ {
+        Type::Terminal(terminal) => {
+            let size = match terminal {
+                Terminal::Void => 0,
+                Terminal::Bool | Terminal::U8 | Terminal::I8 => 1,
+                Terminal::U16 | Terminal::I16 | Terminal::F16 => 2,
+                Terminal::U32 | Terminal::I32 | Terminal::F32 => 4,
+                Terminal::U64 | Terminal::I64 | Terminal::F64 => 8,
+            };
+            (size, size.max(1))
+        }
+        Type::Ptr(_) | Type::Code(_) => (8, 8),
+        Type::Slice(_) | Type::Func(_) => (16, 8),
+        Type::Overflow(terminal) => {
+            let val = layout(&Type::Terminal(*terminal), program, namespace);
+            struct_layout(&[val, (1, 1)])
+        }
+        Type::Array(elem, len) => {
+            let (size, align) = layout(elem, program, namespace);
+            (size * len, align)
+        }
+        Type::Struct(name) => {
+            let s = program.structs.iter().find(|s| &s.name.token == name);
+            let fields: Vec<_> = s
+                .unwrap()
+                .fields
+                .iter()
+                .map(|field| layout(&get_type(&field.typ, namespace), program, namespace))
+                .collect();
+            struct_layout(&fields)
+        }
+        Type::Enum(name) => {
+            let e = program.enums.iter().find(|e| &e.name.token == name);
+            match enum_payload(e.unwrap(), program, namespace) {
+                Some((elem, len)) => {
+                    let elem = layout(&Type::Terminal(elem), program, namespace);
+                    struct_layout(&[(4, 4), (elem.0 * len, elem.1)])
+                }
+                None => (4, 4),
+            }
+        }
+        Type::Param(_) => panic!(),
     }
 }
 
-fn generate_unary(op: UnaryOp, typ: Terminal, children: &[Expr], info: &mut Info) -> Id {
-    let child = children.get(0).unwrap();
+/// The size and alignment of a struct with fields of the given sizes and
+/// alignments, each placed at the next offset its alignment allows.
+fn struct_layout(fields: &[(usize, usize)]) -> (usize, usize) {
+    let mut size = 0;
+    let mut align = 1;
+    for &(field_size, field_align) in fields {
+        size = align_to(size, field_align) + field_size;
+        align = align.max(field_align);
+    }
+    (align_to(size, align), align)
+}
 
-    let arg = generate_expr(child, info).unwrap();
+const fn align_to(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
 
-    let id = info.id_map.add();
+fn generate_declares(program: &Program, namespace: &Namespace) -> Vec<Declare> {
+    let mut declares = Vec::new();
+    for ext in &program.externs {
+        let symbol = namespace
+            .get_then(&ext.name_id.token, ext.name_id.id)
+            .unwrap()
+            .symbol();
+        if let Symbol::Extern(func) = symbol {
+            declares.push(Declare {
+                ret: (*func.ret).clone(),
+                name: ext.name_id.token.clone(),
+                params: func.params.clone(),
+            });
+        }
+    }
+    declares
+}
 
-    let instruction = Instruction::Unary(Unary { op, id, typ, arg });
-    info.instructions.push(instruction);
+/// Declares a C function the generated code calls.
+fn runtime_declare(name: &str) -> Declare {
+    let func = resolve::runtime_func(name).unwrap();
+    Declare {
+        ret: *func.ret,
+        name: name.to_string(),
+        params: func.params,
+    }
+}
 
-    id
+/// The functions called when a bounds check fails, unless the program
+/// declares them itself.
+fn trap_declares() -> Vec<Declare> {
+    vec![
+        runtime_declare("write"),
+        Declare {
+            ret: Type::Terminal(Terminal::Void),
+            name: "llvm.trap".to_string(),
+            params: vec![],
+        },
+    ]
 }
 
-fn generate_binary(op: BinaryOp, typ: Terminal, children: &[Expr], info: &mut Info) -> Id {
-    let child1 = children.get(0).unwrap();
-    let child2 = children.get(1).unwrap();
+fn get_type(typ: &parse::Type, namespace: &Namespace) -> Type {
+    resolve::get_type(typ, namespace).unwrap()
+}
 
-    let arg1 = generate_expr(child1, info).unwrap();
-    let arg2 = generate_expr(child2, info).unwrap();
+/// Gives each parameter an id, under which a named one is also a local.
+fn generate_params(params: &[parse::Param], types: &[Type], id_map: &mut IdMap) -> Vec<Param> {
+    params
+        .iter()
+        .zip(types)
+        .map(|(param, typ)| {
+            let id = match param {
+                parse::Param::Decl(parse::Decl { name, .. }) => {
+                    id_map.insert(name.token.clone(), typ.clone())
+                }
+                parse::Param::Type(_) => id_map.add(),
+            };
+            Param {
+                typ: typ.clone(),
+                id,
+            }
+        })
+        .collect()
+}
 
-    let id = info.id_map.add();
+/// The name and type a def is generated with. Only a generic def's instances
+/// are generated, each under a name of its own.
+fn def_instance(def: &parse::Def, type_args: &[Type], namespace: &Namespace) -> (Name, Func) {
+    let (token, id) = (&def.name_id.token, def.name_id.id);
+    match namespace.get_then(token, id).unwrap().symbol() {
+        Symbol::Generic(func, type_params, _) => (
+            instance_name(token, id, type_args),
+            func.substitute(type_params, type_args),
+        ),
+        Symbol::Var(Type::Func(func)) => (
+            Name {
+                token: token.clone(),
+                id,
+            },
+            func.clone(),
+        ),
+        _ => panic!(),
+    }
+}
 
-    let instruction = Instruction::Binary(Binary {
-        op,
-        id,
-        typ,
-        arg1,
-        arg2,
-    });
-    info.instructions.push(instruction);
+/// The name of a generic def's instance spells out its type arguments, so
+/// that the same instance is always given the same name.
+fn instance_name(token: &str, id: Id, type_args: &[Type]) -> Name {
+    let mut token = token.to_string();
+    if id != 0 {
+        token.push_str(&id.to_string());
+    }
+    for typ in type_args {
+        token.push('.');
+        token.push_str(&mangle(typ));
+    }
+    Name { token, id: 0 }
+}
 
-    id
+fn mangle(typ: &Type) -> String {
+    match typ {
+        Type::Terminal(terminal) => terminal.name().to_string(),
+        Type::Struct(name) | Type::Enum(name) => name.clone(),
+        Type::Ptr(elem) => format!("ptr.{}", mangle(elem)),
+        Type::Array(elem, len) => format!("array.{}.{}", len, mangle(elem)),
+        Type::Slice(elem) => format!("slice.{}", mangle(elem)),
+        Type::Overflow(terminal) => format!("overflow.{}", terminal.name()),
+        Type::Func(func) | Type::Code(func) => {
+            let mut mangled = format!("fn.{}", func.params.len());
+            for typ in func.params.iter().chain([&*func.ret]) {
+                mangled.push('.');
+                mangled.push_str(&mangle(typ));
+            }
+            mangled
+        }
+        Type::Param(_) => panic!(),
+    }
 }
 
-fn generate_func_call(
-    parent: &str,
-    parent_id: Id,
-    children: &[Expr],
-    info: &mut Info,
-) -> Option<Id> {
-    let (params, ret) = if let Symbol::Var(Type::Func(Func { params, ret })) =
-        info.namespace.get_then(parent, parent_id).unwrap().symbol()
-    {
-        (params, ret)
-    } else {
-        panic!()
+/// Asks for an instance of a generic def, giving the name it is called by.
+fn request_instance(token: &str, id: Id, type_args: &[Type], info: &mut Info) -> Name {
+    let type_args: Vec<_> = type_args.iter().map(|typ| info.subst(typ)).collect();
+    let name = instance_name(token, id, &type_args);
+    let def = Name {
+        token: token.to_string(),
+        id,
     };
-
-    let mut args = Vec::new();
-    for (typ, child) in params.iter().zip(children.iter()) {
-        let val = generate_expr(child, info).unwrap();
-        let arg = Arg { typ: *typ, val };
-        args.push(arg);
+    if !info.instances.contains(&(def.clone(), type_args.clone())) {
+        info.instances.push((def, type_args));
     }
+    name
+}
 
-    let parent_token = parent.to_string();
+/// Generates an expression in tail position, returning its value from every
+/// path. An `if` returns from each branch instead of joining them with a
+/// `phi`, so that calls in its branches can become tail calls.
+fn generate_ret(expr: &Expr, typ: &Type, info: &mut Info) {
+    match expr {
+        Expr::Call(call) => {
+            let val = generate_call(call, true, info);
+            let typ = typ.clone();
+            info.push(Instruction::Ret(Ret { typ, val }));
+        }
+        Expr::If(if_expr) => {
+            let label_id = generate_cond(&if_expr.cond, info);
 
-    let id = if *ret == Terminal::Void {
-        None
-    } else {
-        Some(info.id_map.add())
-    };
+            info.start_block(Label::Named("if.then", label_id));
+            generate_ret(&if_expr.then, typ, info);
 
-    let instruction = Instruction::Call(Call {
-        id,
-        typ: *ret,
-        called_name: Name {
-            token: parent_token,
-            id: parent_id,
-        },
-        args,
-    });
-    info.instructions.push(instruction);
+            info.start_block(Label::Named("if.else", label_id));
+            generate_ret(&if_expr.els, typ, info);
+        }
+        Expr::Let(let_expr) => {
+            generate_bindings(&let_expr.bindings, info);
+            generate_ret(&let_expr.body, typ, info);
+            info.id_map.pop_scope();
+        }
+        Expr::Do(do_expr) => {
+            let (last, stmts) = do_expr.exprs.split_last().unwrap();
+            for stmt in stmts {
+                generate_expr(stmt, &Type::Terminal(Terminal::Void), info);
+            }
+            generate_ret(last, typ, info);
+        }
+        Expr::Match(match_expr) => {
+            let (label_id, enum_type, agg) = generate_switch(match_expr, info);
+            for (index, arm) in match_expr.arms.iter().enumerate() {
+                generate_arm(arm, index, label_id, &enum_type, &agg, info);
+                generate_ret(&arm.body, typ, info);
+                info.id_map.pop_scope();
+            }
+        }
+        _ => {
+            let val = generate_expr(expr, typ, info);
+            let typ = typ.clone();
+            info.push(Instruction::Ret(Ret { typ, val }));
+        }
+    }
+}
 
-    id
+fn generate_expr(expr: &Expr, typ: &Type, info: &mut Info) -> Option<Val> {
+    match expr {
+        Expr::Val(NameId {
+            token,
+            id,
+            type_args,
+            ..
+        }) => Some(generate_val(token, *id, type_args, typ, info)),
+        Expr::Call(call) => generate_call(call, false, info),
+        Expr::If(if_expr) => generate_if(if_expr, typ, info),
+        Expr::Let(let_expr) => generate_let(let_expr, typ, info),
+        Expr::Set(set) => {
+            generate_set(set, info);
+            None
+        }
+        Expr::While(while_expr) => {
+            generate_while(while_expr, info);
+            None
+        }
+        Expr::Do(do_expr) => {
+            let (last, stmts) = do_expr.exprs.split_last().unwrap();
+            for stmt in stmts {
+                generate_expr(stmt, &Type::Terminal(Terminal::Void), info);
+            }
+            generate_expr(last, typ, info)
+        }
+        Expr::Match(match_expr) => generate_match(match_expr, typ, info),
+        Expr::Lambda(lambda) => Some(generate_lambda(lambda, info)),
+    }
+}
+
+fn generate_val(token: &str, id: Id, type_args: &[Type], typ: &Type, info: &mut Info) -> Val {
+    match info.id_map.get(token) {
+        Some(Local::Val(val, _)) => return val,
+        Some(Local::Slot(slot, typ)) => {
+            let id = info.id_map.add();
+            info.push(Instruction::Load(Load {
+                id,
+                typ,
+                ptr: Val::Slot(slot),
+            }));
+            return Val::Id(id);
+        }
+        None => (),
+    }
+
+    match info.namespace.get_then(token, 0).unwrap().symbol() {
+        // The first literal symbol need not have the type chosen for it.
+        Symbol::Literal(Type::Terminal(_)) => match (literalise::number(token), typ) {
+            (Some(Number::Int(value)), _) => Val::Literal(value.to_string()),
+            (Some(Number::Float(value)), Type::Terminal(terminal)) => Val::Float(value, *terminal),
+            _ => Val::Literal(token.to_string()),
+        },
+        Symbol::Literal(_) => generate_string(token, typ, info),
+        _ => generate_func_value(token, id, type_args, info),
+    }
+}
+
+/// A def or extern used as a value is called through a wrapper, which takes
+/// an environment like a lambda does and ignores it.
+fn generate_func_value(token: &str, id: Id, type_args: &[Type], info: &mut Info) -> Val {
+    let (func, name) = match info.namespace.get_then(token, id).unwrap().symbol() {
+        Symbol::Var(Type::Func(func)) => (
+            func.clone(),
+            Name {
+                token: token.to_string(),
+                id,
+            },
+        ),
+        Symbol::Extern(func) => (
+            func.clone(),
+            Name {
+                token: token.to_string(),
+                id: 0,
+            },
+        ),
+        Symbol::Generic(func, type_params, _) => {
+            let func = info.subst_func(&func.substitute(type_params, type_args));
+            (func, request_instance(token, id, type_args, info))
+        }
+        _ => panic!(),
+    };
+    if info.wrappers.iter().all(|(wrapped, _)| *wrapped != name) {
+        info.wrappers.push((name.clone(), func.clone()));
+    }
+
+    let code = Val::Func(Name {
+        token: format!("closure.{}", name.token),
+        id: name.id,
+    });
+    generate_closure(&func, code, Val::Literal("null".to_string()), info)
+}
+
+/// Pairs the code of a function value with its environment.
+fn generate_closure(func: &Func, code: Val, env: Val, info: &mut Info) -> Val {
+    let typ = Type::Func(func.clone());
+    let mut agg = Val::Undef;
+    let fields = vec![(Type::Code(func.clone()), code), (env_type(), env)];
+    for (index, (val_typ, val)) in fields.into_iter().enumerate() {
+        let id = info.id_map.add();
+        info.push(Instruction::InsertValue(InsertValue {
+            id,
+            typ: typ.clone(),
+            agg,
+            val_typ,
+            val,
+            index,
+        }));
+        agg = Val::Id(id);
+    }
+    agg
+}
+
+/// The environment of a function value is passed as a byte pointer, which is
+/// null when there is nothing in it.
+fn env_type() -> Type {
+    Type::Ptr(Box::new(Type::Terminal(Terminal::I8)))
+}
+
+fn generate_wrapper(name: &Name, func: &Func, export: bool) -> Def {
+    let ret = (*func.ret).clone();
+    let mut params = vec![Param {
+        typ: env_type(),
+        id: 0,
+    }];
+    let mut args = Vec::new();
+    for (index, typ) in func.params.iter().enumerate() {
+        params.push(Param {
+            typ: typ.clone(),
+            id: index + 1,
+        });
+        args.push(Arg {
+            typ: typ.clone(),
+            val: Val::Id(index + 1),
+        });
+    }
+
+    let entry = params.len();
+    let id = if ret == Type::Terminal(Terminal::Void) {
+        None
+    } else {
+        Some(entry + 1)
+    };
+    let instructions = vec![
+        Instruction::Call(Call {
+            id,
+            kind: CallKind::Tail,
+            typ: ret.clone(),
+            callee: Val::Func(name.clone()),
+            args,
+            export,
+        }),
+        Instruction::Ret(Ret {
+            typ: ret.clone(),
+            val: id.map(Val::Id),
+        }),
+    ];
+
+    Def {
+        ret,
+        name: Name {
+            token: format!("closure.{}", name.token),
+            id: name.id,
+        },
+        params,
+        blocks: vec![Block {
+            label: Label::Entry(entry),
+            instructions,
+        }],
+        export: false,
+    }
+}
+
+/// Stores a string literal as a NUL-terminated global, so that it can be
+/// handed to C as a pointer. As a slice, its length leaves the NUL out.
+fn generate_string(token: &str, typ: &Type, info: &mut Info) -> Val {
+    let mut bytes = literalise::string(token).unwrap();
+    let len = bytes.len();
+    bytes.push(0);
+
+    let string = if let Some(string) = info.strings.iter().position(|s| *s == bytes) {
+        string
+    } else {
+        info.strings.push(bytes);
+        info.strings.len() - 1
+    };
+    let ptr = Val::Str(string, len + 1);
+
+    let elem = match typ {
+        Type::Ptr(_) => return ptr,
+        Type::Slice(elem) => elem,
+        _ => panic!(),
+    };
+
+    let mut agg = Val::Undef;
+    let fields = vec![
+        (Type::Ptr(elem.clone()), ptr),
+        (Type::Terminal(Terminal::I64), Val::Literal(len.to_string())),
+    ];
+    for (index, (val_typ, val)) in fields.into_iter().enumerate() {
+        let id = info.id_map.add();
+        info.push(Instruction::InsertValue(InsertValue {
+            id,
+            typ: typ.clone(),
+            agg,
+            val_typ,
+            val,
+            index,
+        }));
+        agg = Val::Id(id);
+    }
+    agg
+}
+
+#[allow(clippy::too_many_lines)]
+fn generate_call(call: &parse::Call, tail: bool, info: &mut Info) -> Option<Val> {
+    let (parent, children) = call.exprs.split_first()?;
+
+    let (parent_token, parent_id, type_args) = if let Expr::Val(NameId {
+        token,
+        id,
+        type_args,
+        ..
+    }) = parent
+    {
+        (token, *id, type_args)
+    } else {
+        panic!()
+    };
+
+    // A local hides every def of the same name, so the call goes through the
+    // function pointer it holds.
+    if let Some(local) = info.id_map.get(parent_token) {
+        let (Local::Val(_, Type::Func(func)) | Local::Slot(_, Type::Func(func))) = local else {
+            panic!()
+        };
+        let typ = Type::Func(func.clone());
+        let closure = generate_val(parent_token, parent_id, &[], &typ, info);
+        let [code, env] = [0, 1].map(|index| {
+            let id = info.id_map.add();
+            info.push(Instruction::ExtractValue(ExtractValue {
+                id,
+                typ: typ.clone(),
+                agg: closure.clone(),
+                index,
+            }));
+            Val::Id(id)
+        });
+        // Only a lambda takes an environment first, as the code does.
+        let kind = call_kind(tail, *info.def_symbol == Symbol::Lambda(func.clone()));
+        return generate_call_to(code, Some(env), &func, children, kind, false, info).map(Val::Id);
+    }
+
+    // A method is called as its overload for the instance it is called on.
+    let (parent_id, type_args) = match info
+        .namespace
+        .get_then(parent_token, parent_id)
+        .unwrap()
+        .symbol()
+    {
+        Symbol::Method(func, class) => {
+            let instance = info.subst(&type_args[0]);
+            let func = func.substitute(slice::from_ref(class), &[instance]);
+            let symbol = Symbol::Var(Type::Func(func));
+            let overloads = info.namespace.get(parent_token).unwrap();
+            let id = overloads
+                .iter()
+                .position(|n| *n.symbol() == symbol)
+                .unwrap();
+            (id, &[][..])
+        }
+        _ => (parent_id, &type_args[..]),
+    };
+
+    let entry = info.namespace.get_then(parent_token, parent_id).unwrap();
+    let func = match entry.symbol() {
+        Symbol::Constructor(func) => return Some(generate_constructor(func, children, info)),
+        Symbol::Field(func, index) => {
+            return Some(Val::Id(generate_field(func, *index, children, info)))
+        }
+        Symbol::Variant(func, variant) => {
+            return Some(generate_variant(
+                func,
+                variant,
+                parent_token,
+                children,
+                info,
+            ))
+        }
+        Symbol::Intrinsic(func, intrinsic) => {
+            let func = info.subst_func(func);
+            return generate_intrinsic(*intrinsic, &func, children, call.location, info);
+        }
+        Symbol::Var(Type::Func(func)) | Symbol::Extern(func) | Symbol::Generic(func, _, _) => func,
+        _ => panic!(),
+    };
+
+    // A def may share a builtin's name, so only the builtins themselves are
+    // lowered as ops.
+    let op = info
+        .ops
+        .get(parent_token.as_str())
+        .filter(|_| entry.is_builtin());
+    match op {
+        Some(op) => {
+            let typ = match func.params.first().unwrap_or(&func.ret) {
+                Type::Terminal(terminal) => *terminal,
+                _ => panic!(),
+            };
+            let id = match op {
+                Op::UnaryOp(op) => generate_unary(*op, typ, children, info),
+                Op::BinaryOp(op) => {
+                    let check = info.checked.then_some(call.location);
+                    generate_binary(*op, typ, children, check, info)
+                }
+                Op::Wrapping(op) => generate_binary(*op, typ, children, None, info),
+                Op::Saturating(op) => generate_saturating(*op, typ, children, info),
+                Op::Overflowing(op) => generate_overflowing(*op, typ, children, info),
+                Op::Math(op) => generate_math(*op, typ, children, info),
+                Op::Cast => return Some(generate_cast(func, children, info)),
+                Op::And => return Some(generate_short_circuit(true, children, info)),
+                Op::Or => return Some(generate_short_circuit(false, children, info)),
+            };
+            Some(Val::Id(id))
+        }
+        None => generate_func_call(parent_token, parent_id, type_args, children, tail, info)
+            .map(Val::Id),
+    }
+}
+
+fn generate_constructor(func: &Func, children: &[Expr], info: &mut Info) -> Val {
+    let mut agg = Val::Undef;
+    for (index, (val_typ, child)) in func.params.iter().zip(children).enumerate() {
+        let val = generate_expr(child, val_typ, info).unwrap();
+        let id = info.id_map.add();
+        info.push(Instruction::InsertValue(InsertValue {
+            id,
+            typ: (*func.ret).clone(),
+            agg,
+            val_typ: val_typ.clone(),
+            val,
+            index,
+        }));
+        agg = Val::Id(id);
+    }
+    agg
+}
+
+/// Builds a variant as the struct of its tag and fields, then stores it to a
+/// stack slot to read it back as the enum. A variant without fields is just
+/// its tag.
+fn generate_variant(
+    func: &Func,
+    variant: &Variant,
+    token: &str,
+    children: &[Expr],
+    info: &mut Info,
+) -> Val {
+    let typ = (*func.ret).clone();
+    let view = if func.params.is_empty() {
+        typ.clone()
+    } else {
+        variant_view(&typ, token)
+    };
+
+    let mut id = info.id_map.add();
+    info.push(Instruction::InsertValue(InsertValue {
+        id,
+        typ: view.clone(),
+        agg: Val::Undef,
+        val_typ: Type::Terminal(Terminal::I32),
+        val: Val::Literal(variant.tag.to_string()),
+        index: 0,
+    }));
+
+    for (index, (val_typ, child)) in func.params.iter().zip(children).enumerate() {
+        let val = generate_expr(child, val_typ, info).unwrap();
+        let agg = Val::Id(id);
+        id = info.id_map.add();
+        info.push(Instruction::InsertValue(InsertValue {
+            id,
+            typ: view.clone(),
+            agg,
+            val_typ: val_typ.clone(),
+            val,
+            index: index + 2,
+        }));
+    }
+
+    if func.params.is_empty() {
+        return Val::Id(id);
+    }
+
+    let slot = info.alloca(typ.clone());
+    let ptr = generate_view_ptr(&typ, &view, slot, info);
+    info.push(Instruction::Store(Store {
+        typ: view,
+        val: Val::Id(id),
+        ptr,
+    }));
+    generate_load(typ, Val::Slot(slot), info)
+}
+
+/// The struct an enum is read and written through as one of its variants.
+fn variant_view(enum_type: &Type, variant: &str) -> Type {
+    match enum_type {
+        Type::Enum(name) => Type::Struct(format!("{name}.{variant}")),
+        _ => panic!(),
+    }
+}
+
+/// Casts a stack slot holding an enum to a pointer to one of its variants.
+fn generate_view_ptr(enum_type: &Type, view: &Type, slot: Id, info: &mut Info) -> Val {
+    let id = info.id_map.add();
+    info.push(Instruction::Bitcast(Bitcast {
+        id,
+        from: Type::Ptr(Box::new(enum_type.clone())),
+        val: Val::Slot(slot),
+        to: Type::Ptr(Box::new(view.clone())),
+    }));
+    Val::Id(id)
+}
+
+fn generate_intrinsic(
+    intrinsic: Intrinsic,
+    func: &Func,
+    children: &[Expr],
+    location: Location,
+    info: &mut Info,
+) -> Option<Val> {
+    if intrinsic == Intrinsic::Array {
+        return Some(generate_constructor(func, children, info));
+    }
+
+    let mut args = Vec::new();
+    for (typ, child) in func.params.iter().zip(children) {
+        args.push(generate_expr(child, typ, info).unwrap());
+    }
+    let typ = &func.params[0];
+    let ret = (*func.ret).clone();
+
+    let val = match intrinsic {
+        Intrinsic::Array => panic!(),
+        Intrinsic::Len => match typ {
+            Type::Array(_, len) => Val::Literal(len.to_string()),
+            _ => generate_extract(typ, args[0].clone(), 1, info),
+        },
+        Intrinsic::Data => generate_extract(typ, args[0].clone(), 0, info),
+        Intrinsic::Slice => {
+            let mut agg = Val::Undef;
+            for (index, (val_typ, val)) in func.params.iter().zip(args).enumerate() {
+                let id = info.id_map.add();
+                info.push(Instruction::InsertValue(InsertValue {
+                    id,
+                    typ: ret.clone(),
+                    agg,
+                    val_typ: val_typ.clone(),
+                    val,
+                    index,
+                }));
+                agg = Val::Id(id);
+            }
+            agg
+        }
+        Intrinsic::Load => generate_load(ret, args[0].clone(), info),
+        Intrinsic::Store => {
+            let (ptr, val) = (args[0].clone(), args[1].clone());
+            let typ = func.params[1].clone();
+            info.push(Instruction::Store(Store { typ, val, ptr }));
+            return None;
+        }
+        Intrinsic::Offset => {
+            let elem = match typ {
+                Type::Ptr(elem) => (**elem).clone(),
+                _ => panic!(),
+            };
+            let id = info.id_map.add();
+            info.push(Instruction::GetElementPtr(GetElementPtr {
+                id,
+                typ: elem,
+                ptr: args[0].clone(),
+                indices: vec![args[1].clone()],
+            }));
+            Val::Id(id)
+        }
+        Intrinsic::At => {
+            let ptr = generate_element_ptr(typ, &args[0], &args[1], location, info);
+            generate_load(ret, ptr, info)
+        }
+        Intrinsic::SetAt => {
+            let ptr = generate_element_ptr(typ, &args[0], &args[1], location, info);
+            let (typ, val) = (func.params[2].clone(), args[2].clone());
+            info.push(Instruction::Store(Store { typ, val, ptr }));
+            return None;
+        }
+    };
+    Some(val)
+}
+
+fn generate_extract(typ: &Type, agg: Val, index: usize, info: &mut Info) -> Val {
+    let id = info.id_map.add();
+    info.push(Instruction::ExtractValue(ExtractValue {
+        id,
+        typ: typ.clone(),
+        agg,
+        index,
+    }));
+    Val::Id(id)
+}
+
+fn generate_load(typ: Type, ptr: Val, info: &mut Info) -> Val {
+    let id = info.id_map.add();
+    info.push(Instruction::Load(Load { id, typ, ptr }));
+    Val::Id(id)
+}
+
+/// Checks an index against the length of an array or slice and returns a
+/// pointer to the element. An array is a value, so it is first stored to a
+/// stack slot to have an address.
+fn generate_element_ptr(
+    typ: &Type,
+    agg: &Val,
+    index: &Val,
+    location: Location,
+    info: &mut Info,
+) -> Val {
+    let (base, ptr, indices) = match typ {
+        Type::Array(_, len) => {
+            generate_bounds_check(index, Val::Literal(len.to_string()), location, info);
+            let slot = info.alloca(typ.clone());
+            info.push(Instruction::Store(Store {
+                typ: typ.clone(),
+                val: agg.clone(),
+                ptr: Val::Slot(slot),
+            }));
+            (
+                typ.clone(),
+                Val::Slot(slot),
+                vec![Val::Literal("0".to_string()), index.clone()],
+            )
+        }
+        Type::Slice(elem) => {
+            let ptr = generate_extract(typ, agg.clone(), 0, info);
+            let len = generate_extract(typ, agg.clone(), 1, info);
+            generate_bounds_check(index, len, location, info);
+            ((**elem).clone(), ptr, vec![index.clone()])
+        }
+        _ => panic!(),
+    };
+
+    let id = info.id_map.add();
+    info.push(Instruction::GetElementPtr(GetElementPtr {
+        id,
+        typ: base,
+        ptr,
+        indices,
+    }));
+    Val::Id(id)
+}
+
+/// Branches to a block that traps unless the index is below the length.
+fn generate_bounds_check(index: &Val, len: Val, location: Location, info: &mut Info) {
+    let cond = info.id_map.add();
+    info.push(Instruction::Binary(Binary {
+        id: cond,
+        op: BinaryOp::ULess,
+        typ: Terminal::I64,
+        arg1: index.clone(),
+        arg2: len,
+    }));
+
+    let label_id = info.id_map.add_label();
+    let ok_label = Label::Named("bounds.ok", label_id);
+    let fail_label = Label::Named("bounds.fail", label_id);
+    info.push(Instruction::CondBr(CondBr {
+        cond: Val::Id(cond),
+        then: ok_label,
+        els: fail_label,
+    }));
+
+    info.start_block(fail_label);
+    generate_trap("index out of bounds", location, info);
+
+    info.start_block(ok_label);
+}
+
+/// Writes the location of a failed check and `message` to standard error,
+/// then traps.
+fn generate_trap(message: &str, location: Location, info: &mut Info) {
+    let (line, column) = line_column(info.text, location);
+    let message = format!("{line}:{column}: {message}\n").into_bytes();
+    let len = message.len();
+    let string = info.strings.len();
+    info.strings.push(message);
+
+    let id = info.id_map.add();
+    info.push(Instruction::Call(Call {
+        id: Some(id),
+        kind: CallKind::Normal,
+        typ: Type::Terminal(Terminal::I64),
+        callee: Val::Func(Name {
+            token: "write".to_string(),
+            id: 0,
+        }),
+        args: vec![
+            Arg {
+                typ: Type::Terminal(Terminal::I32),
+                val: Val::Literal("2".to_string()),
+            },
+            Arg {
+                typ: Type::Ptr(Box::new(Type::Terminal(Terminal::I8))),
+                val: Val::Str(string, len),
+            },
+            Arg {
+                typ: Type::Terminal(Terminal::I64),
+                val: Val::Literal(len.to_string()),
+            },
+        ],
+        export: false,
+    }));
+    info.push(Instruction::Call(Call {
+        id: None,
+        kind: CallKind::Normal,
+        typ: Type::Terminal(Terminal::Void),
+        callee: Val::Func(Name {
+            token: "llvm.trap".to_string(),
+            id: 0,
+        }),
+        args: vec![],
+        export: false,
+    }));
+    info.push(Instruction::Unreachable);
+    *info.trap = true;
+}
+
+fn generate_field(func: &Func, index: usize, children: &[Expr], info: &mut Info) -> Id {
+    let typ = func.params[0].clone();
+    let agg = generate_expr(&children[0], &typ, info).unwrap();
+    let id = info.id_map.add();
+    info.push(Instruction::ExtractValue(ExtractValue {
+        id,
+        typ,
+        agg,
+        index,
+    }));
+    id
+}
+
+fn generate_unary(op: UnaryOp, typ: Terminal, children: &[Expr], info: &mut Info) -> Id {
+    let child = children.get(0).unwrap();
+
+    let arg = generate_expr(child, &Type::Terminal(typ), info).unwrap();
+
+    let id = info.id_map.add();
+
+    let instruction = Instruction::Unary(Unary { op, id, typ, arg });
+    info.push(instruction);
+
+    id
+}
+
+fn generate_binary(
+    op: BinaryOp,
+    typ: Terminal,
+    children: &[Expr],
+    check: Option<Location>,
+    info: &mut Info,
+) -> Id {
+    let child1 = children.get(0).unwrap();
+    let child2 = children.get(1).unwrap();
+
+    let arg1 = generate_expr(child1, &Type::Terminal(typ), info).unwrap();
+    let arg2 = generate_expr(child2, &Type::Terminal(typ), info).unwrap();
+
+    let check = check.filter(|_| typ.signedness() != Signedness::Float);
+    if let Some(location) = check {
+        match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                return generate_overflow_check(op, typ, arg1, arg2, location, info);
+            }
+            BinaryOp::Div | BinaryOp::Rem => {
+                generate_divisor_check(typ, &arg1, &arg2, location, info);
+            }
+            _ => {}
+        }
+    }
+
+    let id = info.id_map.add();
+
+    let instruction = Instruction::Binary(Binary {
+        op,
+        id,
+        typ,
+        arg1,
+        arg2,
+    });
+    info.push(instruction);
+
+    id
+}
+
+/// Branches around the right operand when the left one already decides the
+/// result: `false` for `&&`, `true` for `||`.
+fn generate_short_circuit(and: bool, children: &[Expr], info: &mut Info) -> Val {
+    let bool_type = Type::Terminal(Terminal::Bool);
+    let (rhs_name, end_name, short) = if and {
+        ("and.rhs", "and.end", "false")
+    } else {
+        ("or.rhs", "or.end", "true")
+    };
+
+    let lhs = generate_expr(&children[0], &bool_type, info).unwrap();
+    let lhs_end = info.label();
+
+    let label_id = info.id_map.add_label();
+    let rhs_label = Label::Named(rhs_name, label_id);
+    let end_label = Label::Named(end_name, label_id);
+    let (then, els) = if and {
+        (rhs_label, end_label)
+    } else {
+        (end_label, rhs_label)
+    };
+    info.push(Instruction::CondBr(CondBr {
+        cond: lhs,
+        then,
+        els,
+    }));
+
+    info.start_block(rhs_label);
+    let rhs = generate_expr(&children[1], &bool_type, info).unwrap();
+    let rhs_end = info.label();
+    info.push(Instruction::Br(Br { label: end_label }));
+
+    info.start_block(end_label);
+    let id = info.id_map.add();
+    info.push(Instruction::Phi(Phi {
+        id,
+        typ: bool_type,
+        incoming: vec![
+            Incoming {
+                val: Val::Literal(short.to_string()),
+                label: lhs_end,
+            },
+            Incoming {
+                val: rhs,
+                label: rhs_end,
+            },
+        ],
+    }));
+    Val::Id(id)
+}
+
+/// Calls the intrinsic that reports whether `op` overflowed, trapping if it
+/// did.
+fn generate_overflow_check(
+    op: BinaryOp,
+    typ: Terminal,
+    arg1: Val,
+    arg2: Val,
+    location: Location,
+    info: &mut Info,
+) -> Id {
+    let name = arith_intrinsic(op, typ, "with.overflow");
+    let operands = arith_args(typ, arg1, arg2);
+    let agg = Val::Id(generate_llvm_call(
+        name,
+        Type::Overflow(typ),
+        operands,
+        info,
+    ));
+
+    let val = info.id_map.add();
+    info.push(Instruction::ExtractValue(ExtractValue {
+        id: val,
+        typ: Type::Overflow(typ),
+        agg: agg.clone(),
+        index: 0,
+    }));
+    let flag = info.id_map.add();
+    info.push(Instruction::ExtractValue(ExtractValue {
+        id: flag,
+        typ: Type::Overflow(typ),
+        agg,
+        index: 1,
+    }));
+
+    let label_id = info.id_map.add_label();
+    let ok_label = Label::Named("overflow.ok", label_id);
+    let fail_label = Label::Named("overflow.fail", label_id);
+    info.push(Instruction::CondBr(CondBr {
+        cond: Val::Id(flag),
+        then: fail_label,
+        els: ok_label,
+    }));
+
+    info.start_block(fail_label);
+    generate_trap("arithmetic overflow", location, info);
+
+    info.start_block(ok_label);
+    val
+}
+
+/// Traps if the divisor is zero, or for signed types if the dividend is the
+/// minimum value and the divisor is -1, since the quotient doesn't fit.
+fn generate_divisor_check(
+    typ: Terminal,
+    arg1: &Val,
+    arg2: &Val,
+    location: Location,
+    info: &mut Info,
+) {
+    let zero = info.id_map.add();
+    info.push(Instruction::Binary(Binary {
+        id: zero,
+        op: BinaryOp::Equal,
+        typ,
+        arg1: arg2.clone(),
+        arg2: Val::Literal("0".to_string()),
+    }));
+
+    let label_id = info.id_map.add_label();
+    let ok_label = Label::Named("div.ok", label_id);
+    let fail_label = Label::Named("div.fail", label_id);
+    info.push(Instruction::CondBr(CondBr {
+        cond: Val::Id(zero),
+        then: fail_label,
+        els: ok_label,
+    }));
+
+    info.start_block(fail_label);
+    generate_trap("division by zero", location, info);
+
+    info.start_block(ok_label);
+
+    if typ.signedness() != Signedness::Signed {
+        return;
+    }
+
+    let min = info.id_map.add();
+    info.push(Instruction::Binary(Binary {
+        id: min,
+        op: BinaryOp::Equal,
+        typ,
+        arg1: arg1.clone(),
+        arg2: Val::Literal((-(1_i128 << (typ.bits() - 1))).to_string()),
+    }));
+    let minus_one = info.id_map.add();
+    info.push(Instruction::Binary(Binary {
+        id: minus_one,
+        op: BinaryOp::Equal,
+        typ,
+        arg1: arg2.clone(),
+        arg2: Val::Literal("-1".to_string()),
+    }));
+    let overflow = info.id_map.add();
+    info.push(Instruction::Binary(Binary {
+        id: overflow,
+        op: BinaryOp::BitAnd,
+        typ: Terminal::Bool,
+        arg1: Val::Id(min),
+        arg2: Val::Id(minus_one),
+    }));
+
+    let label_id = info.id_map.add_label();
+    let ok_label = Label::Named("overflow.ok", label_id);
+    let fail_label = Label::Named("overflow.fail", label_id);
+    info.push(Instruction::CondBr(CondBr {
+        cond: Val::Id(overflow),
+        then: fail_label,
+        els: ok_label,
+    }));
+
+    info.start_block(fail_label);
+    generate_trap("arithmetic overflow", location, info);
+
+    info.start_block(ok_label);
+}
+
+fn generate_saturating(op: BinaryOp, typ: Terminal, children: &[Expr], info: &mut Info) -> Id {
+    let arg1 = generate_expr(&children[0], &Type::Terminal(typ), info).unwrap();
+    let arg2 = generate_expr(&children[1], &Type::Terminal(typ), info).unwrap();
+
+    let mut operands = arith_args(typ, arg1, arg2);
+    // LLVM has no saturating multiply as such, but a fixed-point one with no
+    // fractional bits is the same thing.
+    let name = if op == BinaryOp::Mul {
+        operands.push(Arg {
+            typ: Type::Terminal(Terminal::I32),
+            val: Val::Literal("0".to_string()),
+        });
+        arith_intrinsic(op, typ, "fix.sat")
+    } else {
+        arith_intrinsic(op, typ, "sat")
+    };
+    generate_llvm_call(name, Type::Terminal(typ), operands, info)
+}
+
+fn generate_overflowing(op: BinaryOp, typ: Terminal, children: &[Expr], info: &mut Info) -> Id {
+    let arg1 = generate_expr(&children[0], &Type::Terminal(typ), info).unwrap();
+    let arg2 = generate_expr(&children[1], &Type::Terminal(typ), info).unwrap();
+
+    let name = arith_intrinsic(op, typ, "with.overflow");
+    let operands = arith_args(typ, arg1, arg2);
+    generate_llvm_call(name, Type::Overflow(typ), operands, info)
+}
+
+fn generate_math(op: MathOp, typ: Terminal, children: &[Expr], info: &mut Info) -> Id {
+    let mut vals: Vec<_> = children
+        .iter()
+        .map(|child| generate_expr(child, &Type::Terminal(typ), info).unwrap())
+        .collect();
+    // A funnel shift of a value with itself is a rotate.
+    if let MathOp::Rotl | MathOp::Rotr = op {
+        vals.insert(1, vals[0].clone());
+    }
+    let mut args: Vec<_> = vals
+        .into_iter()
+        .map(|val| Arg {
+            typ: Type::Terminal(typ),
+            val,
+        })
+        .collect();
+
+    let float = typ.signedness() == Signedness::Float;
+    let signed = typ.signedness() == Signedness::Signed;
+    let name = match op {
+        MathOp::Sqrt => "sqrt",
+        MathOp::Abs if float => "fabs",
+        MathOp::Abs => "abs",
+        MathOp::Min if float => "minnum",
+        MathOp::Min if signed => "smin",
+        MathOp::Min => "umin",
+        MathOp::Max if float => "maxnum",
+        MathOp::Max if signed => "smax",
+        MathOp::Max => "umax",
+        MathOp::Floor => "floor",
+        MathOp::Ceil => "ceil",
+        MathOp::Fma => "fma",
+        MathOp::Popcount => "ctpop",
+        MathOp::Clz => "ctlz",
+        MathOp::Ctz => "cttz",
+        MathOp::Rotl => "fshl",
+        MathOp::Rotr => "fshr",
+    };
+
+    // Without the flag, the result is the type's minimum for `abs` and its
+    // width for a zero count, rather than poison.
+    if let MathOp::Abs | MathOp::Clz | MathOp::Ctz = op {
+        if !float {
+            args.push(Arg {
+                typ: Type::Terminal(Terminal::Bool),
+                val: Val::Literal("false".to_string()),
+            });
+        }
+    }
+
+    let name = format!("llvm.{}.{}", name, overload_suffix(typ));
+    generate_llvm_call(name, Type::Terminal(typ), args, info)
+}
+
+/// The name of an LLVM arithmetic intrinsic such as `llvm.sadd.sat.i32`.
+fn arith_intrinsic(op: BinaryOp, typ: Terminal, kind: &str) -> String {
+    let sign = match typ.signedness() {
+        Signedness::Signed => "s",
+        _ => "u",
+    };
+    let op = match op {
+        BinaryOp::Add => "add",
+        BinaryOp::Sub => "sub",
+        BinaryOp::Mul => "mul",
+        _ => panic!(),
+    };
+    format!("llvm.{}{}.{}.{}", sign, op, kind, overload_suffix(typ))
+}
+
+/// The suffix that picks the overload of an LLVM intrinsic for a terminal.
+fn overload_suffix(typ: Terminal) -> String {
+    match typ.signedness() {
+        Signedness::Float => format!("f{}", typ.bits()),
+        _ => format!("i{}", typ.bits()),
+    }
+}
+
+fn arith_args(typ: Terminal, arg1: Val, arg2: Val) -> Vec<Arg> {
+    vec![
+        Arg {
+            typ: Type::Terminal(typ),
+            val: arg1,
+        },
+        Arg {
+            typ: Type::Terminal(typ),
+            val: arg2,
+        },
+    ]
+}
+
+/// Calls an LLVM intrinsic, or a C function the compiler relies on such as
+/// `malloc`, declaring it the first time it is called. A program may declare
+/// the C function itself, as long as its type is the one `resolve` expects.
+fn generate_llvm_call(name: String, ret: Type, args: Vec<Arg>, info: &mut Info) -> Id {
+    if info.intrinsics.iter().all(|declare| declare.name != name) {
+        info.intrinsics.push(Declare {
+            ret: ret.clone(),
+            name: name.clone(),
+            params: args.iter().map(|arg| arg.typ.clone()).collect(),
+        });
+    }
+
+    let id = info.id_map.add();
+    info.push(Instruction::Call(Call {
+        id: Some(id),
+        kind: CallKind::Normal,
+        typ: ret,
+        callee: Val::Func(Name { token: name, id: 0 }),
+        args,
+        export: false,
+    }));
+    id
+}
+
+/// Converts between terminals. Integers of the same width share an LLVM
+/// type, so converting between them needs no instruction.
+fn generate_cast(func: &Func, children: &[Expr], info: &mut Info) -> Val {
+    let (from, to) = match (&func.params[0], &*func.ret) {
+        (Type::Terminal(from), Type::Terminal(to)) => (*from, *to),
+        _ => panic!(),
+    };
+    let val = generate_expr(&children[0], &func.params[0], info).unwrap();
+
+    let op = match (from.signedness(), to.signedness()) {
+        (Signedness::Float, Signedness::Float) if from.bits() < to.bits() => CastOp::FpExt,
+        (Signedness::Float, Signedness::Float) if from.bits() > to.bits() => CastOp::FpTrunc,
+        (Signedness::Float, Signedness::Float) => return val,
+        (Signedness::Unsigned, Signedness::Float) => CastOp::UiToFp,
+        (Signedness::Signed, Signedness::Float) => CastOp::SiToFp,
+        (Signedness::Float, Signedness::Unsigned) => CastOp::FpToUi,
+        (Signedness::Float, Signedness::Signed) => CastOp::FpToSi,
+        _ if from.bits() > to.bits() => CastOp::Trunc,
+        _ if from.bits() == to.bits() => return val,
+        (Signedness::Unsigned, _) => CastOp::ZExt,
+        (Signedness::Signed, _) => CastOp::SExt,
+    };
+
+    let id = info.id_map.add();
+    info.push(Instruction::Cast(Cast {
+        id,
+        op,
+        from,
+        val,
+        to,
+    }));
+    Val::Id(id)
+}
+
+fn generate_func_call(
+    parent: &str,
+    parent_id: Id,
+    type_args: &[Type],
+    children: &[Expr],
+    tail: bool,
+    info: &mut Info,
+) -> Option<Id> {
+    let (symbol, name) = match info.namespace.get_then(parent, parent_id).unwrap().symbol() {
+        Symbol::Generic(func, type_params, _) => {
+            let func = info.subst_func(&func.substitute(type_params, type_args));
+            let name = request_instance(parent, parent_id, type_args, info);
+            (Symbol::Var(Type::Func(func)), name)
+        }
+        symbol @ Symbol::Var(Type::Func(_)) => (
+            symbol.clone(),
+            Name {
+                token: parent.to_string(),
+                id: parent_id,
+            },
+        ),
+        symbol @ Symbol::Extern(_) => (
+            symbol.clone(),
+            Name {
+                token: parent.to_string(),
+                id: 0,
+            },
+        ),
+        _ => panic!(),
+    };
+    let func = symbol.func().unwrap();
+
+    let kind = call_kind(tail, symbol == *info.def_symbol);
+    let export = info.exports.contains(parent);
+    generate_call_to(Val::Func(name), None, func, children, kind, export, info)
+}
+
+const fn call_kind(tail: bool, same_prototype: bool) -> CallKind {
+    if !tail {
+        CallKind::Normal
+    } else if same_prototype {
+        CallKind::MustTail
+    } else {
+        CallKind::Tail
+    }
+}
+
+/// Calls `callee`, which is either a def or extern, or the code of a function
+/// value along with its environment.
+fn generate_call_to(
+    callee: Val,
+    env: Option<Val>,
+    Func { params, ret }: &Func,
+    children: &[Expr],
+    kind: CallKind,
+    export: bool,
+    info: &mut Info,
+) -> Option<Id> {
+    let mut args = Vec::new();
+    if let Some(val) = env {
+        args.push(Arg {
+            typ: env_type(),
+            val,
+        });
+    }
+    for (typ, child) in params.iter().zip(children.iter()) {
+        let val = generate_expr(child, typ, info).unwrap();
+        let arg = Arg {
+            typ: typ.clone(),
+            val,
+        };
+        args.push(arg);
+    }
+
+    let id = if **ret == Type::Terminal(Terminal::Void) {
+        None
+    } else {
+        Some(info.id_map.add())
+    };
+
+    let instruction = Instruction::Call(Call {
+        id,
+        kind,
+        typ: (**ret).clone(),
+        callee,
+        args,
+        export,
+    });
+    info.push(instruction);
+
+    id
+}
+
+/// Branches on an `if` condition and returns the id shared by its labels.
+fn generate_cond(cond: &Expr, info: &mut Info) -> Id {
+    let cond = generate_expr(cond, &Type::Terminal(Terminal::Bool), info).unwrap();
+
+    let label_id = info.id_map.add_label();
+
+    info.push(Instruction::CondBr(CondBr {
+        cond,
+        then: Label::Named("if.then", label_id),
+        els: Label::Named("if.else", label_id),
+    }));
+
+    label_id
+}
+
+fn generate_if(if_expr: &parse::If, typ: &Type, info: &mut Info) -> Option<Val> {
+    let label_id = generate_cond(&if_expr.cond, info);
+    let then_label = Label::Named("if.then", label_id);
+    let else_label = Label::Named("if.else", label_id);
+    let end_label = Label::Named("if.end", label_id);
+
+    info.start_block(then_label);
+    let then_val = generate_expr(&if_expr.then, typ, info);
+    let then_end = info.label();
+    info.push(Instruction::Br(Br { label: end_label }));
+
+    info.start_block(else_label);
+    let else_val = generate_expr(&if_expr.els, typ, info);
+    let else_end = info.label();
+    info.push(Instruction::Br(Br { label: end_label }));
+
+    info.start_block(end_label);
+
+    if let (Some(then_val), Some(else_val)) = (then_val, else_val) {
+        let id = info.id_map.add();
+        let instruction = Instruction::Phi(Phi {
+            id,
+            typ: typ.clone(),
+            incoming: vec![
+                Incoming {
+                    val: then_val,
+                    label: then_end,
+                },
+                Incoming {
+                    val: else_val,
+                    label: else_end,
+                },
+            ],
+        });
+        info.push(instruction);
+        Some(Val::Id(id))
+    } else {
+        None
+    }
+}
+
+fn generate_let(let_expr: &parse::Let, typ: &Type, info: &mut Info) -> Option<Val> {
+    generate_bindings(&let_expr.bindings, info);
+    let val = generate_expr(&let_expr.body, typ, info);
+    info.id_map.pop_scope();
+    val
+}
+
+/// Pushes a scope holding the bindings. The caller pops it after the body.
+fn generate_bindings(bindings: &[parse::Binding], info: &mut Info) {
+    info.id_map.push_scope();
+
+    for binding in bindings {
+        let binding_type = info.get_type(&binding.typ);
+        let val = generate_expr(&binding.expr, &binding_type, info).unwrap();
+        let local = if binding.mutable {
+            let slot = info.alloca(binding_type.clone());
+            info.push(Instruction::Store(Store {
+                typ: binding_type.clone(),
+                val,
+                ptr: Val::Slot(slot),
+            }));
+            Local::Slot(slot, binding_type)
+        } else {
+            Local::Val(val, binding_type)
+        };
+        info.id_map.bind(binding.name.token.clone(), local);
+    }
+}
+
+fn generate_set(set: &parse::Set, info: &mut Info) {
+    let Some(Local::Slot(slot, typ)) = info.id_map.get(&set.name.token) else {
+        panic!()
+    };
+
+    let val = generate_expr(&set.expr, &typ, info).unwrap();
+    info.push(Instruction::Store(Store {
+        typ,
+        val,
+        ptr: Val::Slot(slot),
+    }));
+}
+
+fn generate_while(while_expr: &parse::While, info: &mut Info) {
+    let label_id = info.id_map.add_label();
+    let cond_label = Label::Named("while.cond", label_id);
+    let body_label = Label::Named("while.body", label_id);
+    let end_label = Label::Named("while.end", label_id);
+
+    info.push(Instruction::Br(Br { label: cond_label }));
+
+    info.start_block(cond_label);
+    let cond = generate_expr(&while_expr.cond, &Type::Terminal(Terminal::Bool), info).unwrap();
+    info.push(Instruction::CondBr(CondBr {
+        cond,
+        then: body_label,
+        els: end_label,
+    }));
+
+    info.start_block(body_label);
+    generate_expr(&while_expr.body, &Type::Terminal(Terminal::Void), info);
+    info.push(Instruction::Br(Br { label: cond_label }));
+
+    info.start_block(end_label);
+}
+
+fn arm_variant<'a>(arm: &parse::Arm, info: &Info<'a>) -> Option<(&'a Func, &'a Variant)> {
+    match &arm.pattern {
+        parse::Pattern::Variant(NameId { token, id, .. }, _) => {
+            match info.namespace.get_then(token, *id).unwrap().symbol() {
+                Symbol::Variant(func, variant) => Some((func, variant)),
+                _ => panic!(),
+            }
+        }
+        parse::Pattern::Wildcard => None,
+    }
+}
+
+/// Switches on the tag of the matched value. Returns the id shared by the arm
+/// labels, the enum type and the matched value. Without a wildcard arm, the
+/// default case is unreachable because the match is exhaustive.
+fn generate_switch(match_expr: &parse::Match, info: &mut Info) -> (Id, Type, Val) {
+    let enum_type = match_expr
+        .arms
+        .iter()
+        .find_map(|arm| arm_variant(arm, info))
+        .map(|(func, _)| (*func.ret).clone())
+        .unwrap();
+
+    let agg = generate_expr(&match_expr.expr, &enum_type, info).unwrap();
+
+    let tag = info.id_map.add();
+    info.push(Instruction::ExtractValue(ExtractValue {
+        id: tag,
+        typ: enum_type.clone(),
+        agg: agg.clone(),
+        index: 0,
+    }));
+
+    let label_id = info.id_map.add_label();
+
+    let mut cases = Vec::new();
+    let mut default = Label::Named("match.default", label_id);
+    for (index, arm) in match_expr.arms.iter().enumerate() {
+        let label = Label::Indexed("match.arm", label_id, index);
+        match arm_variant(arm, info) {
+            Some((_, variant)) => cases.push((variant.tag, label)),
+            None => default = label,
+        }
+    }
+
+    let unreachable = matches!(default, Label::Named(..));
+
+    info.push(Instruction::Switch(Switch {
+        val: Val::Id(tag),
+        default,
+        cases,
+    }));
+
+    if unreachable {
+        info.start_block(default);
+        info.push(Instruction::Unreachable);
+    }
+
+    (label_id, enum_type, agg)
+}
+
+/// Starts the block of a match arm and pushes a scope holding the fields it
+/// names. The caller pops it after the body.
+fn generate_arm(
+    arm: &parse::Arm,
+    index: usize,
+    label_id: Id,
+    enum_type: &Type,
+    agg: &Val,
+    info: &mut Info,
+) {
+    info.start_block(Label::Indexed("match.arm", label_id, index));
+    info.id_map.push_scope();
+
+    let (parse::Pattern::Variant(NameId { token, .. }, bindings), Some((func, _))) =
+        (&arm.pattern, arm_variant(arm, info))
+    else {
+        return;
+    };
+    if bindings.iter().all(|binding| binding.token == "_") {
+        return;
+    }
+
+    // The enum is a value, so it is first stored to a stack slot to be read
+    // back as the variant.
+    let view = variant_view(enum_type, token);
+    let slot = info.alloca(enum_type.clone());
+    info.push(Instruction::Store(Store {
+        typ: enum_type.clone(),
+        val: agg.clone(),
+        ptr: Val::Slot(slot),
+    }));
+    let ptr = generate_view_ptr(enum_type, &view, slot, info);
+    let fields = generate_load(view.clone(), ptr, info);
+
+    for (field, binding) in bindings.iter().enumerate() {
+        if binding.token == "_" {
+            continue;
+        }
+        let id = info.id_map.add();
+        info.push(Instruction::ExtractValue(ExtractValue {
+            id,
+            typ: view.clone(),
+            agg: fields.clone(),
+            index: field + 2,
+        }));
+        let local = Local::Val(Val::Id(id), func.params[field].clone());
+        info.id_map.bind(binding.token.clone(), local);
+    }
+}
+
+fn generate_match(match_expr: &parse::Match, typ: &Type, info: &mut Info) -> Option<Val> {
+    let (label_id, enum_type, agg) = generate_switch(match_expr, info);
+    let end_label = Label::Named("match.end", label_id);
+
+    let mut incoming = Vec::new();
+    let mut void = false;
+    for (index, arm) in match_expr.arms.iter().enumerate() {
+        generate_arm(arm, index, label_id, &enum_type, &agg, info);
+        match generate_expr(&arm.body, typ, info) {
+            Some(val) => incoming.push(Incoming {
+                val,
+                label: info.label(),
+            }),
+            None => void = true,
+        }
+        info.id_map.pop_scope();
+        info.push(Instruction::Br(Br { label: end_label }));
+    }
+
+    info.start_block(end_label);
+
+    if void {
+        None
+    } else {
+        let id = info.id_map.add();
+        info.push(Instruction::Phi(Phi {
+            id,
+            typ: typ.clone(),
+            incoming,
+        }));
+        Some(Val::Id(id))
+    }
+}
+
+/// Closure conversion: the lambda's body becomes a def of its own, which
+/// takes an environment holding a copy of every local the body refers to. The
+/// environment is allocated with `malloc` so that the value can outlive the
+/// def that made it. Nothing tracks how long a closure lives, so its
+/// environment is never freed: each closure made leaks one allocation.
+fn generate_lambda(lambda: &parse::Lambda, info: &mut Info) -> Val {
+    let (func, _) = resolve::resolve_func(&lambda.func, info.type_params, info.namespace).unwrap();
+    let func = info.subst_func(&func);
+
+    let mut bound = lambda
+        .func
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            parse::Param::Decl(parse::Decl { name, .. }) => Some(name.token.clone()),
+            parse::Param::Type(_) => None,
+        })
+        .collect();
+    let mut free = Vec::new();
+    free_vars(&lambda.body, &mut bound, &mut free);
+
+    let mut captures = Vec::new();
+    for token in free {
+        let Some(Local::Val(_, typ) | Local::Slot(_, typ)) = info.id_map.get(&token) else {
+            continue;
+        };
+        let val = generate_val(&token, 0, &[], &typ, info);
+        captures.push((token, typ, val));
+    }
+
+    let name = format!("lambda.{}", info.lambda_count);
+    *info.lambda_count += 1;
+
+    let env_struct = Type::Struct(format!("{name}.env"));
+    let env = if captures.is_empty() {
+        Val::Literal("null".to_string())
+    } else {
+        info.structs.push(StructDef {
+            name: format!("{name}.env"),
+            fields: captures.iter().map(|(_, typ, _)| typ.clone()).collect(),
+        });
+        generate_env(&env_struct, &captures, info)
+    };
+
+    let mut id_map = IdMap::new();
+    let mut params = vec![Param {
+        typ: env_type(),
+        id: id_map.add(),
+    }];
+    params.append(&mut generate_params(
+        &lambda.func.params,
+        &func.params,
+        &mut id_map,
+    ));
+
+    let mut blocks = vec![Block {
+        label: Label::Entry(id_map.add()),
+        instructions: Vec::new(),
+    }];
+    let def_symbol = Symbol::Lambda(func.clone());
+    let mut lambda_info = Info {
+        blocks: &mut blocks,
+        id_map: &mut id_map,
+        namespace: info.namespace,
+        def_symbol: &def_symbol,
+        ops: info.ops,
+        strings: info.strings,
+        text: info.text,
+        trap: info.trap,
+        checked: info.checked,
+        intrinsics: info.intrinsics,
+        structs: info.structs,
+        lambdas: info.lambdas,
+        lambda_count: info.lambda_count,
+        wrappers: info.wrappers,
+        exports: info.exports,
+        type_params: info.type_params,
+        type_args: info.type_args,
+        instances: info.instances,
+    };
+
+    if !captures.is_empty() {
+        unpack_env(&env_struct, captures, &mut lambda_info);
+    }
+
+    generate_ret(&lambda.body, &func.ret, &mut lambda_info);
+
+    info.lambdas.push(Def {
+        ret: (*func.ret).clone(),
+        name: Name {
+            token: name.clone(),
+            id: 0,
+        },
+        params,
+        blocks,
+        export: false,
+    });
+
+    let code = Val::Func(Name { token: name, id: 0 });
+    generate_closure(&func, code, env, info)
+}
+
+/// Allocates an environment and copies the captured values into it.
+fn generate_env(env_struct: &Type, captures: &[(String, Type, Val)], info: &mut Info) -> Val {
+    let size = Arg {
+        typ: Type::Terminal(Terminal::I64),
+        val: Val::SizeOf(env_struct.clone()),
+    };
+    let malloc = resolve::runtime_func("malloc").unwrap();
+    let env = Val::Id(generate_llvm_call(
+        "malloc".to_string(),
+        *malloc.ret,
+        vec![size],
+        info,
+    ));
+
+    let ptr = info.id_map.add();
+    info.push(Instruction::Bitcast(Bitcast {
+        id: ptr,
+        from: env_type(),
+        val: env.clone(),
+        to: Type::Ptr(Box::new(env_struct.clone())),
+    }));
+    let mut agg = Val::Undef;
+    for (index, (_, val_typ, val)) in captures.iter().enumerate() {
+        let id = info.id_map.add();
+        info.push(Instruction::InsertValue(InsertValue {
+            id,
+            typ: env_struct.clone(),
+            agg,
+            val_typ: val_typ.clone(),
+            val: val.clone(),
+            index,
+        }));
+        agg = Val::Id(id);
+    }
+    info.push(Instruction::Store(Store {
+        typ: env_struct.clone(),
+        val: agg,
+        ptr: Val::Id(ptr),
+    }));
+    env
+}
+
+/// Binds the captured names in a lambda's body to the values in the
+/// environment it takes first.
+fn unpack_env(env_struct: &Type, captures: Vec<(String, Type, Val)>, info: &mut Info) {
+    let ptr = info.id_map.add();
+    info.push(Instruction::Bitcast(Bitcast {
+        id: ptr,
+        from: env_type(),
+        val: Val::Id(0),
+        to: Type::Ptr(Box::new(env_struct.clone())),
+    }));
+    let agg = info.id_map.add();
+    info.push(Instruction::Load(Load {
+        id: agg,
+        typ: env_struct.clone(),
+        ptr: Val::Id(ptr),
+    }));
+    for (index, (token, typ, _)) in captures.into_iter().enumerate() {
+        let id = info.id_map.add();
+        info.push(Instruction::ExtractValue(ExtractValue {
+            id,
+            typ: env_struct.clone(),
+            agg: Val::Id(agg),
+            index,
+        }));
+        info.id_map.bind(token, Local::Val(Val::Id(id), typ));
+    }
+}
+
+/// Collects the names an expression refers to that it does not bind itself,
+/// in the order they first appear. Module-level names are collected too, and
+/// left for the caller to tell apart from locals.
+fn free_vars(expr: &Expr, bound: &mut Vec<String>, free: &mut Vec<String>) {
+    let mut refer = |token: &String, bound: &[String]| {
+        if !bound.contains(token) && !free.contains(token) {
+            free.push(token.clone());
+        }
+    };
+
+    match expr {
+        Expr::Val(NameId { token, .. }) => refer(token, bound),
+        Expr::Call(parse::Call { exprs, .. }) | Expr::Do(parse::Do { exprs, .. }) => {
+            for expr in exprs {
+                free_vars(expr, bound, free);
+            }
+        }
+        Expr::If(parse::If {
+            cond, then, els, ..
+        }) => {
+            free_vars(cond, bound, free);
+            free_vars(then, bound, free);
+            free_vars(els, bound, free);
+        }
+        Expr::Let(parse::Let { bindings, body, .. }) => {
+            let len = bound.len();
+            for binding in bindings {
+                free_vars(&binding.expr, bound, free);
+                bound.push(binding.name.token.clone());
+            }
+            free_vars(body, bound, free);
+            bound.truncate(len);
+        }
+        Expr::Set(parse::Set { name, expr, .. }) => {
+            refer(&name.token, bound);
+            free_vars(expr, bound, free);
+        }
+        Expr::While(parse::While { cond, body, .. }) => {
+            free_vars(cond, bound, free);
+            free_vars(body, bound, free);
+        }
+        Expr::Match(parse::Match { expr, arms, .. }) => {
+            free_vars(expr, bound, free);
+            for arm in arms {
+                let len = bound.len();
+                if let parse::Pattern::Variant(_, names) = &arm.pattern {
+                    bound.extend(names.iter().map(|name| name.token.clone()));
+                }
+                free_vars(&arm.body, bound, free);
+                bound.truncate(len);
+            }
+        }
+        Expr::Lambda(parse::Lambda { func, body, .. }) => {
+            let len = bound.len();
+            for param in &func.params {
+                if let parse::Param::Decl(parse::Decl { name, .. }) = param {
+                    bound.push(name.token.clone());
+                }
+            }
+            free_vars(body, bound, free);
+            bound.truncate(len);
+        }
+    }
 }
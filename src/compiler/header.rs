@@ -0,0 +1,130 @@
+use super::generate::Def;
+use super::generate::Target;
+use super::symbol::Terminal;
+use super::symbol::Type;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result;
+
+/// Writes a C header declaring every exported def, guarded by a macro derived
+/// from `name`.
+pub fn header(target: &Target, name: &str) -> String {
+    let guard: String = name
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() {
+                ch.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let exports: Vec<_> = target.defs.iter().filter(|def| def.export).collect();
+
+    // Structs and enums only appear behind pointers, so declaring them is
+    // enough.
+    let mut opaque = Vec::new();
+    for def in &exports {
+        for typ in def
+            .params
+            .iter()
+            .map(|param| &param.typ)
+            .chain(Some(&def.ret))
+        {
+            if let Some(name) = pointee_name(typ) {
+                if !opaque.contains(&name) {
+                    opaque.push(name);
+                }
+            }
+        }
+    }
+
+    Header {
+        guard: &guard,
+        opaque: &opaque,
+        exports: &exports,
+    }
+    .to_string()
+}
+
+struct Header<'a> {
+    guard: &'a str,
+    opaque: &'a [&'a str],
+    exports: &'a [&'a Def],
+}
+
+impl Display for Header<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "#ifndef {}_H\n#define {}_H\n", self.guard, self.guard)?;
+        writeln!(f, "#include <stdbool.h>\n#include <stdint.h>\n")?;
+        for name in self.opaque {
+            writeln!(f, "struct {name};")?;
+        }
+        if !self.opaque.is_empty() {
+            writeln!(f)?;
+        }
+        for def in self.exports {
+            write!(f, "{}", Prototype(def))?;
+        }
+        if !self.exports.is_empty() {
+            writeln!(f)?;
+        }
+        writeln!(f, "#endif /* {}_H */", self.guard)
+    }
+}
+
+fn pointee_name(typ: &Type) -> Option<&str> {
+    match typ {
+        Type::Ptr(elem) => match &**elem {
+            Type::Struct(name) | Type::Enum(name) => Some(name),
+            elem => pointee_name(elem),
+        },
+        _ => None,
+    }
+}
+
+struct Prototype<'a>(&'a Def);
+
+impl Display for Prototype<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let def = self.0;
+        write!(f, "{} {}(", CType(&def.ret), def.name.token)?;
+        if let Some((first, rest)) = def.params.split_first() {
+            write!(f, "{}", CType(&first.typ))?;
+            for param in rest {
+                write!(f, ", {}", CType(&param.typ))?;
+            }
+        } else {
+            f.write_str("void")?;
+        }
+        writeln!(f, ");")
+    }
+}
+
+struct CType<'a>(&'a Type);
+
+impl Display for CType<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self.0 {
+            Type::Terminal(terminal) => f.write_str(match terminal {
+                Terminal::Void => "void",
+                Terminal::Bool => "bool",
+                Terminal::U8 => "uint8_t",
+                Terminal::U16 => "uint16_t",
+                Terminal::U32 => "uint32_t",
+                Terminal::U64 => "uint64_t",
+                Terminal::I8 => "int8_t",
+                Terminal::I16 => "int16_t",
+                Terminal::I32 => "int32_t",
+                Terminal::I64 => "int64_t",
+                Terminal::F16 => "_Float16",
+                Terminal::F32 => "float",
+                Terminal::F64 => "double",
+            }),
+            Type::Struct(name) | Type::Enum(name) => write!(f, "struct {name}"),
+            Type::Ptr(elem) => write!(f, "{}*", CType(elem)),
+            _ => panic!(),
+        }
+    }
+}
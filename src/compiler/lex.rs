@@ -1,4 +1,5 @@
 use super::common::Location;
+use super::error::Error;
 
 #[derive(Debug)]
 pub struct TokenStream {
@@ -12,17 +13,37 @@ pub enum Token {
     Other(String, Location),
 }
 
-pub fn lex(text: &str) -> TokenStream {
+pub fn lex(text: &str) -> Result<TokenStream, Error> {
     enum State {
         Ready,
         Other,
+        String,
+        Escape,
     }
 
     let mut tokens = Vec::new();
     let mut state = State::Ready;
 
     for (location, ch) in text.char_indices() {
+        // A string literal is kept whole, quotes and escapes included, even
+        // when it holds spaces or parentheses. It is decoded by `literalise`.
+        if let State::String | State::Escape = state {
+            if let Some(Token::Other(token, _)) = tokens.last_mut() {
+                token.push(ch);
+            }
+            state = match (state, ch) {
+                (State::String, '\\') => State::Escape,
+                (State::String, '"') => State::Other,
+                _ => State::String,
+            };
+            continue;
+        }
+
         match ch {
+            '"' if matches!(state, State::Ready) => {
+                state = State::String;
+                tokens.push(Token::Other(String::from(ch), location));
+            }
             '(' => {
                 state = State::Ready;
                 tokens.push(Token::Open(location));
@@ -49,5 +70,13 @@ pub fn lex(text: &str) -> TokenStream {
         }
     }
 
-    TokenStream { tokens }
+    // The string is the last token, since it takes every character after
+    // its opening quote.
+    if let State::String | State::Escape = state {
+        if let Some(Token::Other(_, location)) = tokens.last() {
+            return err!(unterminated_string, *location);
+        }
+    }
+
+    Ok(TokenStream { tokens })
 }
@@ -3,6 +3,7 @@ use super::lex::TokenStream;
 use super::namespace::Namespace;
 use super::symbol::Symbol;
 use super::symbol::Terminal;
+use super::symbol::Type;
 use super::symbol::BOOL;
 use super::symbol::FLOAT;
 use super::symbol::INT;
@@ -17,16 +18,16 @@ pub fn literalise(token_stream: TokenStream) -> (TokenStream, Namespace) {
             continue;
         };
 
-        let terminals = if let Some(terminals) = literal(token) {
-            terminals
+        let types = if let Some(types) = literal(token) {
+            types
         } else {
             continue;
         };
 
         if namespace.get(token).is_none() {
-            let namespaces = terminals
-                .iter()
-                .map(|terminal| Namespace::from(Symbol::Literal(*terminal)))
+            let namespaces = types
+                .into_iter()
+                .map(|typ| Namespace::from(Symbol::Literal(typ)))
                 .collect();
 
             namespace.insert_namespaces(token.clone(), namespaces);
@@ -36,10 +37,89 @@ pub fn literalise(token_stream: TokenStream) -> (TokenStream, Namespace) {
     (token_stream, namespace)
 }
 
-fn literal(token: &str) -> Option<&[Terminal]> {
-    boolean(token)
-        .or_else(|| integer(token))
-        .or_else(|| float(token))
+fn literal(token: &str) -> Option<Vec<Type>> {
+    if string(token).is_some() {
+        let byte = || Box::new(Type::Terminal(Terminal::U8));
+        return Some(vec![Type::Ptr(byte()), Type::Slice(byte())]);
+    }
+
+    let terminals = boolean(token).or_else(|| numeric(token).map(|(_, terminals)| terminals))?;
+    Some(terminals.iter().copied().map(Type::Terminal).collect())
+}
+
+/// The value of a numeric literal.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+/// Parses a numeric literal token. Returns `None` if the token is not one.
+pub fn number(token: &str) -> Option<Number> {
+    numeric(token).map(|(number, _)| number)
+}
+
+/// Whether a numeric literal can be given a terminal type without
+/// overflowing it.
+pub fn fits(token: &str, terminal: Terminal) -> bool {
+    let (min, max) = match terminal {
+        Terminal::U8 => (0, i128::from(u8::MAX)),
+        Terminal::U16 => (0, i128::from(u16::MAX)),
+        Terminal::U32 => (0, i128::from(u32::MAX)),
+        Terminal::U64 => (0, i128::from(u64::MAX)),
+        Terminal::I8 => (i128::from(i8::MIN), i128::from(i8::MAX)),
+        Terminal::I16 => (i128::from(i16::MIN), i128::from(i16::MAX)),
+        Terminal::I32 => (i128::from(i32::MIN), i128::from(i32::MAX)),
+        Terminal::I64 => (i128::from(i64::MIN), i128::from(i64::MAX)),
+        _ => (0, 0),
+    };
+
+    match number(token) {
+        Some(Number::Int(value)) => min <= value && value <= max,
+        Some(Number::Float(value)) => match terminal {
+            // Anything from here on rounds to infinity.
+            Terminal::F16 => value.abs() < 65520.0,
+            #[allow(clippy::cast_possible_truncation)]
+            Terminal::F32 => (value as f32).is_finite(),
+            _ => value.is_finite(),
+        },
+        None => false,
+    }
+}
+
+/// Decodes a string literal token, quotes included, into its bytes. Returns
+/// `None` if the token is not a well-formed string literal.
+pub fn string(token: &str) -> Option<Vec<u8>> {
+    let token = token.strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut bytes = Vec::new();
+    let mut chars = token.chars();
+    while let Some(ch) = chars.next() {
+        let byte = match ch {
+            '"' => return None,
+            '\\' => match chars.next()? {
+                'n' => b'\n',
+                't' => b'\t',
+                'r' => b'\r',
+                '0' => b'\0',
+                '\\' => b'\\',
+                '"' => b'"',
+                'x' => {
+                    let digits = [chars.next()?, chars.next()?];
+                    let digits: String = digits.iter().collect();
+                    u8::from_str_radix(&digits, 16).ok()?
+                }
+                _ => return None,
+            },
+            _ => {
+                let mut buf = [0; 4];
+                bytes.extend(ch.encode_utf8(&mut buf).bytes());
+                continue;
+            }
+        };
+        bytes.push(byte);
+    }
+    Some(bytes)
 }
 
 fn boolean(token: &str) -> Option<&[Terminal]> {
@@ -49,45 +129,108 @@ fn boolean(token: &str) -> Option<&[Terminal]> {
     }
 }
 
-fn integer(token: &str) -> Option<&[Terminal]> {
-    for ch in token.chars() {
-        if !ch.is_digit(10) {
+/// Parses a numeric literal: an optional `-`, then either an integer in
+/// decimal, or in hex, binary or octal after `0x`, `0b` or `0o`, or a decimal
+/// float with a fraction, an exponent or both. Digits may be separated by `_`.
+/// A suffix such as `u8` or `f32` picks the literal's type, which otherwise is
+/// any integer or float type.
+///
+/// A value too large to parse saturates, so that it is out of range for every
+/// type instead of not being a literal at all.
+fn numeric(token: &str) -> Option<(Number, &'static [Terminal])> {
+    let (negative, rest) = token
+        .strip_prefix('-')
+        .map_or((false, token), |rest| (true, rest));
+
+    let (radix, rest) = match rest.get(..2) {
+        Some("0x") => (16, &rest[2..]),
+        Some("0b") => (2, &rest[2..]),
+        Some("0o") => (8, &rest[2..]),
+        _ => (10, rest),
+    };
+
+    // `f` is a hex digit, so hex literals cannot take a float suffix.
+    let (digits, suffix) = split_suffix(rest, radix == 10);
+
+    let is_float = radix == 10 && digits.contains(['.', 'e', 'E']);
+    if is_float {
+        if !is_float_digits(digits) || matches!(suffix, Some((_, false))) {
             return None;
         }
+    } else if !is_digits(digits, radix) {
+        return None;
     }
-    Some(&INT)
+
+    let terminals = match suffix {
+        Some((terminals, _)) => terminals,
+        None if is_float => &FLOAT,
+        None => &INT,
+    };
+
+    let digits: String = digits.chars().filter(|&ch| ch != '_').collect();
+    let number = if is_float || matches!(suffix, Some((_, true))) {
+        let value: f64 = digits.parse().ok()?;
+        Number::Float(if negative { -value } else { value })
+    } else {
+        let value = i128::from_str_radix(&digits, radix).unwrap_or(i128::MAX);
+        Number::Int(if negative { -value } else { value })
+    };
+    Some((number, terminals))
 }
 
-fn float(token: &str) -> Option<&[Terminal]> {
-    enum State {
-        FirstInteger,
-        Integer,
-        FirstFraction,
-        Fraction,
-    }
-    let mut state = State::FirstInteger;
-
-    for ch in token.chars() {
-        if ch == '.' {
-            if let State::Integer = state {
-                state = State::FirstFraction;
-            } else {
-                return None;
-            }
-        } else if ch.is_digit(10) {
-            match state {
-                State::FirstInteger => state = State::Integer,
-                State::FirstFraction => state = State::Fraction,
-                _ => (),
-            }
-        } else {
-            return None;
+/// Splits a type suffix off a literal, along with whether it is a float type.
+fn split_suffix(token: &str, float: bool) -> (&str, Option<(&'static [Terminal], bool)>) {
+    const SUFFIXES: [(&str, &[Terminal], bool); 11] = [
+        ("u8", &[Terminal::U8], false),
+        ("u16", &[Terminal::U16], false),
+        ("u32", &[Terminal::U32], false),
+        ("u64", &[Terminal::U64], false),
+        ("i8", &[Terminal::I8], false),
+        ("i16", &[Terminal::I16], false),
+        ("i32", &[Terminal::I32], false),
+        ("i64", &[Terminal::I64], false),
+        ("f16", &[Terminal::F16], true),
+        ("f32", &[Terminal::F32], true),
+        ("f64", &[Terminal::F64], true),
+    ];
+
+    for (suffix, terminals, is_float) in &SUFFIXES {
+        if *is_float && !float {
+            continue;
+        }
+        if let Some(digits) = token.strip_suffix(suffix) {
+            return (digits, Some((terminals, *is_float)));
         }
     }
+    (token, None)
+}
 
-    if let State::Fraction = state {
-        Some(&FLOAT)
-    } else {
-        None
-    }
+/// Digits in a radix, where every `_` sits between two digits.
+fn is_digits(token: &str, radix: u32) -> bool {
+    !token.is_empty()
+        && token
+            .split('_')
+            .all(|group| !group.is_empty() && group.chars().all(|ch| ch.is_digit(radix)))
+}
+
+/// `digits`, then an optional `.digits`, then an optional `e` with an optional
+/// sign and `digits`.
+fn is_float_digits(token: &str) -> bool {
+    let (mantissa, exponent) = token
+        .split_once(['e', 'E'])
+        .map_or((token, None), |(mantissa, exponent)| {
+            (mantissa, Some(exponent))
+        });
+
+    let mantissa_ok = match mantissa.split_once('.') {
+        Some((integer, fraction)) => is_digits(integer, 10) && is_digits(fraction, 10),
+        None => is_digits(mantissa, 10),
+    };
+
+    let exponent_ok = exponent.is_none_or(|exponent| {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        is_digits(exponent, 10)
+    });
+
+    mantissa_ok && exponent_ok
 }
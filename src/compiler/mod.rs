@@ -3,6 +3,7 @@ pub mod error;
 mod common;
 mod emit;
 mod generate;
+mod header;
 mod lex;
 mod literalise;
 mod namespace;
@@ -14,33 +15,40 @@ mod test;
 mod treeify;
 mod typecheck;
 
+pub use generate::Options;
+
 use error::Error;
 use generate::Target;
 use lex::TokenStream;
 use namespace::Namespace;
 use parse::Program;
+use std::mem;
 use treeify::TokenTree;
 
-pub fn main(text: &str) -> Result<String, Error> {
-    emit(text)
+/// Compiles a program to LLVM IR, along with any warnings about it.
+pub fn main(text: &str, options: &Options) -> Result<(String, Vec<Error>), Error> {
+    let (mut program, namespace) = typecheck(text)?;
+    let warnings = mem::take(&mut program.warnings);
+    let target = generate::generate(program, namespace, text, options);
+    Ok((emit::emit(target), warnings))
 }
 
-pub fn lex(text: &str) -> TokenStream {
+pub fn lex(text: &str) -> Result<TokenStream, Error> {
     lex::lex(text)
 }
 
-pub fn literalise(text: &str) -> (TokenStream, Namespace) {
-    let token_stream = lex(text);
-    literalise::literalise(token_stream)
+pub fn literalise(text: &str) -> Result<(TokenStream, Namespace), Error> {
+    let token_stream = lex(text)?;
+    Ok(literalise::literalise(token_stream))
 }
 
-pub fn treeify(text: &str) -> (TokenTree, Namespace) {
-    let (token_stream, namespace) = literalise(text);
-    (treeify::treeify(token_stream), namespace)
+pub fn treeify(text: &str) -> Result<(TokenTree, Namespace), Error> {
+    let (token_stream, namespace) = literalise(text)?;
+    Ok((treeify::treeify(token_stream), namespace))
 }
 
 pub fn parse(text: &str) -> Result<(Program, Namespace), Error> {
-    let (token_tree, namespace) = treeify(text);
+    let (token_tree, namespace) = treeify(text)?;
     parse::parse(token_tree, namespace)
 }
 
@@ -54,12 +62,17 @@ pub fn typecheck(text: &str) -> Result<(Program, Namespace), Error> {
     typecheck::typecheck(program, namespace)
 }
 
-pub fn generate(text: &str) -> Result<Target, Error> {
+pub fn generate(text: &str, options: &Options) -> Result<Target, Error> {
     let (program, namespace) = typecheck(text)?;
-    Ok(generate::generate(program, namespace))
+    Ok(generate::generate(program, namespace, text, options))
 }
 
-pub fn emit(text: &str) -> Result<String, Error> {
-    let target = generate(text)?;
+pub fn emit(text: &str, options: &Options) -> Result<String, Error> {
+    let target = generate(text, options)?;
     Ok(emit::emit(target))
 }
+
+pub fn header(text: &str, name: &str) -> Result<String, Error> {
+    let target = generate(text, &Options::default())?;
+    Ok(header::header(&target, name))
+}
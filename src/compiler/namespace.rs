@@ -1,18 +1,26 @@
 use super::common::Id;
+use super::common::Location;
+use super::symbol::Class;
 use super::symbol::Func;
 use super::symbol::Symbol;
 use super::symbol::Terminal;
 use super::symbol::Type;
 use crate::compiler::symbol::ANY;
 use crate::compiler::symbol::BOOL;
+use crate::compiler::symbol::FLOAT;
 use crate::compiler::symbol::INT;
 use crate::compiler::symbol::NUM;
+use crate::compiler::symbol::SIGNED;
 use std::collections::HashMap;
+use std::slice;
 
 #[derive(Debug)]
+#[allow(clippy::struct_field_names)]
 pub struct Namespace {
     symbol: Symbol,
     namespace: HashMap<String, Vec<Namespace>>,
+    builtin: bool,
+    location: Option<Location>,
 }
 
 impl From<(Symbol, HashMap<String, Vec<Namespace>>)> for Namespace {
@@ -20,6 +28,8 @@ impl From<(Symbol, HashMap<String, Vec<Namespace>>)> for Namespace {
         Self {
             symbol: parts.0,
             namespace: parts.1,
+            builtin: false,
+            location: None,
         }
     }
 }
@@ -29,33 +39,59 @@ impl From<Symbol> for Namespace {
         Self {
             symbol,
             namespace: HashMap::new(),
+            builtin: false,
+            location: None,
         }
     }
 }
 
 impl Namespace {
     pub fn new_module() -> Self {
-        Self::from((Symbol::Module, builtins()))
+        let mut builtins = builtins();
+        for namespace in builtins.values_mut().flatten() {
+            namespace.builtin = true;
+        }
+        Self::from((Symbol::Module, builtins))
     }
 
     pub const fn symbol(&self) -> &Symbol {
         &self.symbol
     }
 
-    pub fn get(&self, key: &str) -> Option<&Vec<Self>> {
-        self.namespace.get(key)
+    pub const fn symbol_mut(&mut self) -> &mut Symbol {
+        &mut self.symbol
+    }
+
+    /// Whether this is one of the symbols every module starts with, rather
+    /// than one the program defined.
+    pub const fn is_builtin(&self) -> bool {
+        self.builtin
+    }
+
+    /// Where the program defined this symbol, for errors that point at it.
+    pub const fn location(&self) -> Option<Location> {
+        self.location
+    }
+
+    pub const fn with_location(mut self, location: Location) -> Self {
+        self.location = Some(location);
+        self
     }
 
-    pub fn get_or<'a>(&'a self, other: &'a Self, key: &str) -> Option<&'a Vec<Self>> {
-        self.get(key).or_else(|| other.get(key))
+    pub fn get(&self, key: &str) -> Option<&Vec<Self>> {
+        self.namespace.get(key)
     }
 
     pub fn get_then<'a>(&'a self, key: &str, id: Id) -> Option<&'a Self> {
         self.get(key).and_then(|symbols| symbols.get(id))
     }
 
-    pub fn get_or_then<'a>(&'a self, other: &'a Self, key: &str, id: Id) -> Option<&'a Self> {
-        self.get_or(other, key).and_then(|symbols| symbols.get(id))
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Vec<Self>> {
+        self.namespace.get_mut(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Vec<Self>)> {
+        self.namespace.iter()
     }
 
     pub fn insert_namespaces(&mut self, key: String, namespaces: Vec<Self>) {
@@ -74,6 +110,98 @@ impl Namespace {
     }
 }
 
+/// A chain of namespaces searched innermost first. A name found in an inner
+/// namespace hides every symbol of the same name further out, so a `let`
+/// binding shadows parameters, outer bindings and module-level symbols alike.
+pub struct Scope<'a> {
+    namespace: &'a Namespace,
+    parent: Option<&'a Self>,
+}
+
+impl<'a> Scope<'a> {
+    pub const fn new(namespace: &'a Namespace) -> Self {
+        Self {
+            namespace,
+            parent: None,
+        }
+    }
+
+    pub const fn push(&'a self, namespace: &'a Namespace) -> Self {
+        Self {
+            namespace,
+            parent: Some(self),
+        }
+    }
+
+    pub fn root(&self) -> &'a Namespace {
+        self.parent.map_or(self.namespace, Scope::root)
+    }
+
+    /// The symbol of the function being checked: the innermost lambda, or
+    /// else the def whose namespace sits just inside the module.
+    pub fn def_symbol(&self) -> &'a Symbol {
+        match self.parent {
+            Some(parent)
+                if parent.parent.is_some()
+                    && !matches!(self.namespace.symbol(), Symbol::Lambda(_)) =>
+            {
+                parent.def_symbol()
+            }
+            _ => self.namespace.symbol(),
+        }
+    }
+
+    /// The type parameters of the def being checked, if it is generic.
+    pub fn type_params(&self) -> &'a [String] {
+        match self.parent {
+            Some(parent) if parent.parent.is_some() => parent.type_params(),
+            _ => match self.namespace.symbol() {
+                Symbol::Generic(_, type_params, _) => type_params,
+                _ => &[],
+            },
+        }
+    }
+
+    /// The classes the type parameters of the def being checked are
+    /// constrained by.
+    pub fn constraints(&self) -> &'a [(String, String)] {
+        match self.parent {
+            Some(parent) if parent.parent.is_some() => parent.constraints(),
+            _ => match self.namespace.symbol() {
+                Symbol::Generic(_, _, constraints) => constraints,
+                _ => &[],
+            },
+        }
+    }
+
+    /// Whether `key` names a parameter or binding rather than a symbol of the
+    /// module.
+    pub fn is_local(&self, key: &str) -> bool {
+        self.parent
+            .is_some_and(|parent| self.namespace.get(key).is_some() || parent.is_local(key))
+    }
+
+    /// Whether `key` names a parameter or binding from outside the innermost
+    /// lambda, which the lambda holds a copy of.
+    pub fn is_captured(&self, key: &str) -> bool {
+        if self.namespace.get(key).is_some() {
+            return false;
+        }
+        self.parent
+            .is_some_and(|parent| match self.namespace.symbol() {
+                Symbol::Lambda(_) => parent.is_local(key),
+                _ => parent.is_captured(key),
+            })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&'a Vec<Namespace>> {
+        self.namespace
+            .get(key)
+            .or_else(|| self.parent.and_then(|parent| parent.get(key)))
+    }
+}
+
+#[allow(clippy::too_many_lines)]
 fn builtins() -> HashMap<String, Vec<Namespace>> {
     macro_rules! typ {
         ($s:literal, $terminal:tt) => {
@@ -86,6 +214,24 @@ fn builtins() -> HashMap<String, Vec<Namespace>> {
         };
     }
 
+    // A number type's name also converts any terminal to it. Converting from
+    // the type itself comes last, so that it is tried first and a literal is
+    // not converted.
+    macro_rules! num {
+        ($s:literal, $terminal:tt) => {{
+            let to = Terminal::$terminal;
+            let mut namespaces = vec![Namespace::from(Symbol::Type(Type::Terminal(to)))];
+            let from = ANY.iter().filter(|&&from| from != to).chain(Some(&to));
+            namespaces.extend(from.map(|from| {
+                Namespace::from(Symbol::Var(Type::Func(Func {
+                    params: vec![Type::Terminal(*from)],
+                    ret: Box::new(Type::Terminal(to)),
+                })))
+            }));
+            ($s.to_string(), namespaces)
+        }};
+    }
+
     macro_rules! unary {
         ($s:literal, $params:ident) => {{
             (
@@ -94,8 +240,8 @@ fn builtins() -> HashMap<String, Vec<Namespace>> {
                     .iter()
                     .map(|param| {
                         Namespace::from(Symbol::Var(Type::Func(Func {
-                            params: vec![*param],
-                            ret: *param,
+                            params: vec![Type::Terminal(*param)],
+                            ret: Box::new(Type::Terminal(*param)),
                         })))
                     })
                     .collect(),
@@ -111,23 +257,25 @@ fn builtins() -> HashMap<String, Vec<Namespace>> {
                     .iter()
                     .map(|param| {
                         Namespace::from(Symbol::Var(Type::Func(Func {
-                            params: vec![*param, *param],
-                            ret: *param,
+                            params: vec![Type::Terminal(*param), Type::Terminal(*param)],
+                            ret: Box::new(Type::Terminal(*param)),
                         })))
                     })
                     .collect(),
             )
         }};
+    }
 
-        ($s:literal, $params:ident, $ret:expr) => {{
+    // The result of an overflowing operation, and whether it overflowed.
+    macro_rules! overflowing {
+        ($s:literal) => {{
             (
                 $s.to_string(),
-                $params
-                    .iter()
+                INT.iter()
                     .map(|param| {
                         Namespace::from(Symbol::Var(Type::Func(Func {
-                            params: vec![*param, *param],
-                            ret: $ret,
+                            params: vec![Type::Terminal(*param), Type::Terminal(*param)],
+                            ret: Box::new(Type::Overflow(*param)),
                         })))
                     })
                     .collect(),
@@ -135,42 +283,162 @@ fn builtins() -> HashMap<String, Vec<Namespace>> {
         }};
     }
 
+    macro_rules! overflow_field {
+        ($s:literal, $index:literal, $ret:expr) => {{
+            (
+                $s.to_string(),
+                INT.iter()
+                    .map(|param| {
+                        Namespace::from(Symbol::Field(
+                            Func {
+                                params: vec![Type::Overflow(*param)],
+                                ret: Box::new($ret(*param)),
+                            },
+                            $index,
+                        ))
+                    })
+                    .collect(),
+            )
+        }};
+    }
+
     vec![
         typ!("void", Void),
         typ!("bool", Bool),
-        typ!("u8", U8),
-        typ!("u16", U16),
-        typ!("u32", U32),
-        typ!("u64", U64),
-        typ!("i8", I8),
-        typ!("i16", I16),
-        typ!("i32", I32),
-        typ!("i64", I64),
-        typ!("f16", F16),
-        typ!("f32", F32),
-        typ!("f64", F64),
+        num!("u8", U8),
+        num!("u16", U16),
+        num!("u32", U32),
+        num!("u64", U64),
+        num!("i8", I8),
+        num!("i16", I16),
+        num!("i32", I32),
+        num!("i64", I64),
+        num!("f16", F16),
+        num!("f32", F32),
+        num!("f64", F64),
+        (
+            "ptr".to_string(),
+            vec![Namespace::from(Symbol::Type(Type::Ptr(Box::new(
+                Type::Terminal(Terminal::U8),
+            ))))],
+        ),
         unary!("!", BOOL),
-        unary!("~", INT),
-        binary!("+", NUM),
-        binary!("-", NUM),
-        binary!("*", NUM),
-        binary!("/", NUM),
-        binary!("%", NUM),
-        binary!("&", INT),
-        binary!("|", INT),
-        binary!("^", INT),
-        binary!("<<", INT),
-        binary!(">>", INT),
+        overflowing!("+?"),
+        overflowing!("-?"),
+        overflowing!("*?"),
+        overflow_field!(".value", 0, Type::Terminal),
+        overflow_field!(".overflow", 1, |_| Type::Terminal(Terminal::Bool)),
         binary!("&&", BOOL),
         binary!("||", BOOL),
         binary!("^^", BOOL),
-        binary!("==", ANY, Terminal::Bool),
-        binary!("!=", ANY, Terminal::Bool),
-        binary!("<=", NUM, Terminal::Bool),
-        binary!(">=", NUM, Terminal::Bool),
-        binary!("<", NUM, Terminal::Bool),
-        binary!(">", NUM, Terminal::Bool),
     ]
     .into_iter()
+    .chain(builtin_classes().into_iter().flat_map(class_symbols))
     .collect()
 }
+
+type BuiltinClass = (&'static str, &'static [Terminal], Vec<(&'static str, Func)>);
+
+/// The builtin classes, with the terminals that are instances of each and the
+/// methods they provide. Every builtin operator on numbers is a method, so its
+/// overloads are those of its class's instances.
+fn builtin_classes() -> Vec<BuiltinClass> {
+    macro_rules! methods {
+        ($class:literal, $(($s:literal, $arity:literal $(, $ret:expr)?)),* $(,)?) => {
+            vec![$(($s, Func {
+                params: vec![Type::Param($class.to_string()); $arity],
+                ret: Box::new(methods!(@ret $class $(, $ret)?)),
+            })),*]
+        };
+        (@ret $class:literal) => { Type::Param($class.to_string()) };
+        (@ret $class:literal, $ret:expr) => { $ret };
+    }
+
+    let bool = Type::Terminal(Terminal::Bool);
+    vec![
+        (
+            "Eq",
+            &ANY,
+            methods!("Eq", ("==", 2, bool.clone()), ("!=", 2, bool.clone())),
+        ),
+        (
+            "Ord",
+            &NUM,
+            methods!(
+                "Ord",
+                ("<=", 2, bool.clone()),
+                (">=", 2, bool.clone()),
+                ("<", 2, bool.clone()),
+                (">", 2, bool),
+                ("min", 2),
+                ("max", 2),
+            ),
+        ),
+        (
+            "Num",
+            &NUM,
+            methods!("Num", ("+", 2), ("-", 2), ("*", 2), ("/", 2), ("%", 2)),
+        ),
+        (
+            "Int",
+            &INT,
+            methods!(
+                "Int",
+                ("~", 1),
+                ("+%", 2),
+                ("-%", 2),
+                ("*%", 2),
+                ("+|", 2),
+                ("-|", 2),
+                ("*|", 2),
+                ("&", 2),
+                ("|", 2),
+                ("^", 2),
+                ("<<", 2),
+                (">>", 2),
+                ("popcount", 1),
+                ("clz", 1),
+                ("ctz", 1),
+                ("rotl", 2),
+                ("rotr", 2),
+            ),
+        ),
+        ("Signed", &SIGNED, methods!("Signed", ("abs", 1))),
+        (
+            "Float",
+            &FLOAT,
+            methods!("Float", ("sqrt", 1), ("floor", 1), ("ceil", 1), ("fma", 3)),
+        ),
+    ]
+}
+
+/// The class itself, and each of its methods: first the method, then its
+/// overload for every instance.
+fn class_symbols((name, instances, methods): BuiltinClass) -> Vec<(String, Vec<Namespace>)> {
+    let instances: Vec<_> = instances.iter().copied().map(Type::Terminal).collect();
+    let mut symbols = Vec::new();
+    for (method, func) in &methods {
+        let mut namespaces = vec![Namespace::from(Symbol::Method(
+            func.clone(),
+            name.to_string(),
+        ))];
+        for instance in &instances {
+            let func = func.substitute(&[name.to_string()], slice::from_ref(instance));
+            namespaces.push(Namespace::from(Symbol::Var(Type::Func(func))));
+        }
+        symbols.push((method.to_string(), namespaces));
+    }
+
+    let class = Class {
+        methods: methods
+            .into_iter()
+            .map(|(method, func)| (method.to_string(), func))
+            .collect(),
+        instances,
+    };
+    symbols.push((
+        name.to_string(),
+        vec![Namespace::from(Symbol::Class(class))],
+    ));
+    symbols
+}
@@ -1,22 +1,97 @@
 use super::common::Id;
 use super::common::Location;
 use super::error::Error;
+use super::literalise;
 use super::namespace::Namespace;
+use super::symbol;
 use super::treeify::TokenTree;
+use std::ops::Range;
 
 #[derive(Debug)]
 pub struct Program {
     pub defs: Vec<Def>,
+    pub externs: Vec<Extern>,
+    pub structs: Vec<Struct>,
+    pub enums: Vec<Enum>,
+    pub classes: Vec<Class>,
+    pub instances: Vec<Instance>,
+    /// Problems found that do not stop compilation, reported by the driver.
+    pub warnings: Vec<Error>,
+}
+
+/// A function defined outside Lamb, such as in C, called by its exact name.
+#[derive(Debug)]
+pub struct Extern {
+    pub name_id: NameId,
+    pub func: Func,
+    #[allow(dead_code)]
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct Struct {
+    pub name: Name,
+    pub fields: Vec<Decl>,
+    #[allow(dead_code)]
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct Enum {
+    pub name: Name,
+    pub variants: Vec<Variant>,
+    #[allow(dead_code)]
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct Variant {
+    pub name: Name,
+    pub fields: Vec<Type>,
+}
+
+/// `(class Name (T) (method type) ...)` names the methods an instance of the
+/// class provides, typed in terms of the class's type parameter `T`.
+#[derive(Debug)]
+pub struct Class {
+    pub name: Name,
+    pub param: Name,
+    pub methods: Vec<Decl>,
+    #[allow(dead_code)]
+    pub location: Location,
+}
+
+/// `(instance Class type def ...)` makes a type an instance of a class. Its
+/// defs are the methods, and are kept with the program's other defs at the
+/// indices `defs`.
+#[derive(Debug)]
+pub struct Instance {
+    pub class: Name,
+    pub typ: Type,
+    pub defs: Range<usize>,
+    pub location: Location,
 }
 
 #[derive(Debug)]
 pub struct Def {
     pub name_id: NameId,
+    pub type_params: Vec<TypeParam>,
     pub func: Func,
     pub expr: Expr,
+    pub export: bool,
+    pub checked: bool,
+    #[allow(dead_code)]
     pub location: Location,
 }
 
+/// A type parameter of a generic def, and the classes the types it stands for
+/// must be instances of, as in `(T Num Ord)`.
+#[derive(Debug)]
+pub struct TypeParam {
+    pub name: Name,
+    pub classes: Vec<Name>,
+}
+
 #[derive(Debug)]
 pub struct Func {
     pub params: Vec<Param>,
@@ -34,14 +109,113 @@ pub enum Param {
 pub enum Expr {
     Val(NameId),
     Call(Call),
+    If(If),
+    Let(Let),
+    Set(Set),
+    While(While),
+    Do(Do),
+    Match(Match),
+    Lambda(Lambda),
+}
+
+impl Expr {
+    pub const fn location(&self) -> Location {
+        match self {
+            Self::Val(NameId { location, .. })
+            | Self::Call(Call { location, .. })
+            | Self::If(If { location, .. })
+            | Self::Let(Let { location, .. })
+            | Self::Set(Set { location, .. })
+            | Self::While(While { location, .. })
+            | Self::Do(Do { location, .. })
+            | Self::Match(Match { location, .. })
+            | Self::Lambda(Lambda { location, .. }) => *location,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Call {
     pub exprs: Vec<Expr>,
+    pub tail: bool,
     pub location: Location,
 }
 
+#[derive(Debug)]
+pub struct If {
+    pub cond: Box<Expr>,
+    pub then: Box<Expr>,
+    pub els: Box<Expr>,
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct Let {
+    pub bindings: Vec<Binding>,
+    pub body: Box<Expr>,
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct Binding {
+    pub name: Name,
+    pub typ: Type,
+    pub expr: Expr,
+    pub mutable: bool,
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct Set {
+    pub name: Name,
+    pub expr: Box<Expr>,
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct While {
+    pub cond: Box<Expr>,
+    pub body: Box<Expr>,
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct Do {
+    pub exprs: Vec<Expr>,
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct Match {
+    pub expr: Box<Expr>,
+    pub arms: Vec<Arm>,
+    pub location: Location,
+}
+
+#[derive(Debug)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub body: Expr,
+    pub location: Location,
+}
+
+/// An anonymous function. It may refer to the parameters and bindings around
+/// it, which it captures by value.
+#[derive(Debug)]
+pub struct Lambda {
+    pub func: Func,
+    pub body: Box<Expr>,
+    pub location: Location,
+}
+
+/// A pattern is `_`, a variant name, or a variant name followed by a name
+/// for each of its fields.
+#[derive(Debug)]
+pub enum Pattern {
+    Wildcard,
+    Variant(NameId, Vec<Name>),
+}
+
 #[derive(Debug)]
 pub struct Decl {
     pub name: Name,
@@ -55,21 +229,50 @@ pub struct Name {
     pub location: Location,
 }
 
+/// A type is either a name or one of the compound forms `(ptr T)`,
+/// `(array T N)`, `(slice T)` and `(overflow T)`. Where a value is declared,
+/// it may also be a function type: its parameter types followed by its
+/// return type, as in `(i32 i32)`.
 #[derive(Debug)]
-pub struct Type {
-    pub token: String,
-    pub location: Location,
+pub enum Type {
+    Name(Name),
+    Ptr(Box<Self>, Location),
+    Array(Box<Self>, usize, Location),
+    Slice(Box<Self>, Location),
+    Overflow(Box<Self>, Location),
+    Func(Vec<Self>, Box<Self>, Location),
+}
+
+impl Type {
+    pub const fn location(&self) -> Location {
+        match self {
+            Self::Name(Name { location, .. })
+            | Self::Ptr(_, location)
+            | Self::Array(_, _, location)
+            | Self::Slice(_, location)
+            | Self::Overflow(_, location)
+            | Self::Func(_, _, location) => *location,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct NameId {
     pub token: String,
     pub id: Id,
+    /// The types a generic def is instantiated with here, in terms of the
+    /// type parameters of the def this is in. Set by typechecking.
+    pub type_args: Vec<symbol::Type>,
     pub location: Location,
 }
 
 pub fn parse(token_tree: TokenTree, namespace: Namespace) -> Result<(Program, Namespace), Error> {
     let mut defs = Vec::new();
+    let mut externs = Vec::new();
+    let mut structs = Vec::new();
+    let mut enums = Vec::new();
+    let mut classes = Vec::new();
+    let mut instances = Vec::new();
 
     match token_tree {
         TokenTree::Token(token, location) => {
@@ -77,14 +280,270 @@ pub fn parse(token_tree: TokenTree, namespace: Namespace) -> Result<(Program, Na
         }
         TokenTree::Tree(tree, _) => {
             for token_tree in &tree {
-                defs.push(parse_def(token_tree)?);
+                match token_tree {
+                    TokenTree::Tree(tree, location) if is_keyword(tree, "struct") => {
+                        structs.push(parse_struct(tree, *location)?);
+                    }
+                    TokenTree::Tree(tree, location) if is_keyword(tree, "enum") => {
+                        enums.push(parse_enum(tree, *location)?);
+                    }
+                    TokenTree::Tree(tree, location) if is_keyword(tree, "extern") => {
+                        externs.push(parse_extern(tree, *location)?);
+                    }
+                    TokenTree::Tree(tree, location) if is_keyword(tree, "class") => {
+                        classes.push(parse_class(tree, *location)?);
+                    }
+                    TokenTree::Tree(tree, location) if is_keyword(tree, "instance") => {
+                        let (instance, methods) = parse_instance(tree, *location, defs.len())?;
+                        instances.push(instance);
+                        defs.extend(methods);
+                    }
+                    _ => defs.push(parse_def(token_tree)?),
+                }
+            }
+        }
+    }
+
+    Ok((
+        Program {
+            defs,
+            externs,
+            structs,
+            enums,
+            classes,
+            instances,
+            warnings: Vec::new(),
+        },
+        namespace,
+    ))
+}
+
+fn is_keyword(tree: &[TokenTree], keyword: &str) -> bool {
+    matches!(tree.first(), Some(TokenTree::Token(token, _)) if token == keyword)
+}
+
+fn parse_struct(tree: &[TokenTree], tree_location: Location) -> Result<Struct, Error> {
+    let (name, fields) = match tree {
+        [_, TokenTree::Token(token, location), fields @ ..] => (
+            Name {
+                token: token.clone(),
+                location: *location,
+            },
+            fields,
+        ),
+        _ => return err!(expected_struct_name, tree_location),
+    };
+
+    let mut struct_fields: Vec<Decl> = Vec::new();
+    for field in fields {
+        let field = parse_field(field)?;
+        if struct_fields
+            .iter()
+            .any(|f| f.name.token == field.name.token)
+        {
+            return err!(duplicate_field, field.name.location, field.name.token);
+        }
+        struct_fields.push(field);
+    }
+
+    Ok(Struct {
+        name,
+        fields: struct_fields,
+        location: tree_location,
+    })
+}
+
+fn parse_field(token_tree: &TokenTree) -> Result<Decl, Error> {
+    match token_tree {
+        TokenTree::Tree(tree, location) => match &tree[..] {
+            [TokenTree::Token(name, name_location), typ] if !is_empty_tree(typ) => Ok(Decl {
+                name: Name {
+                    token: name.clone(),
+                    location: *name_location,
+                },
+                typ: parse_value_type(typ)?,
+                location: *location,
+            }),
+            _ => err!(expected_field, *location),
+        },
+        TokenTree::Token(_, location) => err!(expected_field, *location),
+    }
+}
+
+fn parse_enum(tree: &[TokenTree], tree_location: Location) -> Result<Enum, Error> {
+    let (name, variants) = match tree {
+        [_, TokenTree::Token(token, location), variants @ ..] => (
+            Name {
+                token: token.clone(),
+                location: *location,
+            },
+            variants,
+        ),
+        _ => return err!(expected_enum_name, tree_location),
+    };
+
+    if variants.is_empty() {
+        return err!(expected_variant, tree_location);
+    }
+
+    let mut enum_variants: Vec<Variant> = Vec::new();
+    for variant in variants {
+        let variant = parse_variant(variant)?;
+        if enum_variants
+            .iter()
+            .any(|v| v.name.token == variant.name.token)
+        {
+            return err!(duplicate_variant, variant.name.location, variant.name.token);
+        }
+        enum_variants.push(variant);
+    }
+
+    Ok(Enum {
+        name,
+        variants: enum_variants,
+        location: tree_location,
+    })
+}
+
+fn parse_variant(token_tree: &TokenTree) -> Result<Variant, Error> {
+    let (name, fields) = match token_tree {
+        TokenTree::Tree(tree, location) => match &tree[..] {
+            [TokenTree::Token(token, name_location), fields @ ..] => (
+                Name {
+                    token: token.clone(),
+                    location: *name_location,
+                },
+                fields,
+            ),
+            _ => return err!(expected_variant, *location),
+        },
+        TokenTree::Token(token, location) => (
+            Name {
+                token: token.clone(),
+                location: *location,
+            },
+            &[][..],
+        ),
+    };
+
+    let mut variant_fields = Vec::new();
+    for field in fields {
+        if let TokenTree::Tree(_, location) = field {
+            if is_nested(field) {
+                return err!(expected_variant, *location);
             }
         }
+        variant_fields.push(parse_type(field)?);
+    }
+
+    Ok(Variant {
+        name,
+        fields: variant_fields,
+    })
+}
+
+fn parse_class(tree: &[TokenTree], tree_location: Location) -> Result<Class, Error> {
+    let (name, param, methods) = match tree {
+        [_, TokenTree::Token(token, location), TokenTree::Tree(param, _), methods @ ..] => {
+            match &param[..] {
+                [TokenTree::Token(param, param_location)] => (
+                    Name {
+                        token: token.clone(),
+                        location: *location,
+                    },
+                    Name {
+                        token: param.clone(),
+                        location: *param_location,
+                    },
+                    methods,
+                ),
+                _ => return err!(expected_class_param, *location),
+            }
+        }
+        [_, TokenTree::Token(_, location), ..] => return err!(expected_class_param, *location),
+        _ => return err!(expected_class_name, tree_location),
+    };
+
+    let mut class_methods: Vec<Decl> = Vec::new();
+    for method in methods {
+        let method = parse_method(method)?;
+        if class_methods
+            .iter()
+            .any(|m| m.name.token == method.name.token)
+        {
+            return err!(duplicate_method, method.name.location, method.name.token);
+        }
+        class_methods.push(method);
     }
 
-    Ok((Program { defs }, namespace))
+    Ok(Class {
+        name,
+        param,
+        methods: class_methods,
+        location: tree_location,
+    })
+}
+
+/// Parses `(name type)`, where the type must be a function type.
+fn parse_method(token_tree: &TokenTree) -> Result<Decl, Error> {
+    match token_tree {
+        TokenTree::Tree(tree, location) => match &tree[..] {
+            [TokenTree::Token(name, name_location), typ] if is_nested(typ) => Ok(Decl {
+                name: Name {
+                    token: name.clone(),
+                    location: *name_location,
+                },
+                typ: parse_value_type(typ)?,
+                location: *location,
+            }),
+            _ => err!(expected_method, *location),
+        },
+        TokenTree::Token(_, location) => err!(expected_method, *location),
+    }
 }
 
+/// Parses an instance along with its methods, which go after the `start`
+/// defs already parsed.
+fn parse_instance(
+    tree: &[TokenTree],
+    tree_location: Location,
+    start: usize,
+) -> Result<(Instance, Vec<Def>), Error> {
+    let (class, typ, defs) = match tree {
+        [_, TokenTree::Token(token, location), typ, defs @ ..] => (
+            Name {
+                token: token.clone(),
+                location: *location,
+            },
+            parse_type(typ)?,
+            defs,
+        ),
+        _ => return err!(expected_instance, tree_location),
+    };
+
+    let mut methods = Vec::new();
+    for def in defs {
+        let def = parse_def(def)?;
+        if def.export || !def.type_params.is_empty() {
+            return err!(expected_method_def, def.name_id.location);
+        }
+        methods.push(def);
+    }
+
+    let instance = Instance {
+        class,
+        typ,
+        defs: start..start + methods.len(),
+        location: tree_location,
+    };
+    Ok((instance, methods))
+}
+
+/// Parses `(name func expr)`, optionally preceded by the attributes `export`
+/// and `checked`. An attribute is only recognised when a name follows it, so a
+/// def may still be named like one. A generic def lists its type parameters
+/// after its name, as in `(name (T U) func expr)`, each of which may be
+/// constrained by classes, as in `(name ((T Num Ord)) func expr)`.
 fn parse_def(token_tree: &TokenTree) -> Result<Def, Error> {
     let (tree, location) = match token_tree {
         TokenTree::Token(token, location) => {
@@ -93,7 +552,33 @@ fn parse_def(token_tree: &TokenTree) -> Result<Def, Error> {
         TokenTree::Tree(tree, location) => (tree, *location),
     };
 
+    let mut export = false;
+    let mut checked = false;
+    let mut tree = &tree[..];
+    while let [TokenTree::Token(attribute, _), TokenTree::Token(..), ..] = tree {
+        match attribute.as_str() {
+            "export" => export = true,
+            "checked" => checked = true,
+            _ => break,
+        }
+        tree = &tree[1..];
+    }
+
     let name_id = parse_def_name(tree, location)?;
+    let mut type_params: Vec<TypeParam> = Vec::new();
+    if let [_, TokenTree::Tree(params, _), TokenTree::Tree(func, _), _, ..] = tree {
+        let names = params.iter().all(is_type_param);
+        if !params.is_empty() && names && !func.is_empty() {
+            for param in params {
+                let param = parse_type_param(param);
+                if type_params.iter().any(|p| p.name.token == param.name.token) {
+                    return err!(duplicate_type_param, param.name.location, param.name.token);
+                }
+                type_params.push(param);
+            }
+            tree = &tree[1..];
+        }
+    }
     let func = parse_def_func(tree, name_id.location)?;
     let expr = parse_def_expr(tree, func.location)?;
 
@@ -106,12 +591,66 @@ fn parse_def(token_tree: &TokenTree) -> Result<Def, Error> {
 
     Ok(Def {
         name_id,
+        type_params,
         func,
         expr,
+        export,
+        checked,
         location,
     })
 }
 
+/// Parses `(extern name func)`, which is a def without an expression.
+fn parse_extern(tree: &[TokenTree], location: Location) -> Result<Extern, Error> {
+    let tree = &tree[1..];
+    let name_id = parse_def_name(tree, location)?;
+    let func = parse_def_func(tree, name_id.location)?;
+
+    if let Some(token_tree) = tree.get(2) {
+        let location = match token_tree {
+            TokenTree::Tree(_, location) | TokenTree::Token(_, location) => location,
+        };
+        return err!(unexpected_extern_token, *location);
+    }
+
+    Ok(Extern {
+        name_id,
+        func,
+        location,
+    })
+}
+
+/// Whether a token tree is a type parameter: either a name, or a name followed
+/// by the classes it is constrained by.
+fn is_type_param(token_tree: &TokenTree) -> bool {
+    match token_tree {
+        TokenTree::Token(..) => true,
+        TokenTree::Tree(tree, _) => {
+            tree.len() > 1 && tree.iter().all(|t| matches!(t, TokenTree::Token(..)))
+        }
+    }
+}
+
+fn parse_type_param(token_tree: &TokenTree) -> TypeParam {
+    let name = |token_tree: &TokenTree| match token_tree {
+        TokenTree::Token(token, location) => Name {
+            token: token.clone(),
+            location: *location,
+        },
+        TokenTree::Tree(..) => panic!(),
+    };
+    match token_tree {
+        TokenTree::Token(..) => TypeParam {
+            name: name(token_tree),
+            classes: Vec::new(),
+        },
+        TokenTree::Tree(tree, _) => TypeParam {
+            name: name(&tree[0]),
+            classes: tree[1..].iter().map(name).collect(),
+        },
+    }
+}
+
 fn parse_def_name(tree: &[TokenTree], tree_location: Location) -> Result<NameId, Error> {
     let token_tree = tree
         .get(0)
@@ -122,6 +661,7 @@ fn parse_def_name(tree: &[TokenTree], tree_location: Location) -> Result<NameId,
         TokenTree::Token(token, location) => Ok(NameId {
             token: token.clone(),
             id: 0,
+            type_args: Vec::new(),
             location: *location,
         }),
     }
@@ -143,7 +683,7 @@ fn parse_def_expr(tree: &[TokenTree], func_location: Location) -> Result<Expr, E
         .get(2)
         .ok_or_else(|| error!(expected_func_expr, func_location))?;
 
-    Ok(parse_expr(token_tree))
+    parse_expr(token_tree)
 }
 
 fn parse_func(tree: &[TokenTree], tree_location: Location) -> Result<Func, Error> {
@@ -151,10 +691,11 @@ fn parse_func(tree: &[TokenTree], tree_location: Location) -> Result<Func, Error
         .split_last()
         .ok_or_else(|| error!(expected_type, tree_location))?;
 
-    let (ret_token, ret_location) = match ret {
-        TokenTree::Token(token, location) => (token, location),
-        TokenTree::Tree(_, location) => return err!(expected_func_ret_terminal_type, *location),
-    };
+    if let TokenTree::Tree(_, location) = ret {
+        if is_empty_tree(ret) {
+            return err!(expected_func_ret_terminal_type, *location);
+        }
+    }
 
     let mut func_params = Vec::new();
     for param in params {
@@ -163,21 +704,15 @@ fn parse_func(tree: &[TokenTree], tree_location: Location) -> Result<Func, Error
 
     Ok(Func {
         params: func_params,
-        ret: Type {
-            token: ret_token.clone(),
-            location: *ret_location,
-        },
+        ret: parse_value_type(ret)?,
         location: tree_location,
     })
 }
 
 fn parse_param(token_tree: &TokenTree) -> Result<Param, Error> {
     match token_tree {
-        TokenTree::Tree(tree, location) => parse_decl(tree, *location),
-        TokenTree::Token(token, location) => Ok(Param::Type(Type {
-            token: token.clone(),
-            location: *location,
-        })),
+        TokenTree::Tree(tree, location) if is_nested(token_tree) => parse_decl(tree, *location),
+        _ => Ok(Param::Type(parse_type(token_tree)?)),
     }
 }
 
@@ -199,13 +734,10 @@ fn parse_decl(tree: &[TokenTree], tree_location: usize) -> Result<Param, Error>
     };
 
     let typ = match typ {
-        TokenTree::Tree(_, location) => {
+        TokenTree::Tree(_, location) if is_empty_tree(typ) => {
             return err!(expected_param_type, *location);
         }
-        TokenTree::Token(token, location) => Type {
-            token: token.clone(),
-            location: *location,
-        },
+        _ => parse_value_type(typ)?,
     };
 
     Ok(Param::Decl(Decl {
@@ -215,22 +747,331 @@ fn parse_decl(tree: &[TokenTree], tree_location: usize) -> Result<Param, Error>
     }))
 }
 
-fn parse_expr(token_tree: &TokenTree) -> Expr {
+/// Whether a tree is nested without being one of the compound type forms.
+fn is_nested(token_tree: &TokenTree) -> bool {
     match token_tree {
-        TokenTree::Tree(tree, location) => {
-            let mut exprs = Vec::new();
-            for expr in tree {
-                exprs.push(parse_expr(expr));
-            }
-            Expr::Call(Call {
-                exprs,
+        TokenTree::Tree(tree, _) => {
+            !(is_keyword(tree, "ptr")
+                || is_keyword(tree, "array")
+                || is_keyword(tree, "slice")
+                || is_keyword(tree, "overflow"))
+        }
+        TokenTree::Token(..) => false,
+    }
+}
+
+const fn is_empty_tree(token_tree: &TokenTree) -> bool {
+    matches!(token_tree, TokenTree::Tree(tree, _) if tree.is_empty())
+}
+
+/// Parses the type of a parameter, return value, binding or field, which may
+/// be a function type.
+fn parse_value_type(token_tree: &TokenTree) -> Result<Type, Error> {
+    match token_tree {
+        TokenTree::Tree(tree, location) if is_nested(token_tree) => {
+            let (ret, params) = tree
+                .split_last()
+                .ok_or_else(|| error!(expected_type, *location))?;
+            let params = params
+                .iter()
+                .map(parse_value_type)
+                .collect::<Result<_, _>>()?;
+            Ok(Type::Func(
+                params,
+                Box::new(parse_value_type(ret)?),
+                *location,
+            ))
+        }
+        _ => parse_type(token_tree),
+    }
+}
+
+fn parse_type(token_tree: &TokenTree) -> Result<Type, Error> {
+    let (tree, location) = match token_tree {
+        TokenTree::Token(token, location) => {
+            return Ok(Type::Name(Name {
+                token: token.clone(),
                 location: *location,
-            })
+            }))
+        }
+        TokenTree::Tree(tree, location) => (tree, *location),
+    };
+
+    match &tree[..] {
+        [TokenTree::Token(keyword, _), typ] if keyword == "ptr" => {
+            Ok(Type::Ptr(Box::new(parse_type(typ)?), location))
+        }
+        [TokenTree::Token(keyword, _), typ] if keyword == "slice" => {
+            Ok(Type::Slice(Box::new(parse_type(typ)?), location))
+        }
+        [TokenTree::Token(keyword, _), typ] if keyword == "overflow" => {
+            Ok(Type::Overflow(Box::new(parse_type(typ)?), location))
+        }
+        [TokenTree::Token(keyword, _), typ, TokenTree::Token(len, len_location)]
+            if keyword == "array" =>
+        {
+            let len = len
+                .parse()
+                .map_err(|_| error!(expected_array_len, *len_location))?;
+            Ok(Type::Array(Box::new(parse_type(typ)?), len, location))
         }
-        TokenTree::Token(token, location) => Expr::Val(NameId {
+        _ => err!(expected_type_form, location),
+    }
+}
+
+fn parse_expr(token_tree: &TokenTree) -> Result<Expr, Error> {
+    match token_tree {
+        TokenTree::Tree(tree, location) => match tree.first() {
+            Some(TokenTree::Token(token, _)) if token == "if" => parse_if(tree, *location),
+            Some(TokenTree::Token(token, _)) if token == "let" => parse_let(tree, *location),
+            Some(TokenTree::Token(token, _)) if token == "set!" => parse_set(tree, *location),
+            Some(TokenTree::Token(token, _)) if token == "while" => parse_while(tree, *location),
+            Some(TokenTree::Token(token, _)) if token == "do" => parse_do(tree, *location),
+            Some(TokenTree::Token(token, _)) if token == "match" => parse_match(tree, *location),
+            Some(TokenTree::Token(token, _)) if token == "lambda" => parse_lambda(tree, *location),
+            Some(TokenTree::Token(token, _)) if token == "tail" => {
+                parse_call(&tree[1..], true, *location)
+            }
+            _ => parse_call(tree, false, *location),
+        },
+        TokenTree::Token(token, location)
+            if token.starts_with('"') && literalise::string(token).is_none() =>
+        {
+            err!(invalid_string, *location)
+        }
+        TokenTree::Token(token, location)
+            if token
+                .trim_start_matches('-')
+                .starts_with(|ch: char| ch.is_ascii_digit())
+                && literalise::number(token).is_none() =>
+        {
+            err!(invalid_number, *location, token)
+        }
+        TokenTree::Token(token, location) => Ok(Expr::Val(NameId {
             token: token.clone(),
             id: 0,
+            type_args: Vec::new(),
             location: *location,
-        }),
+        })),
+    }
+}
+
+fn parse_call(tree: &[TokenTree], tail: bool, tree_location: Location) -> Result<Expr, Error> {
+    if tail && tree.is_empty() {
+        return err!(expected_tail_call, tree_location);
+    }
+
+    let mut exprs = Vec::new();
+    for expr in tree {
+        exprs.push(parse_expr(expr)?);
+    }
+
+    Ok(Expr::Call(Call {
+        exprs,
+        tail,
+        location: tree_location,
+    }))
+}
+
+fn parse_if(tree: &[TokenTree], tree_location: Location) -> Result<Expr, Error> {
+    let [_, cond, then, els] = tree else {
+        return err!(expected_if, tree_location);
+    };
+
+    Ok(Expr::If(If {
+        cond: Box::new(parse_expr(cond)?),
+        then: Box::new(parse_expr(then)?),
+        els: Box::new(parse_expr(els)?),
+        location: tree_location,
+    }))
+}
+
+fn parse_lambda(tree: &[TokenTree], tree_location: Location) -> Result<Expr, Error> {
+    let [_, TokenTree::Tree(func_tree, func_location), body] = tree else {
+        return err!(expected_lambda, tree_location);
+    };
+
+    Ok(Expr::Lambda(Lambda {
+        func: parse_func(func_tree, *func_location)?,
+        body: Box::new(parse_expr(body)?),
+        location: tree_location,
+    }))
+}
+
+fn parse_let(tree: &[TokenTree], tree_location: Location) -> Result<Expr, Error> {
+    let [_, TokenTree::Tree(bindings_tree, _), body] = tree else {
+        return err!(expected_let, tree_location);
+    };
+
+    let mut bindings: Vec<Binding> = Vec::new();
+    for binding in bindings_tree {
+        let binding = parse_binding(binding)?;
+        if bindings.iter().any(|b| b.name.token == binding.name.token) {
+            return err!(duplicate_binding, binding.name.location, binding.name.token);
+        }
+        bindings.push(binding);
+    }
+
+    Ok(Expr::Let(Let {
+        bindings,
+        body: Box::new(parse_expr(body)?),
+        location: tree_location,
+    }))
+}
+
+fn parse_binding(token_tree: &TokenTree) -> Result<Binding, Error> {
+    let (name, typ, expr, mutable, location) = match token_tree {
+        TokenTree::Tree(tree, location) => match &tree[..] {
+            [name, typ, expr] => (name, typ, expr, false, *location),
+            [TokenTree::Token(token, _), name, typ, expr] if token == "mut" => {
+                (name, typ, expr, true, *location)
+            }
+            _ => return err!(expected_binding, *location),
+        },
+        TokenTree::Token(_, location) => return err!(expected_binding, *location),
+    };
+
+    let name = match name {
+        TokenTree::Tree(_, location) => {
+            return err!(expected_binding_name, *location);
+        }
+        TokenTree::Token(token, location) => Name {
+            token: token.clone(),
+            location: *location,
+        },
+    };
+
+    let typ = match typ {
+        TokenTree::Tree(_, location) if is_empty_tree(typ) => {
+            return err!(expected_binding_type, *location);
+        }
+        _ => parse_value_type(typ)?,
+    };
+
+    Ok(Binding {
+        name,
+        typ,
+        expr: parse_expr(expr)?,
+        mutable,
+        location,
+    })
+}
+
+fn parse_set(tree: &[TokenTree], tree_location: Location) -> Result<Expr, Error> {
+    let (name, expr) = match tree {
+        [_, TokenTree::Token(token, location), expr] => (
+            Name {
+                token: token.clone(),
+                location: *location,
+            },
+            expr,
+        ),
+        _ => return err!(expected_set, tree_location),
+    };
+
+    Ok(Expr::Set(Set {
+        name,
+        expr: Box::new(parse_expr(expr)?),
+        location: tree_location,
+    }))
+}
+
+fn parse_while(tree: &[TokenTree], tree_location: Location) -> Result<Expr, Error> {
+    let [_, cond, body] = tree else {
+        return err!(expected_while, tree_location);
+    };
+
+    Ok(Expr::While(While {
+        cond: Box::new(parse_expr(cond)?),
+        body: Box::new(parse_expr(body)?),
+        location: tree_location,
+    }))
+}
+
+fn parse_do(tree: &[TokenTree], tree_location: Location) -> Result<Expr, Error> {
+    let mut exprs = Vec::new();
+    for expr in &tree[1..] {
+        exprs.push(parse_expr(expr)?);
+    }
+
+    if exprs.is_empty() {
+        return err!(expected_do_expr, tree_location);
     }
+
+    Ok(Expr::Do(Do {
+        exprs,
+        location: tree_location,
+    }))
+}
+
+fn parse_match(tree: &[TokenTree], tree_location: Location) -> Result<Expr, Error> {
+    let (expr, arms) = match tree {
+        [_, expr, arms @ ..] if !arms.is_empty() => (expr, arms),
+        _ => return err!(expected_match, tree_location),
+    };
+
+    let mut match_arms = Vec::new();
+    for arm in arms {
+        match_arms.push(parse_arm(arm)?);
+    }
+
+    Ok(Expr::Match(Match {
+        expr: Box::new(parse_expr(expr)?),
+        arms: match_arms,
+        location: tree_location,
+    }))
+}
+
+fn parse_arm(token_tree: &TokenTree) -> Result<Arm, Error> {
+    let (pattern, body, location) = match token_tree {
+        TokenTree::Tree(tree, location) => match &tree[..] {
+            [pattern, body] => (pattern, body, *location),
+            _ => return err!(expected_arm, *location),
+        },
+        TokenTree::Token(_, location) => return err!(expected_arm, *location),
+    };
+
+    Ok(Arm {
+        pattern: parse_pattern(pattern)?,
+        body: parse_expr(body)?,
+        location,
+    })
+}
+
+fn parse_pattern(token_tree: &TokenTree) -> Result<Pattern, Error> {
+    let (name, names) = match token_tree {
+        TokenTree::Token(token, _) if token == "_" => return Ok(Pattern::Wildcard),
+        TokenTree::Token(token, location) => ((token, *location), &[][..]),
+        TokenTree::Tree(tree, location) => match &tree[..] {
+            [TokenTree::Token(token, name_location), names @ ..] => {
+                ((token, *name_location), names)
+            }
+            _ => return err!(expected_pattern, *location),
+        },
+    };
+
+    let mut bindings: Vec<Name> = Vec::new();
+    for name in names {
+        let name = match name {
+            TokenTree::Token(token, location) => Name {
+                token: token.clone(),
+                location: *location,
+            },
+            TokenTree::Tree(_, location) => return err!(expected_pattern, *location),
+        };
+        if name.token != "_" && bindings.iter().any(|b| b.token == name.token) {
+            return err!(duplicate_binding, name.location, name.token);
+        }
+        bindings.push(name);
+    }
+
+    Ok(Pattern::Variant(
+        NameId {
+            token: name.0.clone(),
+            id: 0,
+            type_args: Vec::new(),
+            location: name.1,
+        },
+        bindings,
+    ))
 }
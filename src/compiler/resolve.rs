@@ -1,61 +1,924 @@
 use super::common::Location;
 use super::error::Error;
 use super::namespace::Namespace;
+use super::parse;
+use super::parse::Call;
 use super::parse::Decl;
+use super::parse::Def;
+use super::parse::Do;
+use super::parse::Enum;
+use super::parse::Expr;
+use super::parse::If;
+use super::parse::Lambda;
+use super::parse::Let;
+use super::parse::Match;
+use super::parse::NameId;
 use super::parse::Param;
 use super::parse::Program;
+use super::parse::Set;
+use super::parse::Struct;
+use super::parse::While;
+use super::symbol::Class;
 use super::symbol::Func;
+use super::symbol::Intrinsic;
 use super::symbol::Symbol;
 use super::symbol::Terminal;
 use super::symbol::Type;
+use super::symbol::Variant;
+use super::symbol::ANY;
+use super::symbol::INT;
 use std::collections::HashMap;
+use std::slice;
 
 pub fn resolve(
     mut program: Program,
     mut namespace: Namespace,
 ) -> Result<(Program, Namespace), Error> {
+    resolve_types(&program.structs, &program.enums, &mut namespace)?;
+    resolve_classes(&program.classes, &mut namespace)?;
+
     for def in &mut program.defs {
-        let mut def_namespace = HashMap::new();
+        let type_params = type_param_names(def);
+        let (func, def_namespace) = resolve_func(&def.func, &type_params, &namespace)?;
+        let symbol = if type_params.is_empty() {
+            Symbol::Var(Type::Func(func))
+        } else {
+            Symbol::Generic(func, type_params, resolve_constraints(def, &namespace)?)
+        };
+        let def_namespace =
+            Namespace::from((symbol, def_namespace)).with_location(def.name_id.location);
+        let id = namespace.append_namespace(&def.name_id.token, def_namespace);
+        def.name_id.id = id;
+    }
+
+    // An extern is emitted under its exact name, so it cannot be overloaded.
+    // Builtins are never emitted under theirs, so a C function may share a
+    // name with one, such as `abs`.
+    for ext in &mut program.externs {
+        let name_id = &mut ext.name_id;
+        let defined = namespace.get(&name_id.token).is_some_and(|namespaces| {
+            !is_c_identifier(&name_id.token) || namespaces.iter().any(|n| !n.is_builtin())
+        });
+        if defined {
+            return err!(duplicate_extern, name_id.location, &name_id.token);
+        }
+        let (func, _) = resolve_func(&ext.func, &[], &namespace)?;
+        let extern_namespace =
+            Namespace::from(Symbol::Extern(func)).with_location(name_id.location);
+        let id = namespace.append_namespace(&name_id.token, extern_namespace);
+        name_id.id = id;
+    }
+
+    resolve_runtime(&program, &namespace)?;
+    resolve_instances(&program, &mut namespace)?;
+
+    for def in program.defs.iter().filter(|def| def.export) {
+        resolve_export(def, &namespace)?;
+    }
+
+    resolve_intrinsics(&program, &mut namespace)?;
+    resolve_overloads(&mut program, &namespace)?;
+
+    Ok((program, namespace))
+}
+
+/// Checks the functions named after a C function the compiler calls. A def
+/// is emitted under its name, so it cannot take one, and an extern is declared
+/// in its place, so it must have the same type once lowered.
+fn resolve_runtime(program: &Program, namespace: &Namespace) -> Result<(), Error> {
+    for def in &program.defs {
+        let name_id = &def.name_id;
+        if runtime_func(&name_id.token).is_some() {
+            return err!(runtime_name, name_id.location, &name_id.token);
+        }
+    }
+    for ext in &program.externs {
+        let name_id = &ext.name_id;
+        let Some(func) = runtime_func(&name_id.token) else {
+            continue;
+        };
+        let symbol = namespace.get(&name_id.token).unwrap()[name_id.id].symbol();
+        let ext_func = symbol.func().unwrap();
+        if Type::Code(ext_func.clone()).to_string() != Type::Code(func).to_string() {
+            return err!(runtime_extern, name_id.location, &name_id.token);
+        }
+    }
+    Ok(())
+}
+
+/// The type of a C function the generated code calls, which is declared
+/// unless the program declares it itself.
+pub fn runtime_func(token: &str) -> Option<Func> {
+    let i8_ptr = Type::Ptr(Box::new(Type::Terminal(Terminal::I8)));
+    match token {
+        "write" => Some(Func {
+            params: vec![
+                Type::Terminal(Terminal::I32),
+                i8_ptr,
+                Type::Terminal(Terminal::I64),
+            ],
+            ret: Box::new(Type::Terminal(Terminal::I64)),
+        }),
+        "malloc" => Some(Func {
+            params: vec![Type::Terminal(Terminal::I64)],
+            ret: Box::new(i8_ptr),
+        }),
+        _ => None,
+    }
+}
+
+/// Checks each def against the other functions of its name, builtins
+/// included, and each extern against the builtins of its name. Calls could
+/// never tell a def from one of the same type, so that is an error. One taking
+/// the same parameters can only be told apart by the type expected of a call,
+/// which is easy to get wrong, so that is a warning.
+fn resolve_overloads(program: &mut Program, namespace: &Namespace) -> Result<(), Error> {
+    for def in &program.defs {
+        let NameId {
+            token,
+            id,
+            location,
+            ..
+        } = &def.name_id;
+        let namespaces = namespace.get(token).unwrap();
+        let (func, constraints) = overload_type(namespaces[*id].symbol()).unwrap();
 
-        let mut params = Vec::new();
-        for param in &def.func.params {
-            let (name_token, typ) = match param {
-                Param::Decl(Decl { name, typ, .. }) => (Some(name.token.clone()), typ),
-                Param::Type(typ) => (None, typ),
+        // Defs after this one are checked against it in turn. Intrinsics are
+        // registered after every def, so they are only checked here.
+        let others = namespaces.iter().enumerate().filter(|(other_id, other)| {
+            other_id < id || matches!(other.symbol(), Symbol::Intrinsic(..))
+        });
+        for (_, other) in others {
+            let Some((other_func, other_constraints)) = overload_type(other.symbol()) else {
+                continue;
             };
+            let note = definition_note(token, other);
+            if other_func == func && other_constraints == constraints {
+                let error = error!(duplicate_overload, *location, token);
+                return Err(error.with_note(other.location(), note));
+            }
+            if other_func.params == func.params {
+                let warning = error!(similar_overload, *location, token);
+                let warning = warning.with_note(other.location(), note).into_warning();
+                program.warnings.push(warning);
+            }
+        }
+    }
+
+    // An extern can only share its name with builtins. One of the same type
+    // is called in place of the builtin, so rather than being an error, that
+    // is warned about too.
+    for ext in &program.externs {
+        let NameId {
+            token,
+            id,
+            location,
+            ..
+        } = &ext.name_id;
+        let namespaces = namespace.get(token).unwrap();
+        let func = namespaces[*id].symbol().func().unwrap();
+        for other in namespaces.iter().filter(|other| other.is_builtin()) {
+            let Some((other_func, _)) = overload_type(other.symbol()) else {
+                continue;
+            };
+            let warning = if other_func == *func {
+                error!(shadowed_builtin, *location, token)
+            } else if other_func.params == func.params {
+                error!(similar_overload, *location, token)
+            } else {
+                continue;
+            };
+            let note = definition_note(token, other);
+            let warning = warning.with_note(other.location(), note).into_warning();
+            program.warnings.push(warning);
+        }
+    }
+    Ok(())
+}
 
-            let param_type = get_terminal(&typ.token, typ.location, &namespace)?;
-            params.push(param_type);
+/// The type of a function as far as telling it apart from others of its name
+/// goes, with a generic def's type parameters renamed by position. A method
+/// stands in for the defs of its instances, so it has none.
+fn overload_type(symbol: &Symbol) -> Option<(Func, Vec<(String, String)>)> {
+    match symbol {
+        Symbol::Method(..) => None,
+        Symbol::Generic(func, type_params, constraints) => {
+            let positions: Vec<_> = (0..type_params.len())
+                .map(|i| Type::Param(i.to_string()))
+                .collect();
+            let position = |param| type_params.iter().position(|p| p == param).unwrap();
+            let mut constraints: Vec<_> = constraints
+                .iter()
+                .map(|(param, class)| (position(param).to_string(), class.clone()))
+                .collect();
+            constraints.sort();
+            Some((func.substitute(type_params, &positions), constraints))
+        }
+        symbol => symbol.func().map(|func| (func.clone(), Vec::new())),
+    }
+}
+
+/// Describes a function another one is reported against.
+pub fn definition_note(token: &str, namespace: &Namespace) -> String {
+    let typ = Type::Func(namespace.symbol().func().unwrap().clone());
+    if namespace.location().is_some() {
+        format!("`{token}` of type `{}` is defined here.", typ.syntax())
+    } else {
+        format!("`{token}` of type `{}` is a builtin.", typ.syntax())
+    }
+}
+
+pub fn type_param_names(def: &Def) -> Vec<String> {
+    def.type_params
+        .iter()
+        .map(|param| param.name.token.clone())
+        .collect()
+}
+
+fn resolve_constraints(def: &Def, namespace: &Namespace) -> Result<Vec<(String, String)>, Error> {
+    let mut constraints = Vec::new();
+    for param in &def.type_params {
+        for class in &param.classes {
+            get_class(class, namespace)?;
+            constraints.push((param.name.token.clone(), class.token.clone()));
+        }
+    }
+    Ok(constraints)
+}
 
-            if let Some(name) = name_token {
-                let symbol = Symbol::Var(Type::Terminal(param_type));
-                def_namespace.insert(name, vec![Namespace::from(symbol)]);
+fn get_class<'a>(name: &parse::Name, namespace: &'a Namespace) -> Result<&'a Class, Error> {
+    match namespace
+        .get(&name.token)
+        .map(|namespaces| namespaces[0].symbol())
+    {
+        Some(Symbol::Class(class)) => Ok(class),
+        _ => err!(expected_class, name.location, &name.token),
+    }
+}
+
+/// Registers each class, and each of its methods ahead of any def sharing the
+/// method's name. The methods' types refer to the class's type parameter by
+/// the class's name.
+fn resolve_classes(classes: &[parse::Class], namespace: &mut Namespace) -> Result<(), Error> {
+    for class in classes {
+        let name = &class.name.token;
+        if namespace.get(name).is_some() {
+            return err!(duplicate_class, class.name.location, name);
+        }
+
+        let mut methods = Vec::new();
+        for method in &class.methods {
+            let typ =
+                get_generic_type(&method.typ, slice::from_ref(&class.param.token), namespace)?;
+            let Type::Func(func) = typ.substitute(
+                slice::from_ref(&class.param.token),
+                &[Type::Param(name.clone())],
+            ) else {
+                panic!()
+            };
+            let symbol = Symbol::Method(func.clone(), name.clone());
+            let method_namespace = Namespace::from(symbol).with_location(method.name.location);
+            namespace.append_namespace(&method.name.token, method_namespace);
+            methods.push((method.name.token.clone(), func));
+        }
+
+        let class = Class {
+            methods,
+            instances: Vec::new(),
+        };
+        namespace.append_namespace(name, Namespace::from(Symbol::Class(class)));
+    }
+    Ok(())
+}
+
+/// Checks that the defs of each instance are exactly the methods of its class,
+/// typed for the instance, and then adds the instance to the class.
+fn resolve_instances(program: &Program, namespace: &mut Namespace) -> Result<(), Error> {
+    for instance in &program.instances {
+        let typ = get_type(&instance.typ, namespace)?;
+        let class_name = &instance.class.token;
+        let class = get_class(&instance.class, namespace)?;
+        if class.instances.contains(&typ) {
+            return err!(duplicate_instance, instance.typ.location(), typ, class_name);
+        }
+
+        let methods: Vec<_> = class
+            .methods
+            .iter()
+            .map(|(method, func)| {
+                let func = func.substitute(slice::from_ref(class_name), slice::from_ref(&typ));
+                (method, func)
+            })
+            .collect();
+        for def in &program.defs[instance.defs.clone()] {
+            let name_id = &def.name_id;
+            let symbol = namespace
+                .get_then(&name_id.token, name_id.id)
+                .unwrap()
+                .symbol();
+            match methods.iter().find(|(method, _)| **method == name_id.token) {
+                None => {
+                    return err!(
+                        unexpected_method,
+                        name_id.location,
+                        &name_id.token,
+                        class_name
+                    )
+                }
+                Some((method, func)) if *symbol != Symbol::Var(Type::Func(func.clone())) => {
+                    return err!(
+                        missing_method,
+                        name_id.location,
+                        method,
+                        Type::Func(func.clone())
+                    );
+                }
+                Some(_) => (),
+            }
+        }
+        for (method, func) in &methods {
+            let defined = program.defs[instance.defs.clone()]
+                .iter()
+                .any(|def| def.name_id.token == **method);
+            if !defined {
+                return err!(
+                    missing_method,
+                    instance.location,
+                    method,
+                    Type::Func(func.clone())
+                );
             }
         }
 
-        let ret = get_terminal(&def.func.ret.token, def.func.ret.location, &namespace)?;
+        let class = namespace.get_mut(class_name).unwrap()[0].symbol_mut();
+        if let Symbol::Class(class) = class {
+            class.instances.push(typ);
+        }
+    }
+    Ok(())
+}
 
-        let symbol = Symbol::Var(Type::Func(Func { params, ret }));
-        let def_namespace = Namespace::from((symbol, def_namespace));
-        let id = namespace.append_namespace(&def.name_id.token, def_namespace);
-        def.name_id.id = id;
+/// Resolves the type of a def, extern or lambda, along with a namespace
+/// holding its named parameters. The type may mention `type_params`.
+pub fn resolve_func(
+    func: &parse::Func,
+    type_params: &[String],
+    namespace: &Namespace,
+) -> Result<(Func, HashMap<String, Vec<Namespace>>), Error> {
+    let mut func_namespace = HashMap::new();
+
+    let mut params = Vec::new();
+    for param in &func.params {
+        let (name_token, typ) = match param {
+            Param::Decl(Decl { name, typ, .. }) => (Some(name.token.clone()), typ),
+            Param::Type(typ) => (None, typ),
+        };
+
+        let param_type = get_generic_type(typ, type_params, namespace)?;
+        params.push(param_type.clone());
+
+        if let Some(name) = name_token {
+            let symbol = Symbol::Var(param_type);
+            func_namespace.insert(name, vec![Namespace::from(symbol)]);
+        }
     }
 
-    Ok((program, namespace))
+    let ret = get_generic_type(&func.ret, type_params, namespace)?;
+
+    let func = Func {
+        params,
+        ret: Box::new(ret),
+    };
+    Ok((func, func_namespace))
+}
+
+/// Checks that an exported def can be called from C: its name is used as is,
+/// so it must be a C identifier and cannot be overloaded, and its types must
+/// have a C equivalent.
+fn resolve_export(def: &Def, namespace: &Namespace) -> Result<(), Error> {
+    let name_id = &def.name_id;
+    if !is_c_identifier(&name_id.token) {
+        return err!(invalid_export_name, name_id.location, &name_id.token);
+    }
+
+    if namespace.get(&name_id.token).map_or(0, Vec::len) > 1 {
+        return err!(overloaded_export, name_id.location, &name_id.token);
+    }
+
+    if !def.type_params.is_empty() {
+        return err!(generic_export, name_id.location, &name_id.token);
+    }
+
+    let params = def.func.params.iter().map(|param| match param {
+        Param::Decl(Decl { typ, .. }) | Param::Type(typ) => typ,
+    });
+    for typ in params.chain(std::iter::once(&def.func.ret)) {
+        if !is_c_type(&get_type(typ, namespace)?) {
+            return err!(unexported_type, typ.location());
+        }
+    }
+    Ok(())
+}
+
+/// Whether a type can be passed to or from C as is. Aggregates are left out,
+/// since C passes them differently from LLVM, but pointers to them are fine.
+fn is_c_identifier(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+fn is_c_type(typ: &Type) -> bool {
+    match typ {
+        Type::Terminal(_) => true,
+        Type::Ptr(elem) => match &**elem {
+            Type::Struct(_) | Type::Enum(_) => true,
+            elem => is_c_type(elem),
+        },
+        _ => false,
+    }
+}
+
+/// Registers each struct and enum as a type, then the struct's constructor
+/// under the same name and an accessor named `.field` for every field, and a
+/// constructor for every variant. All type names are registered first so that
+/// fields can refer to types defined later.
+fn resolve_types(
+    structs: &[Struct],
+    enums: &[Enum],
+    namespace: &mut Namespace,
+) -> Result<(), Error> {
+    let names = structs
+        .iter()
+        .map(|s| (&s.name, Type::Struct(s.name.token.clone())))
+        .chain(
+            enums
+                .iter()
+                .map(|e| (&e.name, Type::Enum(e.name.token.clone()))),
+        );
+    for (name, typ) in names {
+        if namespace.get(&name.token).is_some() {
+            return err!(duplicate_type, name.location, &name.token);
+        }
+        namespace.append_namespace(&name.token, Namespace::from(Symbol::Type(typ)));
+    }
+
+    let mut fields = HashMap::new();
+    for s in structs {
+        let mut field_types = Vec::new();
+        for field in &s.fields {
+            field_types.push(get_field_type(&field.typ, field.location, namespace)?);
+        }
+        fields.insert(s.name.token.as_str(), field_types);
+    }
+
+    let mut variants = HashMap::new();
+    for e in enums {
+        let mut variant_types = Vec::new();
+        for variant in &e.variants {
+            let mut field_types = Vec::new();
+            for field in &variant.fields {
+                field_types.push(get_field_type(field, field.location(), namespace)?);
+            }
+            variant_types.push(field_types);
+        }
+        fields.insert(e.name.token.as_str(), variant_types.concat());
+        variants.insert(e.name.token.as_str(), variant_types);
+    }
+
+    for name in structs
+        .iter()
+        .map(|s| &s.name)
+        .chain(enums.iter().map(|e| &e.name))
+    {
+        if contains_type(&fields[name.token.as_str()], &name.token, &fields) {
+            return err!(recursive_type, name.location, &name.token);
+        }
+    }
+
+    for s in structs {
+        let typ = Type::Struct(s.name.token.clone());
+        let field_types = &fields[s.name.token.as_str()];
+
+        let constructor = Symbol::Constructor(Func {
+            params: field_types.clone(),
+            ret: Box::new(typ.clone()),
+        });
+        let constructor = Namespace::from(constructor).with_location(s.name.location);
+        namespace.append_namespace(&s.name.token, constructor);
+
+        for (index, (field, field_type)) in s.fields.iter().zip(field_types).enumerate() {
+            let accessor = Symbol::Field(
+                Func {
+                    params: vec![typ.clone()],
+                    ret: Box::new(field_type.clone()),
+                },
+                index,
+            );
+            let key = format!(".{}", field.name.token);
+            let accessor = Namespace::from(accessor).with_location(field.name.location);
+            namespace.append_namespace(&key, accessor);
+        }
+    }
+
+    for e in enums {
+        let typ = Type::Enum(e.name.token.clone());
+
+        let mut enum_namespace = HashMap::new();
+        for (tag, (variant, field_types)) in e
+            .variants
+            .iter()
+            .zip(&variants[e.name.token.as_str()])
+            .enumerate()
+        {
+            let func = Func {
+                params: field_types.clone(),
+                ret: Box::new(typ.clone()),
+            };
+            let constructor = || Symbol::Variant(func.clone(), Variant { tag });
+            let variant_namespace =
+                Namespace::from(constructor()).with_location(variant.name.location);
+            namespace.append_namespace(&variant.name.token, variant_namespace);
+            enum_namespace.insert(
+                variant.name.token.clone(),
+                vec![Namespace::from(constructor())],
+            );
+        }
+
+        // Keep the variants under the enum's type too, so that `match` can
+        // tell which of them an arm leaves out.
+        let symbol = Symbol::Type(typ);
+        namespace.insert_namespaces(
+            e.name.token.clone(),
+            vec![Namespace::from((symbol, enum_namespace))],
+        );
+    }
+
+    Ok(())
 }
 
-fn get_terminal(
-    type_token: &str,
+fn resolve_intrinsics(program: &Program, namespace: &mut Namespace) -> Result<(), Error> {
+    // The types of a generic def may mention its type parameters. Their
+    // intrinsics are registered too, so that its body can use them.
+    let type_params: Vec<_> = program.defs.iter().map(type_param_names).collect();
+    let none = Vec::new();
+
+    let mut types = Vec::new();
+    for s in &program.structs {
+        types.extend(s.fields.iter().map(|field| (&field.typ, &none)));
+    }
+    for e in &program.enums {
+        let fields = e.variants.iter().flat_map(|variant| &variant.fields);
+        types.extend(fields.map(|typ| (typ, &none)));
+    }
+    for (def, type_params) in program.defs.iter().zip(&type_params) {
+        let mut def_types = Vec::new();
+        func_types(&def.func, &mut def_types);
+        types.extend(def_types.into_iter().map(|typ| (typ, type_params)));
+    }
+    for ext in &program.externs {
+        let mut ext_types = Vec::new();
+        func_types(&ext.func, &mut ext_types);
+        types.extend(ext_types.into_iter().map(|typ| (typ, &none)));
+    }
+    for (def, type_params) in program.defs.iter().zip(&type_params) {
+        let mut def_types = Vec::new();
+        binding_types(&def.expr, &mut def_types);
+        types.extend(def_types.into_iter().map(|typ| (typ, type_params)));
+    }
+
+    let mut registered = Vec::new();
+    let mut elem_types: Vec<_> = ANY.iter().copied().map(Type::Terminal).collect();
+    elem_types.extend(
+        program
+            .structs
+            .iter()
+            .map(|s| Type::Struct(s.name.token.clone())),
+    );
+    elem_types.extend(
+        program
+            .enums
+            .iter()
+            .map(|e| Type::Enum(e.name.token.clone())),
+    );
+    for (typ, type_params) in types {
+        let typ = get_generic_type(typ, type_params, namespace)?;
+        register_intrinsics(&typ, namespace, &mut registered);
+        if !typ.is_generic() && !elem_types.contains(&typ) {
+            elem_types.push(typ);
+        }
+    }
+
+    // An `array` call can build an array whose type the program never names,
+    // as in `(len (array 1 2 3))`. Its elements may be of any type that is
+    // named, or of a generic def's type parameters inside it.
+    for (def, type_params) in program.defs.iter().zip(&type_params) {
+        let mut def_elem_types = elem_types.clone();
+        def_elem_types.extend(type_params.iter().cloned().map(Type::Param));
+        let mut array_types = Vec::new();
+        literal_array_types(&def.expr, &def_elem_types, &mut array_types);
+        for typ in array_types {
+            register_intrinsics(&typ, namespace, &mut registered);
+        }
+    }
+
+    // String literals can be used as byte pointers and slices without the
+    // program naming either type.
+    let has_strings = namespace
+        .iter()
+        .any(|(_, namespaces)| matches!(namespaces[0].symbol(), Symbol::Literal(Type::Ptr(_))));
+    if has_strings {
+        let byte = Box::new(Type::Terminal(Terminal::U8));
+        register_intrinsics(&Type::Ptr(byte), namespace, &mut registered);
+    }
+    Ok(())
+}
+
+fn get_field_type(
+    typ: &parse::Type,
     location: Location,
     namespace: &Namespace,
-) -> Result<Terminal, Error> {
-    let namespaces = namespace
-        .get(type_token)
-        .ok_or_else(|| error!(expected_defined_type, location, type_token))?;
+) -> Result<Type, Error> {
+    let field_type = get_type(typ, namespace)?;
+    if field_type == Type::Terminal(Terminal::Void) {
+        return err!(unexpected_void_field, location);
+    }
+    Ok(field_type)
+}
 
-    if let Some(Symbol::Type(Type::Terminal(terminal))) = namespaces.get(0).map(Namespace::symbol) {
-        Ok(*terminal)
-    } else {
-        err!(expected_terminal_type, location)
+fn contains_type(types: &[Type], name: &str, fields: &HashMap<&str, Vec<Type>>) -> bool {
+    types.iter().any(|typ| match typ {
+        Type::Struct(field_name) | Type::Enum(field_name) => {
+            field_name == name || contains_type(&fields[field_name.as_str()], name, fields)
+        }
+        Type::Array(elem, _) => contains_type(std::slice::from_ref(elem), name, fields),
+        _ => false,
+    })
+}
+
+pub fn get_type(typ: &parse::Type, namespace: &Namespace) -> Result<Type, Error> {
+    get_generic_type(typ, &[], namespace)
+}
+
+/// Resolves a type that may mention `type_params`, which hide any type of the
+/// same name.
+pub fn get_generic_type(
+    typ: &parse::Type,
+    type_params: &[String],
+    namespace: &Namespace,
+) -> Result<Type, Error> {
+    let get_type = |typ| get_generic_type(typ, type_params, namespace);
+    let get_elem_type = |elem, location| {
+        let elem = get_type(elem)?;
+        if elem == Type::Terminal(Terminal::Void) {
+            return err!(unexpected_void_element, location);
+        }
+        Ok(elem)
+    };
+
+    match typ {
+        parse::Type::Name(name) if type_params.contains(&name.token) => {
+            Ok(Type::Param(name.token.clone()))
+        }
+        parse::Type::Name(name) => {
+            let namespaces = namespace
+                .get(&name.token)
+                .ok_or_else(|| error!(expected_defined_type, name.location, &name.token))?;
+
+            if let Some(Symbol::Type(typ)) = namespaces.get(0).map(Namespace::symbol) {
+                Ok(typ.clone())
+            } else {
+                err!(expected_terminal_type, name.location)
+            }
+        }
+        parse::Type::Ptr(elem, _) => Ok(Type::Ptr(Box::new(get_type(elem)?))),
+        parse::Type::Array(elem, len, location) => {
+            Ok(Type::Array(Box::new(get_elem_type(elem, *location)?), *len))
+        }
+        parse::Type::Slice(elem, location) => {
+            Ok(Type::Slice(Box::new(get_elem_type(elem, *location)?)))
+        }
+        parse::Type::Func(params, ret, _) => Ok(Type::Func(Func {
+            params: params.iter().map(get_type).collect::<Result<_, _>>()?,
+            ret: Box::new(get_type(ret)?),
+        })),
+        parse::Type::Overflow(elem, location) => match get_type(elem)? {
+            Type::Terminal(terminal) if INT.contains(&terminal) => Ok(Type::Overflow(terminal)),
+            _ => err!(expected_overflow_int, *location),
+        },
+    }
+}
+
+fn func_types<'a>(func: &'a parse::Func, types: &mut Vec<&'a parse::Type>) {
+    for param in &func.params {
+        types.push(match param {
+            Param::Decl(Decl { typ, .. }) | Param::Type(typ) => typ,
+        });
+    }
+    types.push(&func.ret);
+}
+
+/// Collects the types named by every `let` binding and lambda in an
+/// expression.
+fn binding_types<'a>(expr: &'a Expr, types: &mut Vec<&'a parse::Type>) {
+    match expr {
+        Expr::Val(_) => (),
+        Expr::Call(Call { exprs, .. }) | Expr::Do(Do { exprs, .. }) => {
+            for expr in exprs {
+                binding_types(expr, types);
+            }
+        }
+        Expr::If(If {
+            cond, then, els, ..
+        }) => {
+            binding_types(cond, types);
+            binding_types(then, types);
+            binding_types(els, types);
+        }
+        Expr::Let(Let { bindings, body, .. }) => {
+            for binding in bindings {
+                types.push(&binding.typ);
+                binding_types(&binding.expr, types);
+            }
+            binding_types(body, types);
+        }
+        Expr::Set(Set { expr, .. }) => binding_types(expr, types),
+        Expr::While(While { cond, body, .. }) => {
+            binding_types(cond, types);
+            binding_types(body, types);
+        }
+        Expr::Match(Match { expr, arms, .. }) => {
+            binding_types(expr, types);
+            for arm in arms {
+                binding_types(&arm.body, types);
+            }
+        }
+        Expr::Lambda(Lambda { func, body, .. }) => {
+            func_types(func, types);
+            binding_types(body, types);
+        }
+    }
+}
+
+/// Collects the types each `array` call in an expression may build.
+fn literal_array_types(expr: &Expr, elem_types: &[Type], types: &mut Vec<Type>) {
+    if let Some(elems) = array_call(expr) {
+        types.extend(array_call_types(elems, elem_types));
+    }
+    let children: Vec<&Expr> = match expr {
+        Expr::Val(_) => Vec::new(),
+        Expr::Call(Call { exprs, .. }) | Expr::Do(Do { exprs, .. }) => exprs.iter().collect(),
+        Expr::If(If {
+            cond, then, els, ..
+        }) => vec![cond, then, els],
+        Expr::Let(Let { bindings, body, .. }) => bindings
+            .iter()
+            .map(|binding| &binding.expr)
+            .chain(std::iter::once(&**body))
+            .collect(),
+        Expr::Set(Set { expr, .. }) => vec![expr],
+        Expr::While(While { cond, body, .. }) => vec![cond, body],
+        Expr::Match(Match { expr, arms, .. }) => std::iter::once(&**expr)
+            .chain(arms.iter().map(|arm| &arm.body))
+            .collect(),
+        Expr::Lambda(Lambda { body, .. }) => vec![body],
+    };
+    for child in children {
+        literal_array_types(child, elem_types, types);
+    }
+}
+
+/// The elements of an `array` call, if the expression is one.
+fn array_call(expr: &Expr) -> Option<&[Expr]> {
+    match expr {
+        Expr::Call(Call { exprs, .. }) => match &exprs[..] {
+            [Expr::Val(NameId { token, .. }), elems @ ..]
+                if token == "array" && !elems.is_empty() =>
+            {
+                Some(elems)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The array types an `array` call with these elements may build. An element
+/// that is itself an `array` call may be any array that call may build.
+fn array_call_types(elems: &[Expr], elem_types: &[Type]) -> Vec<Type> {
+    let mut types = Vec::new();
+    for elem in elems {
+        let elem_types = array_call(elem).map_or_else(
+            || elem_types.to_vec(),
+            |inner| array_call_types(inner, elem_types),
+        );
+        for typ in elem_types {
+            if !types.contains(&typ) {
+                types.push(typ);
+            }
+        }
+    }
+    types
+        .into_iter()
+        .map(|typ| Type::Array(Box::new(typ), elems.len()))
+        .collect()
+}
+
+/// Registers the intrinsics of a pointer, array or slice type, and of the
+/// types they lead to. A pointer and a slice of the same element type each
+/// lead to the other, through `slice` and `data`.
+fn register_intrinsics(typ: &Type, namespace: &mut Namespace, registered: &mut Vec<Type>) {
+    if registered.contains(typ) {
+        return;
+    }
+
+    let int = || Type::Terminal(Terminal::I64);
+    let void = || Type::Terminal(Terminal::Void);
+
+    let (intrinsics, related) = match typ {
+        Type::Ptr(elem) if **elem == void() => (vec![], vec![]),
+        Type::Ptr(elem) => (
+            vec![
+                ("load", vec![typ.clone()], (**elem).clone(), Intrinsic::Load),
+                (
+                    "store!",
+                    vec![typ.clone(), (**elem).clone()],
+                    void(),
+                    Intrinsic::Store,
+                ),
+                (
+                    "offset",
+                    vec![typ.clone(), int()],
+                    typ.clone(),
+                    Intrinsic::Offset,
+                ),
+                (
+                    "slice",
+                    vec![typ.clone(), int()],
+                    Type::Slice(elem.clone()),
+                    Intrinsic::Slice,
+                ),
+            ],
+            vec![(**elem).clone(), Type::Slice(elem.clone())],
+        ),
+        Type::Array(elem, len) => (
+            vec![
+                (
+                    "array",
+                    vec![(**elem).clone(); *len],
+                    typ.clone(),
+                    Intrinsic::Array,
+                ),
+                (
+                    "at",
+                    vec![typ.clone(), int()],
+                    (**elem).clone(),
+                    Intrinsic::At,
+                ),
+                ("len", vec![typ.clone()], int(), Intrinsic::Len),
+            ],
+            vec![(**elem).clone()],
+        ),
+        Type::Slice(elem) => (
+            vec![
+                (
+                    "at",
+                    vec![typ.clone(), int()],
+                    (**elem).clone(),
+                    Intrinsic::At,
+                ),
+                (
+                    "set-at!",
+                    vec![typ.clone(), int(), (**elem).clone()],
+                    void(),
+                    Intrinsic::SetAt,
+                ),
+                ("len", vec![typ.clone()], int(), Intrinsic::Len),
+                (
+                    "data",
+                    vec![typ.clone()],
+                    Type::Ptr(elem.clone()),
+                    Intrinsic::Data,
+                ),
+            ],
+            vec![(**elem).clone(), Type::Ptr(elem.clone())],
+        ),
+        _ => return,
+    };
+
+    registered.push(typ.clone());
+
+    for (name, params, ret, intrinsic) in intrinsics {
+        let func = Func {
+            params,
+            ret: Box::new(ret),
+        };
+        namespace.append_namespace(name, Namespace::from(Symbol::Intrinsic(func, intrinsic)));
+    }
+
+    for typ in &related {
+        register_intrinsics(typ, namespace, registered);
     }
 }
@@ -1,24 +1,185 @@
-#[derive(Debug, PartialEq)]
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Symbol {
     Module,
+    Scope,
     Type(Type),
     Var(Type),
-    Literal(Terminal),
+    MutVar(Type),
+    Literal(Type),
+    Constructor(Func),
+    Field(Func, usize),
+    Variant(Func, Variant),
+    Intrinsic(Func, Intrinsic),
+    Extern(Func),
+    /// The scope of a lambda's parameters. A lambda has no name to be called
+    /// by, so this is only used to tell it apart from the def around it.
+    Lambda(Func),
+    /// A def with type parameters, named in the order they are declared. Its
+    /// type refers to them as `Type::Param`. Each constraint is a type
+    /// parameter and a class it must be an instance of.
+    Generic(Func, Vec<String>, Vec<(String, String)>),
+    Class(Class),
+    /// A method of the named class, which can be called on any instance of
+    /// it. Its type refers to the instance as a `Type::Param` named after the
+    /// class.
+    Method(Func, String),
+}
+
+impl Symbol {
+    /// The type of anything that can be called like a function.
+    pub const fn func(&self) -> Option<&Func> {
+        match self {
+            Self::Var(Type::Func(func))
+            | Self::MutVar(Type::Func(func))
+            | Self::Constructor(func)
+            | Self::Field(func, _)
+            | Self::Variant(func, _)
+            | Self::Intrinsic(func, _)
+            | Self::Extern(func)
+            | Self::Generic(func, _, _)
+            | Self::Method(func, _) => Some(func),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
     Func(Func),
     Terminal(Terminal),
+    Struct(String),
+    Enum(String),
+    Ptr(Box<Self>),
+    Array(Box<Self>, usize),
+    Slice(Box<Self>),
+    /// The result of an overflowing operation on an integer, and whether it
+    /// overflowed.
+    Overflow(Terminal),
+    /// A type parameter of the generic def being checked, which stands for
+    /// whatever type it is instantiated with.
+    Param(String),
+    /// A pointer to the code of a function value, which takes the value's
+    /// environment before its parameters. Only code generation uses it.
+    Code(Func),
 }
 
-#[derive(Debug, PartialEq)]
+impl Type {
+    /// Replaces each type parameter in `params` with the type at the same
+    /// index in `args`.
+    pub fn substitute(&self, params: &[String], args: &[Self]) -> Self {
+        match self {
+            Self::Param(name) => params
+                .iter()
+                .position(|param| param == name)
+                .map_or_else(|| self.clone(), |index| args[index].clone()),
+            Self::Ptr(elem) => Self::Ptr(Box::new(elem.substitute(params, args))),
+            Self::Array(elem, len) => Self::Array(Box::new(elem.substitute(params, args)), *len),
+            Self::Slice(elem) => Self::Slice(Box::new(elem.substitute(params, args))),
+            Self::Func(func) => Self::Func(func.substitute(params, args)),
+            Self::Code(func) => Self::Code(func.substitute(params, args)),
+            Self::Terminal(_) | Self::Struct(_) | Self::Enum(_) | Self::Overflow(_) => self.clone(),
+        }
+    }
+
+    /// Whether the type mentions a type parameter.
+    pub fn is_generic(&self) -> bool {
+        self.mentions(&|_| true)
+    }
+
+    /// Whether the type mentions a type parameter for which `param` holds.
+    pub fn mentions(&self, param: &impl Fn(&str) -> bool) -> bool {
+        match self {
+            Self::Param(name) => param(name),
+            Self::Ptr(elem) | Self::Array(elem, _) | Self::Slice(elem) => elem.mentions(param),
+            Self::Func(func) | Self::Code(func) => {
+                func.params.iter().any(|typ| typ.mentions(param)) || func.ret.mentions(param)
+            }
+            Self::Terminal(_) | Self::Struct(_) | Self::Enum(_) | Self::Overflow(_) => false,
+        }
+    }
+
+    /// The type as it is written in Lamb, for diagnostics.
+    pub const fn syntax(&self) -> Syntax<'_> {
+        Syntax(self)
+    }
+}
+
+pub struct Syntax<'a>(&'a Type);
+
+impl Display for Syntax<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self.0 {
+            Type::Terminal(terminal) => f.write_str(terminal.name()),
+            Type::Struct(name) | Type::Enum(name) | Type::Param(name) => f.write_str(name),
+            Type::Ptr(elem) => write!(f, "(ptr {})", elem.syntax()),
+            Type::Array(elem, len) => write!(f, "(array {} {len})", elem.syntax()),
+            Type::Slice(elem) => write!(f, "(slice {})", elem.syntax()),
+            Type::Overflow(terminal) => write!(f, "(overflow {})", terminal.name()),
+            Type::Func(func) | Type::Code(func) => {
+                f.write_str("(")?;
+                for param in &func.params {
+                    write!(f, "{} ", param.syntax())?;
+                }
+                write!(f, "{})", func.ret.syntax())
+            }
+        }
+    }
+}
+
+/// The methods a class gives its instances, typed like those of a
+/// `Symbol::Method`, and the types that are instances of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Class {
+    pub methods: Vec<(String, Func)>,
+    pub instances: Vec<Type>,
+}
+
+/// An enum is lowered to a struct holding an `i32` tag followed by a payload
+/// that the variants share. `tag` tells which variant the payload holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub tag: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Func {
-    pub params: Vec<Terminal>,
-    pub ret: Terminal,
+    pub params: Vec<Type>,
+    pub ret: Box<Type>,
+}
+
+impl Func {
+    pub fn substitute(&self, params: &[String], args: &[Type]) -> Self {
+        Self {
+            params: self
+                .params
+                .iter()
+                .map(|param| param.substitute(params, args))
+                .collect(),
+            ret: Box::new(self.ret.substitute(params, args)),
+        }
+    }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// Operations on pointers, arrays and slices. Each is registered once for
+/// every such type used in the program, so overloading picks the right one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Intrinsic {
+    Array,
+    At,
+    SetAt,
+    Len,
+    Slice,
+    Data,
+    Load,
+    Store,
+    Offset,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Terminal {
     Void,
     Bool,
@@ -35,6 +196,60 @@ pub enum Terminal {
     F64,
 }
 
+/// How the instructions on a terminal treat its bits. LLVM integer types
+/// have no sign, so unsigned and signed terminals share a type and differ
+/// only in the instructions chosen for them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Signedness {
+    Unsigned,
+    Signed,
+    Float,
+}
+
+impl Terminal {
+    /// `bool` is an unsigned one-bit integer. `void` has no value, so any
+    /// answer will do.
+    pub const fn signedness(self) -> Signedness {
+        match self {
+            Self::Void | Self::Bool | Self::U8 | Self::U16 | Self::U32 | Self::U64 => {
+                Signedness::Unsigned
+            }
+            Self::I8 | Self::I16 | Self::I32 | Self::I64 => Signedness::Signed,
+            Self::F16 | Self::F32 | Self::F64 => Signedness::Float,
+        }
+    }
+
+    /// The name of the terminal in Lamb.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Void => "void",
+            Self::Bool => "bool",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::F16 => "f16",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        }
+    }
+
+    pub const fn bits(self) -> u32 {
+        match self {
+            Self::Void => 0,
+            Self::Bool => 1,
+            Self::U8 | Self::I8 => 8,
+            Self::U16 | Self::I16 | Self::F16 => 16,
+            Self::U32 | Self::I32 | Self::F32 => 32,
+            Self::U64 | Self::I64 | Self::F64 => 64,
+        }
+    }
+}
+
 pub const BOOL: [Terminal; 1] = [Terminal::Bool];
 
 pub const INT: [Terminal; 8] = [
@@ -50,6 +265,17 @@ pub const INT: [Terminal; 8] = [
 
 pub const FLOAT: [Terminal; 3] = [Terminal::F16, Terminal::F32, Terminal::F64];
 
+/// The numbers that can be negative.
+pub const SIGNED: [Terminal; 7] = [
+    Terminal::I8,
+    Terminal::I16,
+    Terminal::I32,
+    Terminal::I64,
+    Terminal::F16,
+    Terminal::F32,
+    Terminal::F64,
+];
+
 pub const NUM: [Terminal; 11] = [
     Terminal::U8,
     Terminal::U16,
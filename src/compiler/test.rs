@@ -2,7 +2,7 @@ use super::*;
 
 macro_rules! ok {
     ($text:literal, $code:literal) => {
-        match emit($text) {
+        match emit($text, &Options::default()) {
             Ok(code) => {
                 if code != $code {
                     eprintln!(
@@ -29,7 +29,7 @@ macro_rules! ok {
 
 macro_rules! err {
     ($text:literal, $error:literal) => {
-        match emit($text) {
+        match emit($text, &Options::default()) {
             Ok(code) => {
                 eprint!(
                     "\ninput:\n{}\n\nexpected error: \n{}\n\ngot code:\n{}\n",
@@ -54,6 +54,35 @@ macro_rules! err {
     };
 }
 
+macro_rules! warn {
+    ($text:literal, $warning:literal) => {
+        match typecheck($text) {
+            Ok((program, _)) => {
+                if !program.warnings.iter().any(|w| w.name() == $warning) {
+                    eprintln!(
+                        "\ninput:\n{}\n\nexpected warning: \n{}\n\ngot warnings:\n{:?}\n",
+                        $text,
+                        $warning,
+                        program.warnings.iter().map(Error::name).collect::<Vec<_>>()
+                    );
+                    panic!();
+                }
+            }
+            Err(error) => {
+                eprintln!(
+                    "\ninput:\n{}\n\nexpected warning: \n{}\n\ngot error:\n{}\n",
+                    $text,
+                    $warning,
+                    error.name()
+                );
+                error.print($text);
+                eprintln!();
+                panic!();
+            }
+        }
+    };
+}
+
 #[test]
 #[allow(clippy::too_many_lines)]
 #[allow(clippy::cognitive_complexity)]
@@ -130,7 +159,7 @@ define i32 @f(i32 %0) {
 ret i32 %0
 }
 define i32 @main(i32 %0) {
-%2 = call i32 @f(i32 %0)
+%2 = musttail call i32 @f(i32 %0)
 ret i32 %2
 }
 "
@@ -146,42 +175,20 @@ define i32 @f1() {
 ret i32 0
 }
 define i32 @main() {
-%1 = call i32 @f1()
+%1 = musttail call i32 @f1()
 ret i32 %1
 }
 "
     );
 
-    ok!(
+    err!(
         "(f (i32) 0) (f (i32) 1) (main (i32) (f))",
-        "\
-define i32 @f() {
-ret i32 0
-}
-define i32 @f1() {
-ret i32 1
-}
-define i32 @main() {
-%1 = call i32 @f1()
-ret i32 %1
-}
-"
+        "duplicate_overload"
     );
 
-    ok!(
+    err!(
         "(f ((x i32) i32) x) (f ((x i32) i32) x) (main (i32) (f 1))",
-        "\
-define i32 @f(i32 %0) {
-ret i32 %0
-}
-define i32 @f1(i32 %0) {
-ret i32 %0
-}
-define i32 @main() {
-%1 = call i32 @f1(i32 1)
-ret i32 %1
-}
-"
+        "duplicate_overload"
     );
 
     ok!(
@@ -192,7 +199,7 @@ define i32 @f(i32 %0, i32 %1) {
 ret i32 %3
 }
 define i32 @main() {
-%1 = call i32 @f(i32 1, i32 2)
+%1 = tail call i32 @f(i32 1, i32 2)
 ret i32 %1
 }
 "
@@ -271,7 +278,7 @@ define i32 @f(i32 %0, i32 %1) {
 ret i32 %3
 }
 define i32 @main(i32 %0) {
-%2 = call i32 @f(i32 1, i32 2)
+%2 = tail call i32 @f(i32 1, i32 2)
 ret i32 %2
 }
 "
@@ -284,11 +291,11 @@ define i32 @f(i32 %0, i32 %1) {
 ret i32 %1
 }
 define i32 @f1() {
-%1 = call i32 @f(i32 1, i32 2)
+%1 = tail call i32 @f(i32 1, i32 2)
 ret i32 %1
 }
 define i32 @main() {
-%1 = call i32 @f1()
+%1 = musttail call i32 @f1()
 ret i32 %1
 }
 "
@@ -301,7 +308,7 @@ define void @f() {
 ret void
 }
 define void @g() {
-call void @f()
+musttail call void @f()
 ret void
 }
 define i32 @main() {
@@ -310,21 +317,9 @@ ret i32 0
 "
     );
 
-    ok!(
+    err!(
         "(f (i32) 0) (f (i32) (f)) (main (i32) (f))",
-        "\
-define i32 @f() {
-ret i32 0
-}
-define i32 @f1() {
-%1 = call i32 @f1()
-ret i32 %1
-}
-define i32 @main() {
-%1 = call i32 @f1()
-ret i32 %1
-}
-"
+        "duplicate_overload"
     );
 
     err!("a", "expected_def");
@@ -355,10 +350,7 @@ ret i32 %1
 
     err!("(f (i32) 1) (main ((x i32) f) 1)", "expected_terminal_type");
 
-    err!(
-        "(f (i32) 1) (main ((x i32) i32) f)",
-        "expected_literal_or_var"
-    );
+    err!("(f (i32) 1) (main ((x i32) i32) f)", "type_mismatch");
 
     err!("(main ((x i32) i32) true)", "type_mismatch");
 
@@ -366,7 +358,7 @@ ret i32 %1
 
     err!("(main (i32) (f))", "expected_defined_symbol");
 
-    err!("(main (i32) (i32))", "expected_func");
+    err!("(main (i32) (bool))", "expected_func");
 
     err!("(f (f32) 1.0) (main (i32) (f))", "func_type_mismatch");
 
@@ -391,12 +383,12 @@ ret i32 %1
     err!("(main ((x i32) void) ())", "expected_main_type");
 
     err!(
-        "(f (i32) 0) (f (i32) 1) (main (i32) (f 1))",
+        "(f (i32) 0) (f (i64) 1) (main (i32) (f 1))",
         "no_type_match"
     );
 
     err!(
-        "(f (f32) 0.0) (f (f32) 1.0) (main (i32) (f))",
+        "(f (f32) 0.0) (f (f64) 1.0) (main (i32) (f))",
         "no_type_match"
     );
 
@@ -415,3 +407,2879 @@ ret i32 %1
 
     err!("(main ((x i32) i32) (|| x 1))", "func_type_mismatch");
 }
+
+#[test]
+fn if_expr() {
+    ok!(
+        "(f ((x i32) (y i32) i32) (if (> x y) x y)) (main (i32) 0)",
+        "\
+define i32 @f(i32 %0, i32 %1) {
+%3 = icmp sgt i32 %0, %1
+br i1 %3, label %if.then.0, label %if.else.0
+if.then.0:
+ret i32 %0
+if.else.0:
+ret i32 %1
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+
+    ok!(
+        "(main ((x i32) i32) (* (if (< x 0) (- 0 x) (if (== x 0) 1 x)) 2))",
+        "\
+define i32 @main(i32 %0) {
+%2 = icmp slt i32 %0, 0
+br i1 %2, label %if.then.0, label %if.else.0
+if.then.0:
+%3 = sub i32 0, %0
+br label %if.end.0
+if.else.0:
+%4 = icmp eq i32 %0, 0
+br i1 %4, label %if.then.1, label %if.else.1
+if.then.1:
+br label %if.end.1
+if.else.1:
+br label %if.end.1
+if.end.1:
+%5 = phi i32 [1, %if.then.1], [%0, %if.else.1]
+br label %if.end.0
+if.end.0:
+%6 = phi i32 [%3, %if.then.0], [%5, %if.end.1]
+%7 = mul i32 %6, 2
+ret i32 %7
+}
+"
+    );
+
+    ok!(
+        "(f (void) ()) (g ((b bool) void) (if b (f) ())) (main (i32) 0)",
+        "\
+define void @f() {
+ret void
+}
+define void @g(i1 %0) {
+br i1 %0, label %if.then.0, label %if.else.0
+if.then.0:
+tail call void @f()
+ret void
+if.else.0:
+ret void
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+
+    err!("(main (i32) (if true 1))", "expected_if");
+
+    err!("(main (i32) (if 1 1 2))", "type_mismatch");
+
+    err!("(main (i32) (if true 1 false))", "type_mismatch");
+}
+
+#[test]
+fn let_expr() {
+    ok!(
+        "(main ((x i32) i32) (let ((y i32 (+ x 1)) (z i32 (* y y))) (- z y)))",
+        "\
+define i32 @main(i32 %0) {
+%2 = add i32 %0, 1
+%3 = mul i32 %2, %2
+%4 = sub i32 %3, %2
+ret i32 %4
+}
+"
+    );
+
+    ok!(
+        "(main ((x i32) i32) (let ((x i32 (+ x 1))) (let ((x i32 (* x 2))) x)))",
+        "\
+define i32 @main(i32 %0) {
+%2 = add i32 %0, 1
+%3 = mul i32 %2, 2
+ret i32 %3
+}
+"
+    );
+
+    ok!(
+        "(main ((x i32) i32) (+ (let ((x i32 5)) x) x))",
+        "\
+define i32 @main(i32 %0) {
+%2 = add i32 5, %0
+ret i32 %2
+}
+"
+    );
+
+    ok!(
+        "(f (i32) 1) (main (i32) (let ((g i32 (f))) g))",
+        "\
+define i32 @f() {
+ret i32 1
+}
+define i32 @main() {
+%1 = call i32 @f()
+ret i32 %1
+}
+"
+    );
+
+    err!("(main (i32) (let ((x i32 1))))", "expected_let");
+
+    err!("(main (i32) (let (x) 1))", "expected_binding");
+
+    err!("(main (i32) (let ((x i32)) x))", "expected_binding");
+
+    err!(
+        "(main (i32) (let (((x) i32 1)) 1))",
+        "expected_binding_name"
+    );
+
+    err!("(main (i32) (let ((x () 1)) x))", "expected_binding_type");
+
+    err!(
+        "(main (i32) (let ((x i32 1) (x i32 2)) x))",
+        "duplicate_binding"
+    );
+
+    err!(
+        "(main (i32) (let ((x void ())) 1))",
+        "unexpected_void_binding"
+    );
+
+    err!("(main (i32) (let ((x a32 1)) 1))", "expected_defined_type");
+
+    err!("(main (i32) (let ((x bool 1)) 1))", "type_mismatch");
+
+    err!("(main (i32) (+ (let ((x i32 1)) x) x))", "no_type_match");
+
+    err!(
+        "(f (i32) 1) (main (i32) (let ((f i32 1)) (f)))",
+        "expected_func"
+    );
+}
+
+#[test]
+fn while_loop() {
+    ok!(
+        "(main ((n i32) i32) (let ((mut i i32 0)) (do (while (< i n) (set! i (+ i 1))) i)))",
+        "\
+define i32 @main(i32 %0) {
+%slot.0 = alloca i32
+store i32 0, i32* %slot.0
+br label %while.cond.0
+while.cond.0:
+%2 = load i32, i32* %slot.0
+%3 = icmp slt i32 %2, %0
+br i1 %3, label %while.body.0, label %while.end.0
+while.body.0:
+%4 = load i32, i32* %slot.0
+%5 = add i32 %4, 1
+store i32 %5, i32* %slot.0
+br label %while.cond.0
+while.end.0:
+%6 = load i32, i32* %slot.0
+ret i32 %6
+}
+"
+    );
+
+    ok!(
+        "(main ((n i32) i32) (let ((mut a i32 n)) (do (if (< a 0) (set! a 0) ()) (let ((mut b i32 a)) (do (set! b (* b 2)) b)))))",
+        "\
+define i32 @main(i32 %0) {
+%slot.0 = alloca i32
+%slot.1 = alloca i32
+store i32 %0, i32* %slot.0
+%2 = load i32, i32* %slot.0
+%3 = icmp slt i32 %2, 0
+br i1 %3, label %if.then.0, label %if.else.0
+if.then.0:
+store i32 0, i32* %slot.0
+br label %if.end.0
+if.else.0:
+br label %if.end.0
+if.end.0:
+%4 = load i32, i32* %slot.0
+store i32 %4, i32* %slot.1
+%5 = load i32, i32* %slot.1
+%6 = mul i32 %5, 2
+store i32 %6, i32* %slot.1
+%7 = load i32, i32* %slot.1
+ret i32 %7
+}
+"
+    );
+
+    ok!(
+        "(f (void) ()) (main (i32) (do (f) (f) 0))",
+        "\
+define void @f() {
+ret void
+}
+define i32 @main() {
+call void @f()
+call void @f()
+ret i32 0
+}
+"
+    );
+
+    err!(
+        "(main (i32) (let ((x i32 0)) (do (set! x 1) x)))",
+        "expected_mutable"
+    );
+
+    err!("(main ((x i32) i32) (do (set! x 1) x))", "expected_mutable");
+
+    err!("(main (i32) (do (set! x 1) 0))", "expected_defined_symbol");
+
+    err!(
+        "(main (i32) (let ((mut x i32 0)) (set! x 1)))",
+        "type_mismatch"
+    );
+
+    err!(
+        "(main (i32) (let ((mut x i32 0)) (do (set! x true) x)))",
+        "type_mismatch"
+    );
+
+    err!("(main (i32) (while true ()))", "type_mismatch");
+
+    err!("(main (i32) (do (while 1 ()) 0))", "type_mismatch");
+
+    err!("(main (i32) (do 1 0))", "type_mismatch");
+
+    err!("(main (i32) (do))", "expected_do_expr");
+
+    err!("(main (i32) (do (set! x) 0))", "expected_set");
+
+    err!("(main (i32) (do (while true) 0))", "expected_while");
+
+    err!("(main (i32) (let ((mut x i32 0 1)) x))", "expected_binding");
+}
+
+#[test]
+fn tail_call() {
+    ok!(
+        "(count ((n i32) (acc i32) i32) (if (== n 0) acc (tail count (- n 1) (+ acc 1)))) (main (i32) (count 10 0))",
+        "\
+define i32 @count(i32 %0, i32 %1) {
+%3 = icmp eq i32 %0, 0
+br i1 %3, label %if.then.0, label %if.else.0
+if.then.0:
+ret i32 %1
+if.else.0:
+%4 = sub i32 %0, 1
+%5 = add i32 %1, 1
+%6 = musttail call i32 @count(i32 %4, i32 %5)
+ret i32 %6
+}
+define i32 @main() {
+%1 = tail call i32 @count(i32 10, i32 0)
+ret i32 %1
+}
+"
+    );
+
+    ok!(
+        "(even ((n i64) bool) (if (== n 0) true (odd (- n 1)))) (odd ((n i64) bool) (if (== n 0) false (even (- n 1)))) (main (i32) 0)",
+        "\
+define i1 @even(i64 %0) {
+%2 = icmp eq i64 %0, 0
+br i1 %2, label %if.then.0, label %if.else.0
+if.then.0:
+ret i1 true
+if.else.0:
+%3 = sub i64 %0, 1
+%4 = musttail call i1 @odd(i64 %3)
+ret i1 %4
+}
+define i1 @odd(i64 %0) {
+%2 = icmp eq i64 %0, 0
+br i1 %2, label %if.then.0, label %if.else.0
+if.then.0:
+ret i1 false
+if.else.0:
+%3 = sub i64 %0, 1
+%4 = musttail call i1 @even(i64 %3)
+ret i1 %4
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+
+    ok!(
+        "(f ((x i32) i32) x) (main ((x i32) i32) (let ((y i32 (f x))) (f y)))",
+        "\
+define i32 @f(i32 %0) {
+ret i32 %0
+}
+define i32 @main(i32 %0) {
+%2 = call i32 @f(i32 %0)
+%3 = musttail call i32 @f(i32 %2)
+ret i32 %3
+}
+"
+    );
+
+    err!(
+        "(f ((x i32) i32) x) (main ((x i32) i32) (+ (tail f x) 1))",
+        "unexpected_tail"
+    );
+
+    err!(
+        "(f ((x i32) i32) x) (main ((x i32) i32) (let ((y i32 (tail f x))) y))",
+        "unexpected_tail"
+    );
+
+    err!(
+        "(f ((x i32) bool) true) (main ((x i32) i32) (if (tail f x) 1 2))",
+        "unexpected_tail"
+    );
+
+    err!(
+        "(f ((x i64) i32) 0) (main ((x i32) i32) (tail f 1))",
+        "tail_type_mismatch"
+    );
+
+    err!("(main (i32) (tail))", "expected_tail_call");
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn struct_expr() {
+    ok!(
+        "(struct Point (x i32) (y i32)) (main (i32) (let ((p Point (Point 1 2))) (+ (.x p) (.y p))))",
+        "\
+%Point = type { i32, i32 }
+
+define i32 @main() {
+%1 = insertvalue %Point undef, i32 1, 0
+%2 = insertvalue %Point %1, i32 2, 1
+%3 = extractvalue %Point %2, 0
+%4 = extractvalue %Point %2, 1
+%5 = add i32 %3, %4
+ret i32 %5
+}
+"
+    );
+
+    ok!(
+        "(struct Line (a Point) (b Point)) (struct Point (x f64) (y f64)) (start ((l Line) Point) (.a l)) (main (i32) 0)",
+        "\
+%Line = type { %Point, %Point }
+%Point = type { double, double }
+
+define %Point @start(%Line %0) {
+%2 = extractvalue %Line %0, 0
+ret %Point %2
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+
+    ok!(
+        "(struct Point (x i32) (y i32)) (struct Size (x i64)) (f ((s Size) i64) (.x s)) (main ((x i32) i32) (.x (Point x 0)))",
+        "\
+%Point = type { i32, i32 }
+%Size = type { i64 }
+
+define i64 @f(%Size %0) {
+%2 = extractvalue %Size %0, 0
+ret i64 %2
+}
+define i32 @main(i32 %0) {
+%2 = insertvalue %Point undef, i32 %0, 0
+%3 = insertvalue %Point %2, i32 0, 1
+%4 = extractvalue %Point %3, 0
+ret i32 %4
+}
+"
+    );
+
+    ok!(
+        "(struct Unit) (f ((u Unit) Unit) u) (main (i32) (let ((u Unit (f (Unit)))) 0))",
+        "\
+%Unit = type {}
+
+define %Unit @f(%Unit %0) {
+ret %Unit %0
+}
+define i32 @main() {
+%1 = call %Unit @f(%Unit undef)
+ret i32 0
+}
+"
+    );
+
+    err!("(struct) (main (i32) 0)", "expected_struct_name");
+    err!("(struct Point x) (main (i32) 0)", "expected_field");
+    err!("(struct Point (x i32 0)) (main (i32) 0)", "expected_field");
+    err!(
+        "(struct Point (x i32) (x i32)) (main (i32) 0)",
+        "duplicate_field"
+    );
+    err!("(struct i32 (x i32)) (main (i32) 0)", "duplicate_type");
+    err!(
+        "(struct P (x i32)) (struct P (y i32)) (main (i32) 0)",
+        "duplicate_type"
+    );
+    err!(
+        "(struct Point (x Foo)) (main (i32) 0)",
+        "expected_defined_type"
+    );
+    err!(
+        "(struct Point (x void)) (main (i32) 0)",
+        "unexpected_void_field"
+    );
+    err!("(struct List (next List)) (main (i32) 0)", "recursive_type");
+    err!(
+        "(struct A (b B)) (struct B (a A)) (main (i32) 0)",
+        "recursive_type"
+    );
+    err!(
+        "(struct Point (x i32) (y i32)) (main (i32) (.x (Point 1)))",
+        "expected_argument"
+    );
+    err!(
+        "(struct Point (x i32) (y i32)) (main (i32) (.x (Point 1 true)))",
+        "type_mismatch"
+    );
+    err!(
+        "(struct Point (x i32) (y i32)) (main (i32) (.z (Point 1 2)))",
+        "expected_defined_symbol"
+    );
+    err!(
+        "(struct Point (x i32)) (main (i32) (.x 1))",
+        "type_mismatch"
+    );
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn enum_match() {
+    ok!(
+        "(enum Shape (Circle f64) (Rect f64 f64)) (area ((s Shape) f64) (match s ((Circle r) (* r r)) ((Rect w h) (* w h)))) (main (i32) 0)",
+        "\
+%Shape = type { i32, [2 x i64] }
+%Shape.Circle = type { i32, [0 x i64], double }
+%Shape.Rect = type { i32, [0 x i64], double, double }
+
+define double @area(%Shape %0) {
+%slot.0 = alloca %Shape
+%slot.1 = alloca %Shape
+%2 = extractvalue %Shape %0, 0
+switch i32 %2, label %match.default.0 [ i32 0, label %match.arm.0.0 i32 1, label %match.arm.0.1 ]
+match.default.0:
+unreachable
+match.arm.0.0:
+store %Shape %0, %Shape* %slot.0
+%3 = bitcast %Shape* %slot.0 to %Shape.Circle*
+%4 = load %Shape.Circle, %Shape.Circle* %3
+%5 = extractvalue %Shape.Circle %4, 2
+%6 = fmul double %5, %5
+ret double %6
+match.arm.0.1:
+store %Shape %0, %Shape* %slot.1
+%7 = bitcast %Shape* %slot.1 to %Shape.Rect*
+%8 = load %Shape.Rect, %Shape.Rect* %7
+%9 = extractvalue %Shape.Rect %8, 2
+%10 = extractvalue %Shape.Rect %8, 3
+%11 = fmul double %9, %10
+ret double %11
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+
+    ok!(
+        "(enum Option (Some i32) None) (get ((o Option) i32) (+ (match o ((Some x) x) (None 0)) 1)) (main (i32) (get (Some 2)))",
+        "\
+%Option = type { i32, [1 x i32] }
+%Option.Some = type { i32, [0 x i32], i32 }
+
+define i32 @get(%Option %0) {
+%slot.0 = alloca %Option
+%2 = extractvalue %Option %0, 0
+switch i32 %2, label %match.default.0 [ i32 0, label %match.arm.0.0 i32 1, label %match.arm.0.1 ]
+match.default.0:
+unreachable
+match.arm.0.0:
+store %Option %0, %Option* %slot.0
+%3 = bitcast %Option* %slot.0 to %Option.Some*
+%4 = load %Option.Some, %Option.Some* %3
+%5 = extractvalue %Option.Some %4, 2
+br label %match.end.0
+match.arm.0.1:
+br label %match.end.0
+match.end.0:
+%6 = phi i32 [%5, %match.arm.0.0], [0, %match.arm.0.1]
+%7 = add i32 %6, 1
+ret i32 %7
+}
+define i32 @main() {
+%slot.0 = alloca %Option
+%1 = insertvalue %Option.Some undef, i32 0, 0
+%2 = insertvalue %Option.Some %1, i32 2, 2
+%3 = bitcast %Option* %slot.0 to %Option.Some*
+store %Option.Some %2, %Option.Some* %3
+%4 = load %Option, %Option* %slot.0
+%5 = tail call i32 @get(%Option %4)
+ret i32 %5
+}
+"
+    );
+
+    ok!(
+        "(enum Color Red Green Blue) (is_red ((c Color) bool) (match c (Red true) (_ false))) (main (i32) (if (is_red (Green)) 1 0))",
+        "\
+%Color = type { i32 }
+
+define i1 @is_red(%Color %0) {
+%2 = extractvalue %Color %0, 0
+switch i32 %2, label %match.arm.0.1 [ i32 0, label %match.arm.0.0 ]
+match.arm.0.0:
+ret i1 true
+match.arm.0.1:
+ret i1 false
+}
+define i32 @main() {
+%1 = insertvalue %Color undef, i32 1, 0
+%2 = call i1 @is_red(%Color %1)
+br i1 %2, label %if.then.0, label %if.else.0
+if.then.0:
+ret i32 1
+if.else.0:
+ret i32 0
+}
+"
+    );
+
+    ok!(
+        "(struct Point (x i32) (y i32)) (enum Shape (Dot Point) (Line Point Point)) (first ((s Shape) i32) (match s ((Dot p) (.x p)) ((Line p _) (.y p)))) (main (i32) 0)",
+        "\
+%Point = type { i32, i32 }
+%Shape = type { i32, [4 x i32] }
+%Shape.Dot = type { i32, [0 x i32], %Point }
+%Shape.Line = type { i32, [0 x i32], %Point, %Point }
+
+define i32 @first(%Shape %0) {
+%slot.0 = alloca %Shape
+%slot.1 = alloca %Shape
+%2 = extractvalue %Shape %0, 0
+switch i32 %2, label %match.default.0 [ i32 0, label %match.arm.0.0 i32 1, label %match.arm.0.1 ]
+match.default.0:
+unreachable
+match.arm.0.0:
+store %Shape %0, %Shape* %slot.0
+%3 = bitcast %Shape* %slot.0 to %Shape.Dot*
+%4 = load %Shape.Dot, %Shape.Dot* %3
+%5 = extractvalue %Shape.Dot %4, 2
+%6 = extractvalue %Point %5, 0
+ret i32 %6
+match.arm.0.1:
+store %Shape %0, %Shape* %slot.1
+%7 = bitcast %Shape* %slot.1 to %Shape.Line*
+%8 = load %Shape.Line, %Shape.Line* %7
+%9 = extractvalue %Shape.Line %8, 2
+%10 = extractvalue %Point %9, 1
+ret i32 %10
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+
+    ok!(
+        "(enum A (X i32)) (enum B (X i64) (Y)) (f ((b B) i64) (match b ((X n) n) (Y 0))) (g ((a A) i32) (match a ((X n) n))) (main (i32) (g (X 1)))",
+        "\
+%A = type { i32, [1 x i32] }
+%B = type { i32, [1 x i64] }
+%A.X = type { i32, [0 x i32], i32 }
+%B.X = type { i32, [0 x i64], i64 }
+
+define i64 @f(%B %0) {
+%slot.0 = alloca %B
+%2 = extractvalue %B %0, 0
+switch i32 %2, label %match.default.0 [ i32 0, label %match.arm.0.0 i32 1, label %match.arm.0.1 ]
+match.default.0:
+unreachable
+match.arm.0.0:
+store %B %0, %B* %slot.0
+%3 = bitcast %B* %slot.0 to %B.X*
+%4 = load %B.X, %B.X* %3
+%5 = extractvalue %B.X %4, 2
+ret i64 %5
+match.arm.0.1:
+ret i64 0
+}
+define i32 @g(%A %0) {
+%slot.0 = alloca %A
+%2 = extractvalue %A %0, 0
+switch i32 %2, label %match.default.0 [ i32 0, label %match.arm.0.0 ]
+match.default.0:
+unreachable
+match.arm.0.0:
+store %A %0, %A* %slot.0
+%3 = bitcast %A* %slot.0 to %A.X*
+%4 = load %A.X, %A.X* %3
+%5 = extractvalue %A.X %4, 2
+ret i32 %5
+}
+define i32 @main() {
+%slot.0 = alloca %A
+%1 = insertvalue %A.X undef, i32 0, 0
+%2 = insertvalue %A.X %1, i32 1, 2
+%3 = bitcast %A* %slot.0 to %A.X*
+store %A.X %2, %A.X* %3
+%4 = load %A, %A* %slot.0
+%5 = tail call i32 @g(%A %4)
+ret i32 %5
+}
+"
+    );
+
+    // Every variant's fields start where the payload does, past the padding
+    // after the tag, even when they are less aligned than the payload.
+    ok!(
+        "(enum E (A u8) (B i64 u8)) (f ((e E) u8) (match e ((A x) x) ((B _ y) y))) (main (i32) (i32 (f (A 3u8))))",
+        "\
+%E = type { i32, [2 x i64] }
+%E.A = type { i32, [0 x i64], i8 }
+%E.B = type { i32, [0 x i64], i64, i8 }
+
+define i8 @f(%E %0) {
+%slot.0 = alloca %E
+%slot.1 = alloca %E
+%2 = extractvalue %E %0, 0
+switch i32 %2, label %match.default.0 [ i32 0, label %match.arm.0.0 i32 1, label %match.arm.0.1 ]
+match.default.0:
+unreachable
+match.arm.0.0:
+store %E %0, %E* %slot.0
+%3 = bitcast %E* %slot.0 to %E.A*
+%4 = load %E.A, %E.A* %3
+%5 = extractvalue %E.A %4, 2
+ret i8 %5
+match.arm.0.1:
+store %E %0, %E* %slot.1
+%6 = bitcast %E* %slot.1 to %E.B*
+%7 = load %E.B, %E.B* %6
+%8 = extractvalue %E.B %7, 3
+ret i8 %8
+}
+define i32 @main() {
+%slot.0 = alloca %E
+%1 = insertvalue %E.A undef, i32 0, 0
+%2 = insertvalue %E.A %1, i8 3, 2
+%3 = bitcast %E* %slot.0 to %E.A*
+store %E.A %2, %E.A* %3
+%4 = load %E, %E* %slot.0
+%5 = call i8 @f(%E %4)
+%6 = zext i8 %5 to i32
+ret i32 %6
+}
+"
+    );
+
+    err!("(enum) (main (i32) 0)", "expected_enum_name");
+    err!("(enum E) (main (i32) 0)", "expected_variant");
+    err!("(enum E ((A) i32)) (main (i32) 0)", "expected_variant");
+    err!("(enum E (A i32 (B))) (main (i32) 0)", "expected_variant");
+    err!("(enum E A A) (main (i32) 0)", "duplicate_variant");
+    err!(
+        "(struct E (x i32)) (enum E A) (main (i32) 0)",
+        "duplicate_type"
+    );
+    err!("(enum E (A void)) (main (i32) 0)", "unexpected_void_field");
+    err!(
+        "(enum List Nil (Cons i32 List)) (main (i32) 0)",
+        "recursive_type"
+    );
+    err!(
+        "(struct S (e E)) (enum E (A S)) (main (i32) 0)",
+        "recursive_type"
+    );
+    err!("(enum E A) (main (i32) (match (A)))", "expected_match");
+    err!("(enum E A) (main (i32) (match (A) A))", "expected_arm");
+    err!(
+        "(enum E A) (main (i32) (match (A) (A 1 2)))",
+        "expected_arm"
+    );
+    err!(
+        "(enum E A) (main (i32) (match (A) (((A)) 1)))",
+        "expected_pattern"
+    );
+    err!(
+        "(enum E (A i32 i32)) (main (i32) (match (A 1 2) ((A x x) x)))",
+        "duplicate_binding"
+    );
+    err!(
+        "(enum E A) (main (i32) (match (A) (_ 1)))",
+        "expected_match_variant"
+    );
+    err!(
+        "(enum E A) (main (i32) (match (A) (B 1)))",
+        "expected_defined_symbol"
+    );
+    err!(
+        "(enum E A) (main (i32) (match (A) (main 1)))",
+        "expected_variant_pattern"
+    );
+    err!(
+        "(enum E A) (enum F B) (main (i32) (match (A) (A 1) (B 2)))",
+        "expected_variant_pattern"
+    );
+    err!(
+        "(enum E (A i32)) (main (i32) (match (A 1) (A 1)))",
+        "pattern_field_count"
+    );
+    err!(
+        "(enum E A B C) (main (i32) (match (A) (A 1)))",
+        "non_exhaustive_match"
+    );
+    err!(
+        "(enum E A B) (main (i32) (match (A) (A 1) (A 2) (B 3)))",
+        "unreachable_arm"
+    );
+    err!(
+        "(enum E A B) (main (i32) (match (A) (_ 1) (A 2)))",
+        "unreachable_arm"
+    );
+    err!(
+        "(enum E A B) (main (i32) (match (A) (A 1) (B 2) (_ 3)))",
+        "unreachable_arm"
+    );
+    err!("(enum E A) (main (i32) (match 1 (A 1)))", "type_mismatch");
+    err!(
+        "(enum E (A i32)) (main (i32) (match (A 1) ((A x) true)))",
+        "type_mismatch"
+    );
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn memory() {
+    ok!(
+        "(main (i32) (let ((xs (array i32 3) (array 1 2 3))) (at xs 2)))",
+        "\
+@str.0 = private unnamed_addr constant [26 x i8] c\"1:53: index out of bounds\\0A\"
+
+define i32 @main() {
+%slot.0 = alloca [3 x i32]
+%1 = insertvalue [3 x i32] undef, i32 1, 0
+%2 = insertvalue [3 x i32] %1, i32 2, 1
+%3 = insertvalue [3 x i32] %2, i32 3, 2
+%4 = icmp ult i64 2, 3
+br i1 %4, label %bounds.ok.0, label %bounds.fail.0
+bounds.fail.0:
+%5 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.0, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.0:
+store [3 x i32] %3, [3 x i32]* %slot.0
+%6 = getelementptr [3 x i32], [3 x i32]* %slot.0, i64 0, i64 2
+%7 = load i32, i32* %6
+ret i32 %7
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+"
+    );
+
+    ok!(
+        "(main (i32) (let ((xs (array i32 3) (array 1 2 3))) (if (== (len xs) 3) 1 0)))",
+        "\
+define i32 @main() {
+%1 = insertvalue [3 x i32] undef, i32 1, 0
+%2 = insertvalue [3 x i32] %1, i32 2, 1
+%3 = insertvalue [3 x i32] %2, i32 3, 2
+%4 = icmp eq i64 3, 3
+br i1 %4, label %if.then.0, label %if.else.0
+if.then.0:
+ret i32 1
+if.else.0:
+ret i32 0
+}
+"
+    );
+    // An array literal whose type is never written out.
+    ok!(
+        "(main (i32) (i32 (len (array 1 2 3))))",
+        "\
+define i32 @main() {
+%1 = insertvalue [3 x i32] undef, i32 1, 0
+%2 = insertvalue [3 x i32] %1, i32 2, 1
+%3 = insertvalue [3 x i32] %2, i32 3, 2
+%4 = trunc i64 3 to i32
+ret i32 %4
+}
+"
+    );
+    ok!(
+        "(main (i32) (at (at (array (array 1 2) (array 3 4)) 1) 0))",
+        "\
+@str.0 = private unnamed_addr constant [26 x i8] c\"1:17: index out of bounds\\0A\"
+@str.1 = private unnamed_addr constant [26 x i8] c\"1:13: index out of bounds\\0A\"
+
+define i32 @main() {
+%slot.0 = alloca [2 x [2 x i32]]
+%slot.1 = alloca [2 x i32]
+%1 = insertvalue [2 x i32] undef, i32 1, 0
+%2 = insertvalue [2 x i32] %1, i32 2, 1
+%3 = insertvalue [2 x [2 x i32]] undef, [2 x i32] %2, 0
+%4 = insertvalue [2 x i32] undef, i32 3, 0
+%5 = insertvalue [2 x i32] %4, i32 4, 1
+%6 = insertvalue [2 x [2 x i32]] %3, [2 x i32] %5, 1
+%7 = icmp ult i64 1, 2
+br i1 %7, label %bounds.ok.0, label %bounds.fail.0
+bounds.fail.0:
+%8 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.0, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.0:
+store [2 x [2 x i32]] %6, [2 x [2 x i32]]* %slot.0
+%9 = getelementptr [2 x [2 x i32]], [2 x [2 x i32]]* %slot.0, i64 0, i64 1
+%10 = load [2 x i32], [2 x i32]* %9
+%11 = icmp ult i64 0, 2
+br i1 %11, label %bounds.ok.1, label %bounds.fail.1
+bounds.fail.1:
+%12 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.1, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.1:
+store [2 x i32] %10, [2 x i32]* %slot.1
+%13 = getelementptr [2 x i32], [2 x i32]* %slot.1, i64 0, i64 0
+%14 = load i32, i32* %13
+ret i32 %14
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+"
+    );
+
+    ok!(
+        "(swap ((s (slice f64)) (i i64) (j i64) void) (let ((x f64 (at s i))) (do (set-at! s i (at s j)) (set-at! s j x)))) (main (i32) 0)",
+        "\
+@str.0 = private unnamed_addr constant [26 x i8] c\"1:59: index out of bounds\\0A\"
+@str.1 = private unnamed_addr constant [26 x i8] c\"1:87: index out of bounds\\0A\"
+@str.2 = private unnamed_addr constant [26 x i8] c\"1:74: index out of bounds\\0A\"
+@str.3 = private unnamed_addr constant [26 x i8] c\"1:97: index out of bounds\\0A\"
+
+define void @swap({ double*, i64 } %0, i64 %1, i64 %2) {
+%4 = extractvalue { double*, i64 } %0, 0
+%5 = extractvalue { double*, i64 } %0, 1
+%6 = icmp ult i64 %1, %5
+br i1 %6, label %bounds.ok.0, label %bounds.fail.0
+bounds.fail.0:
+%7 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.0, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.0:
+%8 = getelementptr double, double* %4, i64 %1
+%9 = load double, double* %8
+%10 = extractvalue { double*, i64 } %0, 0
+%11 = extractvalue { double*, i64 } %0, 1
+%12 = icmp ult i64 %2, %11
+br i1 %12, label %bounds.ok.1, label %bounds.fail.1
+bounds.fail.1:
+%13 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.1, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.1:
+%14 = getelementptr double, double* %10, i64 %2
+%15 = load double, double* %14
+%16 = extractvalue { double*, i64 } %0, 0
+%17 = extractvalue { double*, i64 } %0, 1
+%18 = icmp ult i64 %1, %17
+br i1 %18, label %bounds.ok.2, label %bounds.fail.2
+bounds.fail.2:
+%19 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.2, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.2:
+%20 = getelementptr double, double* %16, i64 %1
+store double %15, double* %20
+%21 = extractvalue { double*, i64 } %0, 0
+%22 = extractvalue { double*, i64 } %0, 1
+%23 = icmp ult i64 %2, %22
+br i1 %23, label %bounds.ok.3, label %bounds.fail.3
+bounds.fail.3:
+%24 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.3, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.3:
+%25 = getelementptr double, double* %21, i64 %2
+store double %9, double* %25
+ret void
+}
+define i32 @main() {
+ret i32 0
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+"
+    );
+
+    ok!(
+        "(view ((s (slice i64)) (ptr i64)) (data s)) (size ((s (slice i64)) i64) (len s)) (main (i32) 0)",
+        "\
+define i64* @view({ i64*, i64 } %0) {
+%2 = extractvalue { i64*, i64 } %0, 0
+ret i64* %2
+}
+define i64 @size({ i64*, i64 } %0) {
+%2 = extractvalue { i64*, i64 } %0, 1
+ret i64 %2
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+
+    ok!(
+        "(second ((p (ptr i64)) i64) (do (store! (offset p 1) 5) (load (offset p 1)))) (view ((p (ptr f32)) (n i64) (slice f32)) (slice p n)) (raw ((p (ptr void)) (ptr void)) p) (main (i32) 0)",
+        "\
+define i64 @second(i64* %0) {
+%2 = getelementptr i64, i64* %0, i64 1
+store i64 5, i64* %2
+%3 = getelementptr i64, i64* %0, i64 1
+%4 = load i64, i64* %3
+ret i64 %4
+}
+define { float*, i64 } @view(float* %0, i64 %1) {
+%3 = insertvalue { float*, i64 } undef, float* %0, 0
+%4 = insertvalue { float*, i64 } %3, i64 %1, 1
+ret { float*, i64 } %4
+}
+define i8* @raw(i8* %0) {
+ret i8* %0
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+
+    ok!(
+        "(struct Point (x i32) (y i32)) (struct Path (points (array Point 2))) (end ((p Path) i32) (.y (at (.points p) 1))) (main (i32) 0)",
+        "\
+%Point = type { i32, i32 }
+%Path = type { [2 x %Point] }
+
+@str.0 = private unnamed_addr constant [26 x i8] c\"1:95: index out of bounds\\0A\"
+
+define i32 @end(%Path %0) {
+%slot.0 = alloca [2 x %Point]
+%2 = extractvalue %Path %0, 0
+%3 = icmp ult i64 1, 2
+br i1 %3, label %bounds.ok.0, label %bounds.fail.0
+bounds.fail.0:
+%4 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.0, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.0:
+store [2 x %Point] %2, [2 x %Point]* %slot.0
+%5 = getelementptr [2 x %Point], [2 x %Point]* %slot.0, i64 0, i64 1
+%6 = load %Point, %Point* %5
+%7 = extractvalue %Point %6, 1
+ret i32 %7
+}
+define i32 @main() {
+ret i32 0
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+"
+    );
+
+    ok!(
+        "(main (i32) (let ((grid (array (array i32 2) 2) (array (array 1 2) (array 3 4)))) (at (at grid 1) 0)))",
+        "\
+@str.0 = private unnamed_addr constant [26 x i8] c\"1:87: index out of bounds\\0A\"
+@str.1 = private unnamed_addr constant [26 x i8] c\"1:83: index out of bounds\\0A\"
+
+define i32 @main() {
+%slot.0 = alloca [2 x [2 x i32]]
+%slot.1 = alloca [2 x i32]
+%1 = insertvalue [2 x i32] undef, i32 1, 0
+%2 = insertvalue [2 x i32] %1, i32 2, 1
+%3 = insertvalue [2 x [2 x i32]] undef, [2 x i32] %2, 0
+%4 = insertvalue [2 x i32] undef, i32 3, 0
+%5 = insertvalue [2 x i32] %4, i32 4, 1
+%6 = insertvalue [2 x [2 x i32]] %3, [2 x i32] %5, 1
+%7 = icmp ult i64 1, 2
+br i1 %7, label %bounds.ok.0, label %bounds.fail.0
+bounds.fail.0:
+%8 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.0, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.0:
+store [2 x [2 x i32]] %6, [2 x [2 x i32]]* %slot.0
+%9 = getelementptr [2 x [2 x i32]], [2 x [2 x i32]]* %slot.0, i64 0, i64 1
+%10 = load [2 x i32], [2 x i32]* %9
+%11 = icmp ult i64 0, 2
+br i1 %11, label %bounds.ok.1, label %bounds.fail.1
+bounds.fail.1:
+%12 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.1, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.1:
+store [2 x i32] %10, [2 x i32]* %slot.1
+%13 = getelementptr [2 x i32], [2 x i32]* %slot.1, i64 0, i64 0
+%14 = load i32, i32* %13
+ret i32 %14
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+"
+    );
+
+    err!("(f ((p (ptr)) i32) 0) (main (i32) 0)", "expected_type_form");
+    err!(
+        "(f ((p (slice i32 2)) i32) 0) (main (i32) 0)",
+        "expected_type_form"
+    );
+    err!(
+        "(f ((p (ptr (i32))) i32) 0) (main (i32) 0)",
+        "expected_type_form"
+    );
+    err!(
+        "(f ((xs (array i32 -1)) i32) 0) (main (i32) 0)",
+        "expected_array_len"
+    );
+    err!(
+        "(f ((xs (array i32 n)) i32) 0) (main (i32) 0)",
+        "expected_array_len"
+    );
+    err!(
+        "(f ((s (slice void)) i32) 0) (main (i32) 0)",
+        "unexpected_void_element"
+    );
+    err!(
+        "(f ((xs (array void 2)) i32) 0) (main (i32) 0)",
+        "unexpected_void_element"
+    );
+    err!(
+        "(f ((xs (array Foo 2)) i32) 0) (main (i32) 0)",
+        "expected_defined_type"
+    );
+    err!(
+        "(struct S (xs (array S 2))) (main (i32) 0)",
+        "recursive_type"
+    );
+    err!(
+        "(main (i32) (let ((xs (array i32 3) (array 1 2))) 0))",
+        "no_type_match"
+    );
+    err!(
+        "(main (i32) (let ((xs (array i32 2) (array 1 2))) (at xs 1.5)))",
+        "no_type_match"
+    );
+    err!(
+        "(main (i32) (let ((xs (array i64 2) (array 1 2))) (at xs 0)))",
+        "no_type_match"
+    );
+    err!(
+        "(f ((p (ptr void)) i32) (load p)) (main (i32) 0)",
+        "expected_defined_symbol"
+    );
+}
+
+#[test]
+fn string_literal() {
+    ok!(
+        "(main (i32) (if (== (len \"a (b)\\n\") 6) 0 1))",
+        "\
+@str.0 = private unnamed_addr constant [7 x i8] c\"a (b)\\0A\\00\"
+
+define i32 @main() {
+%1 = insertvalue { i8*, i64 } undef, i8* getelementptr inbounds ([7 x i8], [7 x i8]* @str.0, i64 0, i64 0), 0
+%2 = insertvalue { i8*, i64 } %1, i64 6, 1
+%3 = extractvalue { i8*, i64 } %2, 1
+%4 = icmp eq i64 %3, 6
+br i1 %4, label %if.then.0, label %if.else.0
+if.then.0:
+ret i32 0
+if.else.0:
+ret i32 1
+}
+"
+    );
+    ok!(
+        "(f ((p (ptr u8)) u8) (load p)) (main (i32) (if (== (f \"\\x41\\\"\") (f \"\\x41\\\"\")) 0 1))",
+        "\
+@str.0 = private unnamed_addr constant [3 x i8] c\"A\\22\\00\"
+
+define i8 @f(i8* %0) {
+%2 = load i8, i8* %0
+ret i8 %2
+}
+define i32 @main() {
+%1 = call i8 @f(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @str.0, i64 0, i64 0))
+%2 = call i8 @f(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @str.0, i64 0, i64 0))
+%3 = icmp eq i8 %1, %2
+br i1 %3, label %if.then.0, label %if.else.0
+if.then.0:
+ret i32 0
+if.else.0:
+ret i32 1
+}
+"
+    );
+    ok!(
+        "(main (i32) (let ((s (slice u8) \"\\t\\0\\\\\")) (if (== (at s 2) 92) 0 1)))",
+        "\
+@str.0 = private unnamed_addr constant [4 x i8] c\"\\09\\00\\5C\\00\"
+@str.1 = private unnamed_addr constant [26 x i8] c\"1:52: index out of bounds\\0A\"
+
+define i32 @main() {
+%1 = insertvalue { i8*, i64 } undef, i8* getelementptr inbounds ([4 x i8], [4 x i8]* @str.0, i64 0, i64 0), 0
+%2 = insertvalue { i8*, i64 } %1, i64 3, 1
+%3 = extractvalue { i8*, i64 } %2, 0
+%4 = extractvalue { i8*, i64 } %2, 1
+%5 = icmp ult i64 2, %4
+br i1 %5, label %bounds.ok.0, label %bounds.fail.0
+bounds.fail.0:
+%6 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.1, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.0:
+%7 = getelementptr i8, i8* %3, i64 2
+%8 = load i8, i8* %7
+%9 = icmp eq i8 %8, 92
+br i1 %9, label %if.then.1, label %if.else.1
+if.then.1:
+ret i32 0
+if.else.1:
+ret i32 1
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+"
+    );
+    err!("(main (i32) (do \"abc 0))", "unterminated_string");
+    err!("(main (i32) (do \"abc\\\"))", "unterminated_string");
+    err!("(main (i32) (do \"\\q\" 0))", "invalid_string");
+    err!("(main (i32) (do \"\\x4\" 0))", "invalid_string");
+    err!("(main (i32) (do \"ab\"c 0))", "invalid_string");
+    err!("(main (i32) \"abc\")", "type_mismatch");
+    err!(
+        "(main (i32) (let ((s (slice i8) \"abc\")) 0))",
+        "type_mismatch"
+    );
+}
+
+#[test]
+fn extern_decl() {
+    ok!(
+        "(extern puts ((s ptr) i32)) (main (i32) (puts \"hi\"))",
+        "\
+@str.0 = private unnamed_addr constant [3 x i8] c\"hi\\00\"
+
+define i32 @main() {
+%1 = tail call i32 @puts(i8* getelementptr inbounds ([3 x i8], [3 x i8]* @str.0, i64 0, i64 0))
+ret i32 %1
+}
+declare i32 @puts(i8*)
+"
+    );
+    ok!(
+        "(extern abs (i32 i32)) (f ((x i32) i32) (tail abs x)) (main (i32) (f 1))",
+        "\
+define i32 @f(i32 %0) {
+%2 = tail call i32 @abs(i32 %0)
+ret i32 %2
+}
+define i32 @main() {
+%1 = tail call i32 @f(i32 1)
+ret i32 %1
+}
+declare i32 @abs(i32)
+"
+    );
+    ok!(
+        "(extern write (i32 ptr i64 i64)) (main (i32) (let ((s (slice u8) \"ab\")) (if (== (at s 1) 98) 0 1)))",
+        "\
+@str.0 = private unnamed_addr constant [3 x i8] c\"ab\\00\"
+@str.1 = private unnamed_addr constant [26 x i8] c\"1:81: index out of bounds\\0A\"
+
+define i32 @main() {
+%1 = insertvalue { i8*, i64 } undef, i8* getelementptr inbounds ([3 x i8], [3 x i8]* @str.0, i64 0, i64 0), 0
+%2 = insertvalue { i8*, i64 } %1, i64 2, 1
+%3 = extractvalue { i8*, i64 } %2, 0
+%4 = extractvalue { i8*, i64 } %2, 1
+%5 = icmp ult i64 1, %4
+br i1 %5, label %bounds.ok.0, label %bounds.fail.0
+bounds.fail.0:
+%6 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.1, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.0:
+%7 = getelementptr i8, i8* %3, i64 1
+%8 = load i8, i8* %7
+%9 = icmp eq i8 %8, 98
+br i1 %9, label %if.then.1, label %if.else.1
+if.then.1:
+ret i32 0
+if.else.1:
+ret i32 1
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+"
+    );
+    err!(
+        "(extern puts ((s ptr) i32) 0) (main (i32) 0)",
+        "unexpected_extern_token"
+    );
+    err!(
+        "(extern puts) (main (i32) 0)",
+        "expected_func_type_after_name"
+    );
+    err!("(extern (puts) (i32)) (main (i32) 0)", "expected_name");
+    err!(
+        "(f (i32) 0) (extern f (i32)) (main (i32) 0)",
+        "duplicate_extern"
+    );
+    err!(
+        "(extern f (i32)) (extern f (i64)) (main (i32) 0)",
+        "duplicate_extern"
+    );
+    err!(
+        "(extern + (i32 i32 i32)) (main (i32) 0)",
+        "duplicate_extern"
+    );
+    err!("(write (i32) 0) (main (i32) (write))", "runtime_name");
+    err!("(malloc ((x i64) i64) x) (main (i32) 0)", "runtime_name");
+    err!(
+        "(extern malloc ((n i64) i64)) (main (i32) 0)",
+        "runtime_extern"
+    );
+    err!(
+        "(extern write (i32 ptr i64 i32)) (main (i32) 0)",
+        "runtime_extern"
+    );
+    err!(
+        "(extern f ((x foo) i32)) (main (i32) 0)",
+        "expected_defined_type"
+    );
+    err!(
+        "(extern puts ((s ptr) i32)) (main (i32) (puts 1))",
+        "type_mismatch"
+    );
+    err!("(extern abs (i32 i32)) (main (i32) abs)", "type_mismatch");
+    err!(
+        "(extern abs (i32 i32)) (main (i32) (tail abs 1))",
+        "tail_type_mismatch"
+    );
+}
+
+/// Compiles IR to assembly with `llc`, which rejects some IR that the
+/// assembler accepts, such as a `musttail` call whose prototype does not
+/// match. Returns `None` when `llc` is not installed.
+fn llc(code: &str) -> Option<bool> {
+    use std::io::Write;
+    use std::process::Command;
+    use std::process::Stdio;
+
+    let mut llc = Command::new("llc")
+        .args(["-o", "/dev/null"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .ok()?;
+    llc.stdin
+        .take()
+        .unwrap()
+        .write_all(code.as_bytes())
+        .unwrap();
+    Some(llc.wait().unwrap().success())
+}
+
+#[test]
+fn export_def_llc() {
+    for text in [
+        "(export f ((x u8) u8) (f x)) (main (i32) (i32 (f 1u8)))",
+        "(export f ((x i16) i16) x) (main (i32) (let ((g (i16 i16) f)) (i32 (g 1i16))))",
+    ] {
+        let code = emit(text, &Options::default()).unwrap();
+        match llc(&code) {
+            Some(ok) => assert!(ok, "llc rejected:\n{}", code),
+            None => eprintln!("llc is not installed, so it was skipped"),
+        }
+    }
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn export_def() {
+    ok!(
+        "(export add ((a i32) (b i32) i32) (+ a b)) (main (i32) (add 1 2))",
+        "\
+define i32 @add(i32 %0, i32 %1) {
+%3 = add i32 %0, %1
+ret i32 %3
+}
+define i32 @main() {
+%1 = tail call i32 @add(i32 1, i32 2)
+ret i32 %1
+}
+"
+    );
+    ok!(
+        "(export small ((x i8) (y u16) bool) true) (main (i32) 0)",
+        "\
+define zeroext i1 @small(i8 signext %0, i16 zeroext %1) {
+ret i1 true
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    // Calls extend narrow integers the way the prototype says, which a
+    // `musttail` call needs to match.
+    ok!(
+        "(export f ((x u8) u8) (f x)) (main (i32) (i32 (f 1u8)))",
+        "\
+define zeroext i8 @f(i8 zeroext %0) {
+%2 = musttail call zeroext i8 @f(i8 zeroext %0)
+ret i8 %2
+}
+define i32 @main() {
+%1 = call zeroext i8 @f(i8 zeroext 1)
+%2 = zext i8 %1 to i32
+ret i32 %2
+}
+"
+    );
+    ok!(
+        "(export f ((x i16) i16) x) (main (i32) (let ((g (i16 i16) f)) (i32 (g 1i16))))",
+        "\
+define signext i16 @f(i16 signext %0) {
+ret i16 %0
+}
+define i32 @main() {
+%1 = insertvalue { i16 (i8*, i16)*, i8* } undef, i16 (i8*, i16)* @closure.f, 0
+%2 = insertvalue { i16 (i8*, i16)*, i8* } %1, i8* null, 1
+%3 = extractvalue { i16 (i8*, i16)*, i8* } %2, 0
+%4 = extractvalue { i16 (i8*, i16)*, i8* } %2, 1
+%5 = call i16 %3(i8* %4, i16 1)
+%6 = sext i16 %5 to i32
+ret i32 %6
+}
+define i16 @closure.f(i8* %0, i16 %1) {
+%3 = tail call signext i16 @f(i16 signext %1)
+ret i16 %3
+}
+"
+    );
+    ok!(
+        "(export ((x i32) i32) x) (main (i32) (export 0))",
+        "\
+define i32 @export(i32 %0) {
+ret i32 %0
+}
+define i32 @main() {
+%1 = tail call i32 @export(i32 0)
+ret i32 %1
+}
+"
+    );
+    err!(
+        "(export add-one ((a i32) i32) a) (main (i32) 0)",
+        "invalid_export_name"
+    );
+    err!(
+        "(export f ((a i32) i32) a) (f ((a i64) i64) a) (main (i32) 0)",
+        "overloaded_export"
+    );
+    err!(
+        "(struct P (x i32)) (export f ((p P) i32) 0) (main (i32) 0)",
+        "unexported_type"
+    );
+    err!(
+        "(export f ((s (slice u8)) i32) 0) (main (i32) 0)",
+        "unexported_type"
+    );
+    err!(
+        "(export f ((p (ptr (array i32 2))) i32) 0) (main (i32) 0)",
+        "unexported_type"
+    );
+    err!(
+        "(export f (i32) 0) (extern f (i32)) (main (i32) 0)",
+        "duplicate_extern"
+    );
+
+    let code = header(
+        "(struct P (x i32)) \
+         (export f ((p (ptr P)) (q (ptr (ptr P))) (b bool) (x u8) f64) 0.5) \
+         (export g ((p (ptr i32)) void) (store! p 0)) \
+         (h ((x i32) i32) x) \
+         (main (i32) 0)",
+        "my-lib",
+    )
+    .unwrap();
+    assert_eq!(
+        code,
+        "\
+#ifndef MY_LIB_H
+#define MY_LIB_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+struct P;
+
+double f(struct P*, struct P**, bool, uint8_t);
+void g(int32_t*);
+
+#endif /* MY_LIB_H */
+"
+    );
+}
+
+#[test]
+fn terminal_ops() {
+    const TERMINALS: [(&str, &str); 12] = [
+        ("bool", "i1"),
+        ("u8", "i8"),
+        ("u16", "i16"),
+        ("u32", "i32"),
+        ("u64", "i64"),
+        ("i8", "i8"),
+        ("i16", "i16"),
+        ("i32", "i32"),
+        ("i64", "i64"),
+        ("f16", "half"),
+        ("f32", "float"),
+        ("f64", "double"),
+    ];
+
+    // The instruction expected for each operator on each terminal above, or
+    // "" if the operator does not accept that terminal.
+    #[rustfmt::skip]
+    const OPS: [(&str, [&str; 12]); 19] = [
+        ("!", ["xor", "", "", "", "", "", "", "", "", "", "", ""]),
+        ("~", ["", "xor", "xor", "xor", "xor", "xor", "xor", "xor", "xor", "", "", ""]),
+        ("+", ["", "add", "add", "add", "add", "add", "add", "add", "add", "fadd", "fadd", "fadd"]),
+        ("-", ["", "sub", "sub", "sub", "sub", "sub", "sub", "sub", "sub", "fsub", "fsub", "fsub"]),
+        ("*", ["", "mul", "mul", "mul", "mul", "mul", "mul", "mul", "mul", "fmul", "fmul", "fmul"]),
+        ("/", ["", "udiv", "udiv", "udiv", "udiv", "sdiv", "sdiv", "sdiv", "sdiv", "fdiv", "fdiv", "fdiv"]),
+        ("%", ["", "urem", "urem", "urem", "urem", "srem", "srem", "srem", "srem", "frem", "frem", "frem"]),
+        ("&", ["", "and", "and", "and", "and", "and", "and", "and", "and", "", "", ""]),
+        ("|", ["", "or", "or", "or", "or", "or", "or", "or", "or", "", "", ""]),
+        ("^", ["", "xor", "xor", "xor", "xor", "xor", "xor", "xor", "xor", "", "", ""]),
+        ("<<", ["", "shl", "shl", "shl", "shl", "shl", "shl", "shl", "shl", "", "", ""]),
+        (">>", ["", "lshr", "lshr", "lshr", "lshr", "ashr", "ashr", "ashr", "ashr", "", "", ""]),
+        ("^^", ["xor", "", "", "", "", "", "", "", "", "", "", ""]),
+        ("==", ["icmp eq", "icmp eq", "icmp eq", "icmp eq", "icmp eq", "icmp eq", "icmp eq", "icmp eq", "icmp eq", "fcmp oeq", "fcmp oeq", "fcmp oeq"]),
+        ("!=", ["icmp ne", "icmp ne", "icmp ne", "icmp ne", "icmp ne", "icmp ne", "icmp ne", "icmp ne", "icmp ne", "fcmp une", "fcmp une", "fcmp une"]),
+        ("<=", ["", "icmp ule", "icmp ule", "icmp ule", "icmp ule", "icmp sle", "icmp sle", "icmp sle", "icmp sle", "fcmp ole", "fcmp ole", "fcmp ole"]),
+        (">=", ["", "icmp uge", "icmp uge", "icmp uge", "icmp uge", "icmp sge", "icmp sge", "icmp sge", "icmp sge", "fcmp oge", "fcmp oge", "fcmp oge"]),
+        ("<", ["", "icmp ult", "icmp ult", "icmp ult", "icmp ult", "icmp slt", "icmp slt", "icmp slt", "icmp slt", "fcmp olt", "fcmp olt", "fcmp olt"]),
+        (">", ["", "icmp ugt", "icmp ugt", "icmp ugt", "icmp ugt", "icmp sgt", "icmp sgt", "icmp sgt", "icmp sgt", "fcmp ogt", "fcmp ogt", "fcmp ogt"]),
+    ];
+
+    for (op, instructions) in &OPS {
+        for ((terminal, llvm_type), instruction) in TERMINALS.iter().zip(instructions) {
+            let unary = *op == "!" || *op == "~";
+            let ret = if instruction.starts_with("icmp") || instruction.starts_with("fcmp") {
+                "bool"
+            } else {
+                terminal
+            };
+            let text = if unary {
+                format!("(f ((a {terminal}) {ret}) ({op} a)) (main (i32) 0)")
+            } else {
+                format!("(f ((a {terminal}) (b {terminal}) {ret}) ({op} a b)) (main (i32) 0)")
+            };
+            let expected = match (unary, *op) {
+                (_, _) if instruction.is_empty() => None,
+                (true, "!") => Some(format!("%2 = xor {llvm_type} %0, true\n")),
+                (true, _) => Some(format!("%2 = xor {llvm_type} %0, -1\n")),
+                (false, _) => Some(format!("%3 = {instruction} {llvm_type} %0, %1\n")),
+            };
+
+            match (emit(&text, &Options::default()), expected) {
+                (Ok(code), Some(expected)) => {
+                    assert!(code.contains(&expected), "{}\n{}", text, code);
+                }
+                (Err(error), None) => assert!(
+                    ["no_type_match", "func_type_mismatch"].contains(&error.name()),
+                    "{}\n{}",
+                    text,
+                    error.name()
+                ),
+                (Ok(code), None) => panic!("expected an error:\n{}\n{}", text, code),
+                (Err(error), Some(_)) => panic!("{}\n{}", text, error.name()),
+            }
+        }
+    }
+}
+
+#[test]
+fn literal() {
+    ok!(
+        "(f ((x u8) (y i64) u8) (+ x 255)) (main (i32) 007)",
+        "\
+define i8 @f(i8 %0, i64 %1) {
+%3 = add i8 %0, 255
+ret i8 %3
+}
+define i32 @main() {
+ret i32 7
+}
+"
+    );
+    ok!(
+        "(f ((x u64) u64) (+ x 18446744073709551615)) (main (i32) 0)",
+        "\
+define i64 @f(i64 %0) {
+%2 = add i64 %0, 18446744073709551615
+ret i64 %2
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    ok!(
+        "(h ((x f16) f16) (+ x 0.1)) (f ((x f32) f32) (* x 0.1)) (d ((x f64) f64) (- x 0.1)) (main (i32) 0)",
+        "\
+define half @h(half %0) {
+%2 = fadd half %0, 0xH2E66
+ret half %2
+}
+define float @f(float %0) {
+%2 = fmul float %0, 0x3FB99999A0000000
+ret float %2
+}
+define double @d(double %0) {
+%2 = fsub double %0, 0.1
+ret double %2
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    ok!(
+        "(f ((x f32) f32) (+ x 2.5)) (h ((x f16) f16) (+ x 65504.0)) (main (i32) 0)",
+        "\
+define float @f(float %0) {
+%2 = fadd float %0, 2.5
+ret float %2
+}
+define half @h(half %0) {
+%2 = fadd half %0, 0xH7BFF
+ret half %2
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    ok!(
+        "(h ((x f16) f16) (+ x 0.00000006)) (main (i32) 0)",
+        "\
+define half @h(half %0) {
+%2 = fadd half %0, 0xH0001
+ret half %2
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    err!("(main (i32) 2147483648)", "literal_out_of_range");
+    err!(
+        "(f ((x u8) u8) (+ x 256)) (main (i32) 0)",
+        "literal_out_of_range"
+    );
+    err!(
+        "(f ((x i8) i8) (+ x 128)) (main (i32) 0)",
+        "literal_out_of_range"
+    );
+    err!(
+        "(main (i32) (let ((x u64 18446744073709551616)) 0))",
+        "literal_out_of_range"
+    );
+    err!(
+        "(main (i32) (let ((x i64 100000000000000000000000000000000000000000)) 0))",
+        "literal_out_of_range"
+    );
+    err!(
+        "(main (i32) (let ((x f16 65520.0)) 0))",
+        "literal_out_of_range"
+    );
+    err!(
+        "(main (i32) (let ((x f32 1000000000000000000000000000000000000000.0)) 0))",
+        "literal_out_of_range"
+    );
+}
+
+#[test]
+fn literal_syntax() {
+    ok!(
+        "(f ((x i32) i32) (+ x (+ 0xFF (+ 0b1010 (+ 0o17 (+ 1_000_000 -0x1_0)))))) (main (i32) 0)",
+        "\
+define i32 @f(i32 %0) {
+%2 = add i32 1000000, -16
+%3 = add i32 15, %2
+%4 = add i32 10, %3
+%5 = add i32 255, %4
+%6 = add i32 %0, %5
+ret i32 %6
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    ok!(
+        "(f ((x f64) f64) (+ x (+ 1e-9 (+ -2.5E3 1_0.2_5e+1_0)))) (main (i32) 0)",
+        "\
+define double @f(double %0) {
+%2 = fadd double -2500.0, 102500000000.0
+%3 = fadd double 0x3E112E0BE826D695, %2
+%4 = fadd double %0, %3
+ret double %4
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    ok!(
+        "(f ((x u8) u8) x) (f ((x i64) i64) x) (f ((x f32) f32) x) \
+         (main (i32) (let ((a u8 (f 42u8)) (b i64 (f 0xFFi64)) (c f32 (f 1.5f32)) (d f32 (f 2f32))) 0))",
+        "\
+define i8 @f(i8 %0) {
+ret i8 %0
+}
+define i64 @f1(i64 %0) {
+ret i64 %0
+}
+define float @f2(float %0) {
+ret float %0
+}
+define i32 @main() {
+%1 = call i8 @f(i8 42)
+%2 = call i64 @f1(i64 255)
+%3 = call float @f2(float 1.5)
+%4 = call float @f2(float 2.0)
+ret i32 0
+}
+"
+    );
+    err!("(main (i32) (let ((x i32 42u8)) 0))", "type_mismatch");
+    err!("(main (i32) (let ((x u8 -1)) 0))", "literal_out_of_range");
+    err!("(main (i32) (let ((x u8 -1u8)) 0))", "literal_out_of_range");
+    err!(
+        "(main (i32) (let ((x f64 1e400)) 0))",
+        "literal_out_of_range"
+    );
+    err!("(main (i32) (let ((x i8 0x80)) 0))", "literal_out_of_range");
+    err!("(main (i32) 0x)", "invalid_number");
+    err!("(main (i32) 0b102)", "invalid_number");
+    err!("(main (i32) _1)", "expected_defined_symbol");
+    err!("(main (i32) 1.)", "invalid_number");
+    err!("(main (i32) 1.5i32)", "invalid_number");
+    err!("(main (i32) 1e)", "invalid_number");
+    err!("(main (i32) 12abc)", "invalid_number");
+    err!("(main (i32) -0o8)", "invalid_number");
+    err!("(main (i32) 1__2)", "invalid_number");
+    err!("(main (i32) 1_)", "invalid_number");
+    err!("(main (i32) 0x_1)", "invalid_number");
+    err!("(main (i32) 1_.5)", "invalid_number");
+}
+
+#[test]
+fn conversion() {
+    // The instruction expected for converting between two terminals, or ""
+    // if they share an LLVM type.
+    const CASTS: [(&str, &str, &str); 22] = [
+        ("bool", "u8", "zext"),
+        ("bool", "i64", "zext"),
+        ("bool", "f32", "uitofp"),
+        ("u8", "u64", "zext"),
+        ("u16", "i32", "zext"),
+        ("i8", "i16", "sext"),
+        ("i32", "u64", "sext"),
+        ("u64", "u8", "trunc"),
+        ("i64", "i32", "trunc"),
+        ("u32", "i32", ""),
+        ("i8", "u8", ""),
+        ("i64", "i64", ""),
+        ("u32", "f64", "uitofp"),
+        ("i16", "f16", "sitofp"),
+        ("f64", "u32", "fptoui"),
+        ("f32", "i8", "fptosi"),
+        ("f16", "f32", "fpext"),
+        ("f32", "f64", "fpext"),
+        ("f64", "f16", "fptrunc"),
+        ("f64", "f32", "fptrunc"),
+        ("f32", "f32", ""),
+        ("f16", "u64", "fptoui"),
+    ];
+    fn llvm_type(terminal: &str) -> &str {
+        match terminal {
+            "bool" => "i1",
+            "u8" => "i8",
+            "u16" => "i16",
+            "u32" => "i32",
+            "u64" => "i64",
+            "f16" => "half",
+            "f32" => "float",
+            "f64" => "double",
+            terminal => terminal,
+        }
+    }
+
+    for (from, to, instruction) in &CASTS {
+        let text = format!("(f ((x {from}) {to}) ({to} x)) (main (i32) 0)");
+        let code = match emit(&text, &Options::default()) {
+            Ok(code) => code,
+            Err(error) => panic!("{}\n{}", text, error.name()),
+        };
+        let expected = if instruction.is_empty() {
+            format!("ret {} %0\n", llvm_type(to))
+        } else {
+            format!(
+                "%2 = {} {} %0 to {}\n",
+                instruction,
+                llvm_type(from),
+                llvm_type(to)
+            )
+        };
+        assert!(code.contains(&expected), "{}\n{}", text, code);
+    }
+
+    ok!(
+        "(f ((x i32) f64) (f64 x)) (g ((x f64) u8) (u8 x)) (main (i32) (i32 (g (f 300))))",
+        "\
+define double @f(i32 %0) {
+%2 = sitofp i32 %0 to double
+ret double %2
+}
+define i8 @g(double %0) {
+%2 = fptoui double %0 to i8
+ret i8 %2
+}
+define i32 @main() {
+%1 = call double @f(i32 300)
+%2 = call i8 @g(double %1)
+%3 = zext i8 %2 to i32
+ret i32 %3
+}
+"
+    );
+    ok!(
+        "(main (i32) (i32 7))",
+        "\
+define i32 @main() {
+ret i32 7
+}
+"
+    );
+
+    err!("(main (i32) (let ((x bool (bool 1))) 0))", "expected_func");
+    err!("(main (i32) (let ((x i64 (i64 1 2))) 0))", "no_type_match");
+    err!(
+        "(main (i32) (let ((xs (array i32 1) (array 1))) (i32 xs)))",
+        "no_type_match"
+    );
+    err!("(main (i32) (i64 1))", "no_type_match");
+}
+
+#[test]
+fn short_circuit() {
+    ok!(
+        "(f ((a bool) (b bool) bool) (&& a b)) (main (i32) 0)",
+        "\
+define i1 @f(i1 %0, i1 %1) {
+br i1 %0, label %and.rhs.0, label %and.end.0
+and.rhs.0:
+br label %and.end.0
+and.end.0:
+%3 = phi i1 [false, %2], [%1, %and.rhs.0]
+ret i1 %3
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    ok!(
+        "(f ((a bool) (b bool) bool) (|| a b)) (main (i32) 0)",
+        "\
+define i1 @f(i1 %0, i1 %1) {
+br i1 %0, label %or.end.0, label %or.rhs.0
+or.rhs.0:
+br label %or.end.0
+or.end.0:
+%3 = phi i1 [true, %2], [%1, %or.rhs.0]
+ret i1 %3
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    ok!(
+        "(f ((a bool) (b bool) (c bool) bool) (|| (&& a b) c)) (main (i32) 0)",
+        "\
+define i1 @f(i1 %0, i1 %1, i1 %2) {
+br i1 %0, label %and.rhs.0, label %and.end.0
+and.rhs.0:
+br label %and.end.0
+and.end.0:
+%4 = phi i1 [false, %3], [%1, %and.rhs.0]
+br i1 %4, label %or.end.1, label %or.rhs.1
+or.rhs.1:
+br label %or.end.1
+or.end.1:
+%5 = phi i1 [true, %and.end.0], [%2, %or.rhs.1]
+ret i1 %5
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    ok!(
+        "(f ((s (slice i64)) (i i64) bool) (&& (< i (len s)) (== (at s i) 0))) (main (i32) 0)",
+        "\
+@str.0 = private unnamed_addr constant [26 x i8] c\"1:57: index out of bounds\\0A\"
+
+define i1 @f({ i64*, i64 } %0, i64 %1) {
+%3 = extractvalue { i64*, i64 } %0, 1
+%4 = icmp slt i64 %1, %3
+br i1 %4, label %and.rhs.0, label %and.end.0
+and.rhs.0:
+%5 = extractvalue { i64*, i64 } %0, 0
+%6 = extractvalue { i64*, i64 } %0, 1
+%7 = icmp ult i64 %1, %6
+br i1 %7, label %bounds.ok.1, label %bounds.fail.1
+bounds.fail.1:
+%8 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.0, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+bounds.ok.1:
+%9 = getelementptr i64, i64* %5, i64 %1
+%10 = load i64, i64* %9
+%11 = icmp eq i64 %10, 0
+br label %and.end.0
+and.end.0:
+%12 = phi i1 [false, %2], [%11, %bounds.ok.1]
+ret i1 %12
+}
+define i32 @main() {
+ret i32 0
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+"
+    );
+    err!(
+        "(f ((a i32) (b i32) bool) (&& a b)) (main (i32) 0)",
+        "type_mismatch"
+    );
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn checked() {
+    ok!(
+        "(checked f ((a i32) (b i32) i32) (+ a b)) (main (i32) 0)",
+        "\
+@str.0 = private unnamed_addr constant [26 x i8] c\"1:34: arithmetic overflow\\0A\"
+
+define i32 @f(i32 %0, i32 %1) {
+%3 = call { i32, i1 } @llvm.sadd.with.overflow.i32(i32 %0, i32 %1)
+%4 = extractvalue { i32, i1 } %3, 0
+%5 = extractvalue { i32, i1 } %3, 1
+br i1 %5, label %overflow.fail.0, label %overflow.ok.0
+overflow.fail.0:
+%6 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.0, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+overflow.ok.0:
+ret i32 %4
+}
+define i32 @main() {
+ret i32 0
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+declare { i32, i1 } @llvm.sadd.with.overflow.i32(i32, i32)
+"
+    );
+    ok!(
+        "(checked f ((a u64) (b u64) u64) (* a b)) (main (i32) 0)",
+        "\
+@str.0 = private unnamed_addr constant [26 x i8] c\"1:34: arithmetic overflow\\0A\"
+
+define i64 @f(i64 %0, i64 %1) {
+%3 = call { i64, i1 } @llvm.umul.with.overflow.i64(i64 %0, i64 %1)
+%4 = extractvalue { i64, i1 } %3, 0
+%5 = extractvalue { i64, i1 } %3, 1
+br i1 %5, label %overflow.fail.0, label %overflow.ok.0
+overflow.fail.0:
+%6 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.0, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+overflow.ok.0:
+ret i64 %4
+}
+define i32 @main() {
+ret i32 0
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+declare { i64, i1 } @llvm.umul.with.overflow.i64(i64, i64)
+"
+    );
+    ok!(
+        "(checked f ((a u8) (b u8) u8) (/ a b)) (main (i32) 0)",
+        "\
+@str.0 = private unnamed_addr constant [23 x i8] c\"1:31: division by zero\\0A\"
+
+define i8 @f(i8 %0, i8 %1) {
+%3 = icmp eq i8 %1, 0
+br i1 %3, label %div.fail.0, label %div.ok.0
+div.fail.0:
+%4 = call i64 @write(i32 2, i8* getelementptr inbounds ([23 x i8], [23 x i8]* @str.0, i64 0, i64 0), i64 23)
+call void @llvm.trap()
+unreachable
+div.ok.0:
+%5 = udiv i8 %0, %1
+ret i8 %5
+}
+define i32 @main() {
+ret i32 0
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+"
+    );
+    ok!(
+        "(checked f ((a i16) (b i16) i16) (% a b)) (main (i32) 0)",
+        "\
+@str.0 = private unnamed_addr constant [23 x i8] c\"1:34: division by zero\\0A\"
+@str.1 = private unnamed_addr constant [26 x i8] c\"1:34: arithmetic overflow\\0A\"
+
+define i16 @f(i16 %0, i16 %1) {
+%3 = icmp eq i16 %1, 0
+br i1 %3, label %div.fail.0, label %div.ok.0
+div.fail.0:
+%4 = call i64 @write(i32 2, i8* getelementptr inbounds ([23 x i8], [23 x i8]* @str.0, i64 0, i64 0), i64 23)
+call void @llvm.trap()
+unreachable
+div.ok.0:
+%5 = icmp eq i16 %0, -32768
+%6 = icmp eq i16 %1, -1
+%7 = and i1 %5, %6
+br i1 %7, label %overflow.fail.1, label %overflow.ok.1
+overflow.fail.1:
+%8 = call i64 @write(i32 2, i8* getelementptr inbounds ([26 x i8], [26 x i8]* @str.1, i64 0, i64 0), i64 26)
+call void @llvm.trap()
+unreachable
+overflow.ok.1:
+%9 = srem i16 %0, %1
+ret i16 %9
+}
+define i32 @main() {
+ret i32 0
+}
+declare i64 @write(i32, i8*, i64)
+declare void @llvm.trap()
+"
+    );
+    ok!(
+        "(checked f ((a f64) (b f64) f64) (- a b)) (main (i32) 0)",
+        "\
+define double @f(double %0, double %1) {
+%3 = fsub double %0, %1
+ret double %3
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+
+    // Padded so the locations in the messages line up.
+    let options = Options { checked: true };
+    assert_eq!(
+        main(
+            "        (f ((a i8) (b i8) i8) (- a b)) (main (i32) 0)",
+            &options
+        )
+        .ok()
+        .map(|(code, _)| code),
+        emit(
+            "(checked f ((a i8) (b i8) i8) (- a b)) (main (i32) 0)",
+            &Options::default()
+        )
+        .ok()
+    );
+    assert_eq!(
+        main("(f ((a i8) (b i8) i8) (- a b)) (main (i32) 0)", &options)
+            .ok()
+            .map(|(code, _)| code),
+        emit("(f ((a i8) (b i8) i8) (- a b)) (main (i32) 0)", &options).ok()
+    );
+}
+
+#[test]
+fn arith_families() {
+    ok!(
+        "(checked f ((a i32) (b i32) i32) (+% a b)) (main (i32) 0)",
+        "\
+define i32 @f(i32 %0, i32 %1) {
+%3 = add i32 %0, %1
+ret i32 %3
+}
+define i32 @main() {
+ret i32 0
+}
+"
+    );
+    ok!(
+        "(f ((a u16) (b u16) u16) (-| a b)) (main (i32) 0)",
+        "\
+define i16 @f(i16 %0, i16 %1) {
+%3 = call i16 @llvm.usub.sat.i16(i16 %0, i16 %1)
+ret i16 %3
+}
+define i32 @main() {
+ret i32 0
+}
+declare i16 @llvm.usub.sat.i16(i16, i16)
+"
+    );
+    ok!(
+        "(f ((a i64) (b i64) i64) (*| a b)) (main (i32) 0)",
+        "\
+define i64 @f(i64 %0, i64 %1) {
+%3 = call i64 @llvm.smul.fix.sat.i64(i64 %0, i64 %1, i32 0)
+ret i64 %3
+}
+define i32 @main() {
+ret i32 0
+}
+declare i64 @llvm.smul.fix.sat.i64(i64, i64, i32)
+"
+    );
+    ok!(
+        "(f ((a u8) (b u8) bool) (.overflow (+? a b))) (main (i32) 0)",
+        "\
+define i1 @f(i8 %0, i8 %1) {
+%3 = call { i8, i1 } @llvm.uadd.with.overflow.i8(i8 %0, i8 %1)
+%4 = extractvalue { i8, i1 } %3, 1
+ret i1 %4
+}
+define i32 @main() {
+ret i32 0
+}
+declare { i8, i1 } @llvm.uadd.with.overflow.i8(i8, i8)
+"
+    );
+    ok!(
+        "(f ((a i32) (b i32) i32) (let ((r (overflow i32) (*? a b))) (if (.overflow r) 0 (.value r)))) (main (i32) 0)",
+        "\
+define i32 @f(i32 %0, i32 %1) {
+%3 = call { i32, i1 } @llvm.smul.with.overflow.i32(i32 %0, i32 %1)
+%4 = extractvalue { i32, i1 } %3, 1
+br i1 %4, label %if.then.0, label %if.else.0
+if.then.0:
+ret i32 0
+if.else.0:
+%5 = extractvalue { i32, i1 } %3, 0
+ret i32 %5
+}
+define i32 @main() {
+ret i32 0
+}
+declare { i32, i1 } @llvm.smul.with.overflow.i32(i32, i32)
+"
+    );
+    err!(
+        "(f ((a f32) (b f32) f32) (+% a b)) (main (i32) 0)",
+        "no_type_match"
+    );
+    err!(
+        "(f ((a bool) (b bool) bool) (+| a b)) (main (i32) 0)",
+        "no_type_match"
+    );
+    err!(
+        "(f ((r (overflow f64)) i32) 0) (main (i32) 0)",
+        "expected_overflow_int"
+    );
+}
+
+#[test]
+fn math_builtins() {
+    // The call expected for each builtin on a terminal, or "" if the builtin
+    // does not accept that terminal.
+    #[rustfmt::skip]
+    const CALLS: [(&str, usize, &str, &str); 19] = [
+        ("sqrt", 1, "f32", "call float @llvm.sqrt.f32(float %0)"),
+        ("sqrt", 1, "i32", ""),
+        ("abs", 1, "i64", "call i64 @llvm.abs.i64(i64 %0, i1 false)"),
+        ("abs", 1, "f16", "call half @llvm.fabs.f16(half %0)"),
+        ("abs", 1, "u32", ""),
+        ("min", 2, "u16", "call i16 @llvm.umin.i16(i16 %0, i16 %1)"),
+        ("min", 2, "i8", "call i8 @llvm.smin.i8(i8 %0, i8 %1)"),
+        ("max", 2, "f64", "call double @llvm.maxnum.f64(double %0, double %1)"),
+        ("max", 2, "bool", ""),
+        ("floor", 1, "f64", "call double @llvm.floor.f64(double %0)"),
+        ("ceil", 1, "f16", "call half @llvm.ceil.f16(half %0)"),
+        ("ceil", 1, "u8", ""),
+        ("fma", 3, "f32", "call float @llvm.fma.f32(float %0, float %1, float %2)"),
+        ("popcount", 1, "u64", "call i64 @llvm.ctpop.i64(i64 %0)"),
+        ("popcount", 1, "f32", ""),
+        ("clz", 1, "i16", "call i16 @llvm.ctlz.i16(i16 %0, i1 false)"),
+        ("ctz", 1, "u32", "call i32 @llvm.cttz.i32(i32 %0, i1 false)"),
+        ("rotl", 2, "u8", "call i8 @llvm.fshl.i8(i8 %0, i8 %0, i8 %1)"),
+        ("rotr", 2, "i64", "call i64 @llvm.fshr.i64(i64 %0, i64 %0, i64 %1)"),
+    ];
+
+    for (builtin, arity, terminal, call) in &CALLS {
+        let names = &["a", "b", "c"][..*arity];
+        let params: Vec<_> = names
+            .iter()
+            .map(|name| format!("({name} {terminal})"))
+            .collect();
+        let text = format!(
+            "(f ({} {}) ({} {})) (main (i32) 0)",
+            params.join(" "),
+            terminal,
+            builtin,
+            names.join(" ")
+        );
+
+        match emit(&text, &Options::default()) {
+            Ok(code) => {
+                let expected = format!("%{} = {}\n", arity + 1, call);
+                assert!(
+                    !call.is_empty() && code.contains(&expected),
+                    "{}\n{}",
+                    text,
+                    code
+                );
+            }
+            Err(error) => assert!(
+                call.is_empty() && error.name() == "no_type_match",
+                "{}\n{}",
+                text,
+                error.name()
+            ),
+        }
+    }
+
+    ok!(
+        "(main (i32) (i32 (sqrt 2.0)))",
+        "\
+define i32 @main() {
+%1 = call double @llvm.sqrt.f64(double 2.0)
+%2 = fptosi double %1 to i32
+ret i32 %2
+}
+declare double @llvm.sqrt.f64(double)
+"
+    );
+    // A def sharing a builtin's name is called like any other.
+    ok!(
+        "(min ((a bool) (b bool) bool) (&& a b)) (main (i32) (if (min true false) 1 0))",
+        "\
+define i1 @min12(i1 %0, i1 %1) {
+br i1 %0, label %and.rhs.0, label %and.end.0
+and.rhs.0:
+br label %and.end.0
+and.end.0:
+%3 = phi i1 [false, %2], [%1, %and.rhs.0]
+ret i1 %3
+}
+define i32 @main() {
+%1 = call i1 @min12(i1 true, i1 false)
+br i1 %1, label %if.then.0, label %if.else.0
+if.then.0:
+ret i32 1
+if.else.0:
+ret i32 0
+}
+"
+    );
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn func_value() {
+    ok!(
+        "(twice ((f (i32 i32)) (x i32) i32) (f (f x))) (inc ((x i32) i32) (+ x 1)) (main (i32) (twice inc 3))",
+        "\
+define i32 @twice({ i32 (i8*, i32)*, i8* } %0, i32 %1) {
+%3 = extractvalue { i32 (i8*, i32)*, i8* } %0, 0
+%4 = extractvalue { i32 (i8*, i32)*, i8* } %0, 1
+%5 = extractvalue { i32 (i8*, i32)*, i8* } %0, 0
+%6 = extractvalue { i32 (i8*, i32)*, i8* } %0, 1
+%7 = call i32 %5(i8* %6, i32 %1)
+%8 = tail call i32 %3(i8* %4, i32 %7)
+ret i32 %8
+}
+define i32 @inc(i32 %0) {
+%2 = add i32 %0, 1
+ret i32 %2
+}
+define i32 @main() {
+%1 = insertvalue { i32 (i8*, i32)*, i8* } undef, i32 (i8*, i32)* @closure.inc, 0
+%2 = insertvalue { i32 (i8*, i32)*, i8* } %1, i8* null, 1
+%3 = tail call i32 @twice({ i32 (i8*, i32)*, i8* } %2, i32 3)
+ret i32 %3
+}
+define i32 @closure.inc(i8* %0, i32 %1) {
+%3 = tail call i32 @inc(i32 %1)
+ret i32 %3
+}
+"
+    );
+    // A function can be returned, bound, and called in tail position.
+    ok!(
+        "(inc ((x i32) i32) (+ x 1)) (pick ((b bool) (i32 i32)) (if b inc pick1)) (pick1 ((x i32) i32) x) (main ((x i32) i32) (let ((g (i32 i32) (pick true))) (g x)))",
+        "\
+define i32 @inc(i32 %0) {
+%2 = add i32 %0, 1
+ret i32 %2
+}
+define { i32 (i8*, i32)*, i8* } @pick(i1 %0) {
+br i1 %0, label %if.then.0, label %if.else.0
+if.then.0:
+%2 = insertvalue { i32 (i8*, i32)*, i8* } undef, i32 (i8*, i32)* @closure.inc, 0
+%3 = insertvalue { i32 (i8*, i32)*, i8* } %2, i8* null, 1
+ret { i32 (i8*, i32)*, i8* } %3
+if.else.0:
+%4 = insertvalue { i32 (i8*, i32)*, i8* } undef, i32 (i8*, i32)* @closure.pick1, 0
+%5 = insertvalue { i32 (i8*, i32)*, i8* } %4, i8* null, 1
+ret { i32 (i8*, i32)*, i8* } %5
+}
+define i32 @pick1(i32 %0) {
+ret i32 %0
+}
+define i32 @main(i32 %0) {
+%2 = call { i32 (i8*, i32)*, i8* } @pick(i1 true)
+%3 = extractvalue { i32 (i8*, i32)*, i8* } %2, 0
+%4 = extractvalue { i32 (i8*, i32)*, i8* } %2, 1
+%5 = tail call i32 %3(i8* %4, i32 %0)
+ret i32 %5
+}
+define i32 @closure.inc(i8* %0, i32 %1) {
+%3 = tail call i32 @inc(i32 %1)
+ret i32 %3
+}
+define i32 @closure.pick1(i8* %0, i32 %1) {
+%3 = tail call i32 @pick1(i32 %1)
+ret i32 %3
+}
+"
+    );
+    ok!(
+        "(extern abs ((x i32) i32)) (main (i32) (let ((mut g (i32 i32) abs)) (g -1)))",
+        "\
+define i32 @main() {
+%slot.0 = alloca { i32 (i8*, i32)*, i8* }
+%1 = insertvalue { i32 (i8*, i32)*, i8* } undef, i32 (i8*, i32)* @closure.abs, 0
+%2 = insertvalue { i32 (i8*, i32)*, i8* } %1, i8* null, 1
+store { i32 (i8*, i32)*, i8* } %2, { i32 (i8*, i32)*, i8* }* %slot.0
+%3 = load { i32 (i8*, i32)*, i8* }, { i32 (i8*, i32)*, i8* }* %slot.0
+%4 = extractvalue { i32 (i8*, i32)*, i8* } %3, 0
+%5 = extractvalue { i32 (i8*, i32)*, i8* } %3, 1
+%6 = tail call i32 %4(i8* %5, i32 -1)
+ret i32 %6
+}
+define i32 @closure.abs(i8* %0, i32 %1) {
+%3 = tail call i32 @abs(i32 %1)
+ret i32 %3
+}
+declare i32 @abs(i32)
+"
+    );
+    ok!(
+        "(inc ((x i32) i32) (+ x 1)) (inc ((x i64) i64) (+ x 1)) (apply ((f (i64 i64)) (x i64) i64) (f x)) (main (i32) (i32 (apply inc 1)))",
+        "\
+define i32 @inc(i32 %0) {
+%2 = add i32 %0, 1
+ret i32 %2
+}
+define i64 @inc1(i64 %0) {
+%2 = add i64 %0, 1
+ret i64 %2
+}
+define i64 @apply({ i64 (i8*, i64)*, i8* } %0, i64 %1) {
+%3 = extractvalue { i64 (i8*, i64)*, i8* } %0, 0
+%4 = extractvalue { i64 (i8*, i64)*, i8* } %0, 1
+%5 = tail call i64 %3(i8* %4, i64 %1)
+ret i64 %5
+}
+define i32 @main() {
+%1 = insertvalue { i64 (i8*, i64)*, i8* } undef, i64 (i8*, i64)* @closure.inc1, 0
+%2 = insertvalue { i64 (i8*, i64)*, i8* } %1, i8* null, 1
+%3 = call i64 @apply({ i64 (i8*, i64)*, i8* } %2, i64 1)
+%4 = trunc i64 %3 to i32
+ret i32 %4
+}
+define i64 @closure.inc1(i8* %0, i64 %1) {
+%3 = tail call i64 @inc1(i64 %1)
+ret i64 %3
+}
+"
+    );
+
+    err!(
+        "(apply ((f (i32 i32 i32)) i32) (f 1 2)) (main (i32) (apply +))",
+        "builtin_value"
+    );
+    err!(
+        "(apply ((f (i32 i32)) i32) (f 1)) (inc ((x i64) i64) x) (main (i32) (apply inc))",
+        "type_mismatch"
+    );
+    err!(
+        "(apply ((f (i32 i32)) i32) (f true)) (main (i32) 0)",
+        "type_mismatch"
+    );
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn lambda() {
+    ok!(
+        "(adder ((n i32) (i32 i32)) (lambda ((x i32) i32) (+ x n))) (main (i32) 0)",
+        "\
+%lambda.0.env = type { i32 }
+
+define { i32 (i8*, i32)*, i8* } @adder(i32 %0) {
+%2 = call i8* @malloc(i64 ptrtoint (%lambda.0.env* getelementptr (%lambda.0.env, %lambda.0.env* null, i32 1) to i64))
+%3 = bitcast i8* %2 to %lambda.0.env*
+%4 = insertvalue %lambda.0.env undef, i32 %0, 0
+store %lambda.0.env %4, %lambda.0.env* %3
+%5 = insertvalue { i32 (i8*, i32)*, i8* } undef, i32 (i8*, i32)* @lambda.0, 0
+%6 = insertvalue { i32 (i8*, i32)*, i8* } %5, i8* %2, 1
+ret { i32 (i8*, i32)*, i8* } %6
+}
+define i32 @lambda.0(i8* %0, i32 %1) {
+%3 = bitcast i8* %0 to %lambda.0.env*
+%4 = load %lambda.0.env, %lambda.0.env* %3
+%5 = extractvalue %lambda.0.env %4, 0
+%6 = add i32 %1, %5
+ret i32 %6
+}
+define i32 @main() {
+ret i32 0
+}
+declare i8* @malloc(i64)
+"
+    );
+    // A program may declare `malloc` itself, with any types that lower to
+    // the same LLVM ones.
+    ok!(
+        "(extern malloc ((n u64) ptr)) (adder ((n i32) (i32 i32)) (lambda ((x i32) i32) (+ x n))) (main (i32) 0)",
+        "\
+%lambda.0.env = type { i32 }
+
+define { i32 (i8*, i32)*, i8* } @adder(i32 %0) {
+%2 = call i8* @malloc(i64 ptrtoint (%lambda.0.env* getelementptr (%lambda.0.env, %lambda.0.env* null, i32 1) to i64))
+%3 = bitcast i8* %2 to %lambda.0.env*
+%4 = insertvalue %lambda.0.env undef, i32 %0, 0
+store %lambda.0.env %4, %lambda.0.env* %3
+%5 = insertvalue { i32 (i8*, i32)*, i8* } undef, i32 (i8*, i32)* @lambda.0, 0
+%6 = insertvalue { i32 (i8*, i32)*, i8* } %5, i8* %2, 1
+ret { i32 (i8*, i32)*, i8* } %6
+}
+define i32 @lambda.0(i8* %0, i32 %1) {
+%3 = bitcast i8* %0 to %lambda.0.env*
+%4 = load %lambda.0.env, %lambda.0.env* %3
+%5 = extractvalue %lambda.0.env %4, 0
+%6 = add i32 %1, %5
+ret i32 %6
+}
+define i32 @main() {
+ret i32 0
+}
+declare i8* @malloc(i64)
+"
+    );
+    // A lambda without captures has no environment.
+    ok!(
+        "(apply ((f (i32 i32)) i32) (f 1)) (main (i32) (apply (lambda ((x i32) i32) (* x 2))))",
+        "\
+define i32 @apply({ i32 (i8*, i32)*, i8* } %0) {
+%2 = extractvalue { i32 (i8*, i32)*, i8* } %0, 0
+%3 = extractvalue { i32 (i8*, i32)*, i8* } %0, 1
+%4 = tail call i32 %2(i8* %3, i32 1)
+ret i32 %4
+}
+define i32 @main() {
+%1 = insertvalue { i32 (i8*, i32)*, i8* } undef, i32 (i8*, i32)* @lambda.0, 0
+%2 = insertvalue { i32 (i8*, i32)*, i8* } %1, i8* null, 1
+%3 = tail call i32 @apply({ i32 (i8*, i32)*, i8* } %2)
+ret i32 %3
+}
+define i32 @lambda.0(i8* %0, i32 %1) {
+%3 = mul i32 %1, 2
+ret i32 %3
+}
+"
+    );
+    // A mutable binding is captured by its current value, and a nested
+    // lambda captures through the one around it.
+    ok!(
+        "(main (i32) (let ((mut m i32 1) (f (i32 (i32 i32)) (lambda ((a i32) (i32 i32)) (lambda ((b i32) i32) (+ a (+ b m)))))) 0))",
+        "\
+%lambda.0.env = type { i32 }
+%lambda.1.env = type { i32, i32 }
+
+define i32 @main() {
+%slot.0 = alloca i32
+store i32 1, i32* %slot.0
+%1 = load i32, i32* %slot.0
+%2 = call i8* @malloc(i64 ptrtoint (%lambda.0.env* getelementptr (%lambda.0.env, %lambda.0.env* null, i32 1) to i64))
+%3 = bitcast i8* %2 to %lambda.0.env*
+%4 = insertvalue %lambda.0.env undef, i32 %1, 0
+store %lambda.0.env %4, %lambda.0.env* %3
+%5 = insertvalue { { i32 (i8*, i32)*, i8* } (i8*, i32)*, i8* } undef, { i32 (i8*, i32)*, i8* } (i8*, i32)* @lambda.0, 0
+%6 = insertvalue { { i32 (i8*, i32)*, i8* } (i8*, i32)*, i8* } %5, i8* %2, 1
+ret i32 0
+}
+define i32 @lambda.1(i8* %0, i32 %1) {
+%3 = bitcast i8* %0 to %lambda.1.env*
+%4 = load %lambda.1.env, %lambda.1.env* %3
+%5 = extractvalue %lambda.1.env %4, 0
+%6 = extractvalue %lambda.1.env %4, 1
+%7 = add i32 %1, %6
+%8 = add i32 %5, %7
+ret i32 %8
+}
+define { i32 (i8*, i32)*, i8* } @lambda.0(i8* %0, i32 %1) {
+%3 = bitcast i8* %0 to %lambda.0.env*
+%4 = load %lambda.0.env, %lambda.0.env* %3
+%5 = extractvalue %lambda.0.env %4, 0
+%6 = call i8* @malloc(i64 ptrtoint (%lambda.1.env* getelementptr (%lambda.1.env, %lambda.1.env* null, i32 1) to i64))
+%7 = bitcast i8* %6 to %lambda.1.env*
+%8 = insertvalue %lambda.1.env undef, i32 %1, 0
+%9 = insertvalue %lambda.1.env %8, i32 %5, 1
+store %lambda.1.env %9, %lambda.1.env* %7
+%10 = insertvalue { i32 (i8*, i32)*, i8* } undef, i32 (i8*, i32)* @lambda.1, 0
+%11 = insertvalue { i32 (i8*, i32)*, i8* } %10, i8* %6, 1
+ret { i32 (i8*, i32)*, i8* } %11
+}
+declare i8* @malloc(i64)
+"
+    );
+
+    err!(
+        "(main (i32) (let ((mut m i32 0) (f (i32) (lambda (i32) (do (set! m 1) m)))) (f)))",
+        "captured_set"
+    );
+    err!(
+        "(main (i32) (let ((f (i32 i32) (lambda ((x i32) i32)))) (f 1)))",
+        "expected_lambda"
+    );
+    err!(
+        "(main (i32) (let ((f (i32 i32) (lambda ((x i64) i32) 1))) (f 1)))",
+        "type_mismatch"
+    );
+    err!(
+        "(g ((x i32) i32) x) (main (i32) (let ((f (i32 i32) (lambda ((x i32) i32) (tail g x)))) (f 1)))",
+        "tail_type_mismatch"
+    );
+    err!(
+        "(main ((x i32) i32) (let ((f (i32 i32) (lambda ((y i32) i32) (+ x y)))) (tail f x)))",
+        "tail_type_mismatch"
+    );
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn generics() {
+    ok!(
+        "(id (T) ((x T) T) x) (main (i32) (let ((a i64 (id 1)) (b bool (id true))) (id 2)))",
+        "\
+define i32 @main() {
+%1 = call i64 @id.i64(i64 1)
+%2 = call i1 @id.bool(i1 true)
+%3 = tail call i32 @id.i32(i32 2)
+ret i32 %3
+}
+define i64 @id.i64(i64 %0) {
+ret i64 %0
+}
+define i1 @id.bool(i1 %0) {
+ret i1 %0
+}
+define i32 @id.i32(i32 %0) {
+ret i32 %0
+}
+"
+    );
+    ok!(
+        "(first (A B) ((a A) (b B) A) a) (wrap (T) ((x T) T) (first x true)) (main (i32) (wrap (first 1 2)))",
+        "\
+define i32 @main() {
+%1 = call i32 @first.i32.i32(i32 1, i32 2)
+%2 = tail call i32 @wrap.i32(i32 %1)
+ret i32 %2
+}
+define i32 @wrap.i32(i32 %0) {
+%2 = tail call i32 @first.i32.bool(i32 %0, i1 true)
+ret i32 %2
+}
+define i32 @first.i32.i32(i32 %0, i32 %1) {
+ret i32 %0
+}
+define i32 @first.i32.bool(i32 %0, i1 %1) {
+ret i32 %0
+}
+"
+    );
+    ok!(
+        "(deref (T) ((p (ptr T)) T) (load p)) (get ((p (ptr i64)) i64) (deref p)) (main (i32) 0)",
+        "\
+define i64 @get(i64* %0) {
+%2 = musttail call i64 @deref.i64(i64* %0)
+ret i64 %2
+}
+define i32 @main() {
+ret i32 0
+}
+define i64 @deref.i64(i64* %0) {
+%2 = load i64, i64* %0
+ret i64 %2
+}
+"
+    );
+    ok!(
+        "(twice (T) ((f (T T)) (x T) T) (f (f x))) (id (T) ((x T) T) x) (main (i32) (twice id 3))",
+        "\
+define i32 @main() {
+%1 = insertvalue { i32 (i8*, i32)*, i8* } undef, i32 (i8*, i32)* @closure.id.i32, 0
+%2 = insertvalue { i32 (i8*, i32)*, i8* } %1, i8* null, 1
+%3 = tail call i32 @twice.i32({ i32 (i8*, i32)*, i8* } %2, i32 3)
+ret i32 %3
+}
+define i32 @twice.i32({ i32 (i8*, i32)*, i8* } %0, i32 %1) {
+%3 = extractvalue { i32 (i8*, i32)*, i8* } %0, 0
+%4 = extractvalue { i32 (i8*, i32)*, i8* } %0, 1
+%5 = extractvalue { i32 (i8*, i32)*, i8* } %0, 0
+%6 = extractvalue { i32 (i8*, i32)*, i8* } %0, 1
+%7 = call i32 %5(i8* %6, i32 %1)
+%8 = tail call i32 %3(i8* %4, i32 %7)
+ret i32 %8
+}
+define i32 @id.i32(i32 %0) {
+ret i32 %0
+}
+define i32 @closure.id.i32(i8* %0, i32 %1) {
+%3 = tail call i32 @id.i32(i32 %1)
+ret i32 %3
+}
+"
+    );
+
+    err!(
+        "(none (T) ((x i32) i32) x) (main (i32) (none 1))",
+        "unresolved_type_param"
+    );
+    err!(
+        "(id (T) ((x T) T) x) (main (i32) (let ((f (i32 i64) id)) 0))",
+        "type_mismatch"
+    );
+    err!("(id (T) ((x T) T) (+ x x)) (main (i32) 0)", "no_type_match");
+    err!(
+        "(export id (T) ((x T) T) x) (main (i32) 0)",
+        "generic_export"
+    );
+    err!(
+        "(id (T T) ((x T) T) x) (main (i32) 0)",
+        "duplicate_type_param"
+    );
+}
+
+#[test]
+#[allow(clippy::too_many_lines)]
+fn classes() {
+    ok!(
+        "(square ((T Num)) ((x T) T) (* x x)) (main (i32) (let ((a f64 (square 1.5))) (square 3)))",
+        "\
+define i32 @main() {
+%1 = call double @square.f64(double 1.5)
+%2 = tail call i32 @square.i32(i32 3)
+ret i32 %2
+}
+define double @square.f64(double %0) {
+%2 = fmul double %0, %0
+ret double %2
+}
+define i32 @square.i32(i32 %0) {
+%2 = mul i32 %0, %0
+ret i32 %2
+}
+"
+    );
+    ok!(
+        "(max3 ((T Ord)) ((a T) (b T) (c T) T) (max a (max b c))) (quad ((T Num)) ((x T) T) (+ x (+ x (+ x x)))) (main (i32) (quad (max3 1 2 3)))",
+        "\
+define i32 @main() {
+%1 = call i32 @max3.i32(i32 1, i32 2, i32 3)
+%2 = tail call i32 @quad.i32(i32 %1)
+ret i32 %2
+}
+define i32 @quad.i32(i32 %0) {
+%2 = add i32 %0, %0
+%3 = add i32 %0, %2
+%4 = add i32 %0, %3
+ret i32 %4
+}
+define i32 @max3.i32(i32 %0, i32 %1, i32 %2) {
+%4 = call i32 @llvm.smax.i32(i32 %1, i32 %2)
+%5 = call i32 @llvm.smax.i32(i32 %0, i32 %4)
+ret i32 %5
+}
+declare i32 @llvm.smax.i32(i32, i32)
+"
+    );
+    ok!(
+        "(struct Point (x i32) (y i32)) (instance Eq Point (== ((a Point) (b Point) bool) (== (.x a) (.x b))) (!= ((a Point) (b Point) bool) (! (== a b)))) (same ((T Eq)) ((a T) (b T) bool) (== a b)) (main (i32) (if (same (Point 1 2) (Point 1 3)) 1 0))",
+        "\
+%Point = type { i32, i32 }
+
+define i1 @\"==13\"(%Point %0, %Point %1) {
+%3 = extractvalue %Point %0, 0
+%4 = extractvalue %Point %1, 0
+%5 = icmp eq i32 %3, %4
+ret i1 %5
+}
+define i1 @\"!=13\"(%Point %0, %Point %1) {
+%3 = call i1 @\"==13\"(%Point %0, %Point %1)
+%4 = xor i1 %3, true
+ret i1 %4
+}
+define i32 @main() {
+%1 = insertvalue %Point undef, i32 1, 0
+%2 = insertvalue %Point %1, i32 2, 1
+%3 = insertvalue %Point undef, i32 1, 0
+%4 = insertvalue %Point %3, i32 3, 1
+%5 = call i1 @same.Point(%Point %2, %Point %4)
+br i1 %5, label %if.then.0, label %if.else.0
+if.then.0:
+ret i32 1
+if.else.0:
+ret i32 0
+}
+define i1 @same.Point(%Point %0, %Point %1) {
+%3 = musttail call i1 @\"==13\"(%Point %0, %Point %1)
+ret i1 %3
+}
+"
+    );
+    ok!(
+        "(class Show (T) (show (T i32))) (instance Show bool (show ((x bool) i32) (if x 1 0))) (twice ((T Show)) ((x T) i32) (+ (show x) (show x))) (main (i32) (twice true))",
+        "\
+define i32 @show1(i1 %0) {
+br i1 %0, label %if.then.0, label %if.else.0
+if.then.0:
+ret i32 1
+if.else.0:
+ret i32 0
+}
+define i32 @main() {
+%1 = tail call i32 @twice.bool(i1 true)
+ret i32 %1
+}
+define i32 @twice.bool(i1 %0) {
+%2 = call i32 @show1(i1 %0)
+%3 = call i32 @show1(i1 %0)
+%4 = add i32 %2, %3
+ret i32 %4
+}
+"
+    );
+
+    err!(
+        "(square ((T Num)) ((x T) T) (* x x)) (main (i32) (if (square true) 1 0))",
+        "missing_instance"
+    );
+    err!(
+        "(square ((T Num)) ((x T) T) (* x x)) (bad (T) ((x T) T) (square x)) (main (i32) 0)",
+        "missing_instance"
+    );
+    err!("(f ((T Foo)) ((x T) T) x) (main (i32) 0)", "expected_class");
+    err!("(instance i32 i32) (main (i32) 0)", "expected_class");
+    err!(
+        "(class Show (T) (show (T i32))) (instance Show i32) (main (i32) 0)",
+        "missing_method"
+    );
+    err!(
+        "(class Show (T) (show (T i32))) (instance Show i32 (show ((x i64) i32) 0)) (main (i32) 0)",
+        "missing_method"
+    );
+    err!(
+        "(class Show (T) (show (T i32))) (instance Show i32 (show ((x i32) i32) x) (other ((x i32) i32) x)) (main (i32) 0)",
+        "unexpected_method"
+    );
+    err!("(instance Num i32) (main (i32) 0)", "duplicate_instance");
+    err!("(class Num (T)) (main (i32) 0)", "duplicate_class");
+    err!(
+        "(class Show T (show (T i32))) (main (i32) 0)",
+        "expected_class_param"
+    );
+    err!("(class Show (T) show) (main (i32) 0)", "expected_method");
+    err!(
+        "(class Show (T) (show (T i32)) (show (T i64))) (main (i32) 0)",
+        "duplicate_method"
+    );
+    err!("(class) (main (i32) 0)", "expected_class_name");
+    err!("(instance Show) (main (i32) 0)", "expected_instance");
+    err!(
+        "(class Show (T) (show (T i32))) (instance Show i32 (export show ((x i32) i32) x)) (main (i32) 0)",
+        "expected_method_def"
+    );
+}
+
+#[test]
+fn overloads() {
+    ok!(
+        "(f ((x i32) i32) x) (f ((x i64) i32) 0) (main (i32) (f 1))",
+        "\
+define i32 @f(i32 %0) {
+ret i32 %0
+}
+define i32 @f1(i64 %0) {
+ret i32 0
+}
+define i32 @main() {
+%1 = tail call i32 @f(i32 1)
+ret i32 %1
+}
+"
+    );
+    ok!(
+        "(f ((x i64) i32) 0) (f ((x i32) i32) x) (main (i32) (f 1))",
+        "\
+define i32 @f(i64 %0) {
+ret i32 0
+}
+define i32 @f1(i32 %0) {
+ret i32 %0
+}
+define i32 @main() {
+%1 = tail call i32 @f1(i32 1)
+ret i32 %1
+}
+"
+    );
+    ok!(
+        "(f ((x i64) i32) 0) (f ((x u8) i32) 1) (main (i32) (let ((x u8 2)) (f x)))",
+        "\
+define i32 @f(i64 %0) {
+ret i32 0
+}
+define i32 @f1(i8 %0) {
+ret i32 1
+}
+define i32 @main() {
+%1 = tail call i32 @f1(i8 2)
+ret i32 %1
+}
+"
+    );
+    ok!(
+        "(id (T) ((x T) T) x) (id ((x i32) i32) 7) (main (i32) (id 1))",
+        "\
+define i32 @id1(i32 %0) {
+ret i32 7
+}
+define i32 @main() {
+%1 = tail call i32 @id1(i32 1)
+ret i32 %1
+}
+"
+    );
+    err!(
+        "(f ((x u8) i32) 0) (f ((x i64) i32) 1) (main (i32) (f 1))",
+        "ambiguous_overload"
+    );
+    err!(
+        "(f ((x i32) i64) 0) (f ((x i32) i32) 1) (main (i32) (i32 (f 1)))",
+        "ambiguous_overload"
+    );
+    err!(
+        "(+ ((a i32) (b i32) i32) a) (main (i32) (+ 1 2))",
+        "duplicate_overload"
+    );
+    err!(
+        "(struct Point (x i32)) (Point ((x i32) Point) (Point x)) (main (i32) 0)",
+        "duplicate_overload"
+    );
+    err!(
+        "(id (T) ((x T) T) x) (id (U) ((x U) U) x) (main (i32) 0)",
+        "duplicate_overload"
+    );
+    warn!(
+        "(f ((x i32) i32) x) (f ((x i32) i64) 0) (main (i32) (f 1))",
+        "similar_overload"
+    );
+    warn!(
+        "(+ ((a i32) (b i32) i64) 0) (main (i32) (+ 1 2))",
+        "similar_overload"
+    );
+    warn!("(extern abs (i32 i32)) (main (i32) 0)", "shadowed_builtin");
+    warn!("(extern abs (i32 i64)) (main (i32) 0)", "similar_overload");
+}
+
+/// `(i32 (+ (+ (+ 1 2) 3) ...))`, where the type of the sum is only settled
+/// by the conversion around it.
+fn nested_sum(depth: usize) -> String {
+    let mut sum = String::from("1");
+    for i in 0..depth {
+        sum = format!("(+ {} {})", sum, i + 2);
+    }
+    format!("(main (i32) (i32 {sum}))")
+}
+
+#[test]
+fn nested_overloads() {
+    // Every `+` has a candidate for each numeric type, so checking the
+    // arguments again for each one would never finish.
+    assert!(typecheck(&nested_sum(64)).is_ok());
+    let generic = "(twice ((T Num)) ((x T) T) (+ (+ (+ (+ (+ (+ (+ (+ x x) x) x) x) x) x) x) x))";
+    assert!(typecheck(&format!("{generic} (main (i32) (twice 1))")).is_ok());
+}
+
+/// Each expression should be checked against each type it may be expected
+/// to have at most once per pass, so that the number of checks grows by the
+/// same amount with every level of nesting.
+#[test]
+fn nested_overloads_check_count() {
+    let checks = |depth| {
+        let (mut program, namespace) = resolve(&nested_sum(depth)).unwrap();
+        typecheck::typecheck_defs(&mut program, &namespace).unwrap()
+    };
+    let [a, b, c] = [checks(16), checks(32), checks(64)];
+    assert_eq!(c - b, 2 * (b - a));
+}
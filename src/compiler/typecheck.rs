@@ -1,35 +1,91 @@
+use super::common::Id;
 use super::common::Location;
 use super::error::Error;
+use super::literalise;
 use super::namespace::Namespace;
+use super::namespace::Scope;
+use super::parse::Binding;
 use super::parse::Call;
+use super::parse::Do;
 use super::parse::Expr;
+use super::parse::If;
+use super::parse::Lambda;
+use super::parse::Let;
+use super::parse::Match;
 use super::parse::NameId;
+use super::parse::Pattern;
 use super::parse::Program;
+use super::parse::Set;
+use super::parse::While;
+use super::resolve::definition_note;
+use super::resolve::get_generic_type;
+use super::resolve::resolve_func;
 use super::symbol::Func;
 use super::symbol::Symbol;
 use super::symbol::Terminal;
 use super::symbol::Type;
+use std::collections::HashMap;
+use std::ptr;
+use std::slice;
 
 pub fn typecheck(
     mut program: Program,
     namespace: Namespace,
 ) -> Result<(Program, Namespace), Error> {
     typecheck_main(&namespace)?;
+    typecheck_defs(&mut program, &namespace)?;
+    Ok((program, namespace))
+}
 
+/// Checks every def, and returns how many times an expression was checked
+/// against a type it had not already been checked against in the same pass.
+pub fn typecheck_defs(program: &mut Program, namespace: &Namespace) -> Result<usize, Error> {
+    let mut checks = 0;
     for def in &mut program.defs {
         let def_namespace = namespace
             .get_then(&def.name_id.token, def.name_id.id)
             .unwrap();
 
-        if let Symbol::Type(Type::Terminal(ret)) =
-            namespace.get_then(&def.func.ret.token, 0).unwrap().symbol()
-        {
-            typecheck_expr(&mut def.expr, *ret, &namespace, def_namespace)?;
+        if let Some(Func { ret, .. }) = def_namespace.symbol().func() {
+            let module_scope = Scope::new(namespace);
+            let def_scope = module_scope.push(def_namespace);
+            typecheck_tail(&def.expr, true)?;
+            let mut cache = Cache {
+                searching: true,
+                ..Cache::default()
+            };
+            typecheck_expr(&mut def.expr, ret, &def_scope, &mut cache)?;
+            cache.searching = false;
+            typecheck_expr(&mut def.expr, ret, &def_scope, &mut cache)?;
+            checks += cache.checks;
         } else {
             panic!()
         }
     }
-    Ok((program, namespace))
+    Ok(checks)
+}
+
+/// What typechecking a def has found so far. Every candidate of an overloaded
+/// call checks the call's arguments, so without it nested calls are checked
+/// a number of times exponential in how deeply they nest. With it each
+/// expression is checked at most once per pass for each type it is expected
+/// to have, so the number of checks is linear in how deeply calls nest.
+///
+/// A def is checked twice. The first pass searches for the candidate each
+/// call is made to, and leaves behind the ids of whichever candidates were
+/// tried last. The second sets the ids of those chosen, checking only them.
+#[derive(Default)]
+struct Cache {
+    searching: bool,
+    /// Each expression checked against each type, and how many literals in
+    /// it take a type other than their default.
+    checked: HashMap<(*const Expr, Type), Result<usize, Error>>,
+    /// The candidate a call is made to given the type expected of it, its
+    /// type arguments, and the literal count of the call.
+    calls: HashMap<(*const Expr, Type), (Id, Vec<Type>, usize)>,
+    inferred: HashMap<*const Expr, Vec<Type>>,
+    /// How many times an expression was checked rather than found here.
+    checks: usize,
 }
 
 fn typecheck_main(namespace: &Namespace) -> Result<(), Error> {
@@ -42,13 +98,13 @@ fn typecheck_main(namespace: &Namespace) -> Result<(), Error> {
     };
 
     let main1 = Symbol::Var(Type::Func(Func {
-        params: vec![Terminal::I32],
-        ret: Terminal::I32,
+        params: vec![Type::Terminal(Terminal::I32)],
+        ret: Box::new(Type::Terminal(Terminal::I32)),
     }));
 
     let main2 = Symbol::Var(Type::Func(Func {
         params: vec![],
-        ret: Terminal::I32,
+        ret: Box::new(Type::Terminal(Terminal::I32)),
     }));
 
     if *symbol == main1 || *symbol == main2 {
@@ -60,58 +116,388 @@ fn typecheck_main(namespace: &Namespace) -> Result<(), Error> {
 
 fn typecheck_expr(
     expr: &mut Expr,
-    outer_ret: Terminal,
-    namespace: &Namespace,
-    def_namespace: &Namespace,
+    outer_ret: &Type,
+    scope: &Scope,
+    cache: &mut Cache,
+) -> Result<(), Error> {
+    let key = (ptr::from_ref(expr), outer_ret.clone());
+    if let Some(result) = cache.checked.get(&key) {
+        // Only a failure can be reused once the search is over, since a
+        // success still has ids to set.
+        if cache.searching || result.is_err() {
+            return result.clone().map(|_| ());
+        }
+    }
+
+    cache.checks += 1;
+    let result = typecheck_expr_uncached(expr, outer_ret, scope, cache);
+    if cache.searching {
+        let result = result
+            .clone()
+            .map(|()| literal_count(expr, outer_ret, scope, cache));
+        cache.checked.insert(key, result);
+    }
+    result
+}
+
+fn typecheck_expr_uncached(
+    expr: &mut Expr,
+    outer_ret: &Type,
+    scope: &Scope,
+    cache: &mut Cache,
 ) -> Result<(), Error> {
     match expr {
         Expr::Val(NameId {
-            token, location, ..
-        }) => typecheck_val(token, *location, outer_ret, namespace, def_namespace),
-        Expr::Call(Call { exprs, location }) => {
-            typecheck_exprs(exprs, *location, outer_ret, namespace, def_namespace)
+            token,
+            id,
+            type_args,
+            location,
+        }) => {
+            (*id, *type_args) = typecheck_val(token, *location, outer_ret, scope)?;
+            Ok(())
+        }
+        Expr::Call(Call {
+            exprs,
+            tail,
+            location,
+        }) => {
+            typecheck_exprs(exprs, *location, outer_ret, scope, cache)?;
+            if *tail {
+                typecheck_tail_call(exprs, *location, scope)?;
+            }
+            Ok(())
+        }
+        Expr::If(If {
+            cond, then, els, ..
+        }) => {
+            typecheck_expr(cond, &Type::Terminal(Terminal::Bool), scope, cache)?;
+            typecheck_expr(then, outer_ret, scope, cache)?;
+            typecheck_expr(els, outer_ret, scope, cache)
+        }
+        Expr::Let(Let { bindings, body, .. }) => {
+            typecheck_let(bindings, body, outer_ret, scope, cache)
+        }
+        Expr::Set(Set {
+            name,
+            expr,
+            location,
+        }) => {
+            typecheck_void(outer_ret, *location)?;
+
+            let namespaces = scope
+                .get(&name.token)
+                .ok_or_else(|| error!(expected_defined_symbol, name.location, &name.token))?;
+
+            if let Some(Symbol::MutVar(typ)) = namespaces.first().map(Namespace::symbol) {
+                if scope.is_captured(&name.token) {
+                    return err!(captured_set, name.location, &name.token);
+                }
+                typecheck_expr(expr, typ, scope, cache)
+            } else {
+                err!(expected_mutable, name.location, &name.token)
+            }
+        }
+        Expr::While(While {
+            cond,
+            body,
+            location,
+        }) => {
+            typecheck_void(outer_ret, *location)?;
+            typecheck_expr(cond, &Type::Terminal(Terminal::Bool), scope, cache)?;
+            typecheck_expr(body, &Type::Terminal(Terminal::Void), scope, cache)
+        }
+        Expr::Do(Do { exprs, .. }) => {
+            let (last, stmts) = exprs.split_last_mut().unwrap();
+            for stmt in stmts {
+                typecheck_expr(stmt, &Type::Terminal(Terminal::Void), scope, cache)?;
+            }
+            typecheck_expr(last, outer_ret, scope, cache)
+        }
+        Expr::Match(match_expr) => typecheck_match(match_expr, outer_ret, scope, cache),
+        Expr::Lambda(lambda) => typecheck_lambda(lambda, outer_ret, scope, cache),
+    }
+}
+
+fn typecheck_void(outer_ret: &Type, location: Location) -> Result<(), Error> {
+    if *outer_ret == Type::Terminal(Terminal::Void) {
+        Ok(())
+    } else {
+        err!(
+            type_mismatch,
+            location,
+            outer_ret,
+            Type::Terminal(Terminal::Void)
+        )
+    }
+}
+
+fn typecheck_let(
+    bindings: &mut [Binding],
+    body: &mut Expr,
+    outer_ret: &Type,
+    scope: &Scope,
+    cache: &mut Cache,
+) -> Result<(), Error> {
+    let mut let_namespace = Namespace::from(Symbol::Scope);
+
+    for binding in bindings {
+        let typ = get_generic_type(&binding.typ, scope.type_params(), scope.root())?;
+        if typ == Type::Terminal(Terminal::Void) {
+            return err!(unexpected_void_binding, binding.location);
+        }
+
+        typecheck_expr(&mut binding.expr, &typ, &scope.push(&let_namespace), cache)?;
+
+        let symbol = if binding.mutable {
+            Symbol::MutVar(typ)
+        } else {
+            Symbol::Var(typ)
+        };
+        let_namespace.insert_namespaces(binding.name.token.clone(), vec![Namespace::from(symbol)]);
+    }
+
+    typecheck_expr(body, outer_ret, &scope.push(&let_namespace), cache)
+}
+
+/// A lambda's body sees its parameters, and the parameters and bindings
+/// around it.
+fn typecheck_lambda(
+    lambda: &mut Lambda,
+    outer_ret: &Type,
+    scope: &Scope,
+    cache: &mut Cache,
+) -> Result<(), Error> {
+    let (func, params) = resolve_func(&lambda.func, scope.type_params(), scope.root())?;
+    let typ = Type::Func(func.clone());
+    if typ != *outer_ret {
+        return err!(type_mismatch, lambda.location, outer_ret, typ);
+    }
+
+    let ret = (*func.ret).clone();
+    let lambda_namespace = Namespace::from((Symbol::Lambda(func), params));
+    typecheck_expr(
+        &mut lambda.body,
+        &ret,
+        &scope.push(&lambda_namespace),
+        cache,
+    )
+}
+
+/// The matched enum is taken from the first arm naming a variant. Every
+/// other arm must name a variant of the same enum, and together the arms must
+/// cover all of them.
+fn typecheck_match(
+    match_expr: &mut Match,
+    outer_ret: &Type,
+    scope: &Scope,
+    cache: &mut Cache,
+) -> Result<(), Error> {
+    let enum_type = typecheck_match_expr(match_expr, scope, cache)?;
+
+    let enum_namespace = match enum_type {
+        Type::Enum(name) => scope.root().get_then(name, 0).unwrap(),
+        _ => panic!(),
+    };
+    let variant_count = enum_namespace.iter().count();
+
+    let mut covered = Vec::new();
+    let mut wildcard = false;
+    for arm in &mut match_expr.arms {
+        let mut arm_namespace = Namespace::from(Symbol::Scope);
+
+        match &mut arm.pattern {
+            Pattern::Wildcard => {
+                if wildcard || covered.len() == variant_count {
+                    return err!(unreachable_arm, arm.location);
+                }
+                wildcard = true;
+            }
+            Pattern::Variant(name_id, bindings) => {
+                let (id, func, variant) = scope
+                    .get(&name_id.token)
+                    .into_iter()
+                    .flatten()
+                    .enumerate()
+                    .find_map(|(id, n)| match n.symbol() {
+                        Symbol::Variant(func, variant) if *func.ret == *enum_type => {
+                            Some((id, func, variant))
+                        }
+                        _ => None,
+                    })
+                    .ok_or_else(|| {
+                        error!(expected_variant_pattern, name_id.location, &name_id.token)
+                    })?;
+                name_id.id = id;
+
+                if wildcard || covered.contains(&variant.tag) {
+                    return err!(unreachable_arm, arm.location);
+                }
+                covered.push(variant.tag);
+
+                if bindings.len() != func.params.len() {
+                    return err!(
+                        pattern_field_count,
+                        arm.location,
+                        func.params.len(),
+                        bindings.len()
+                    );
+                }
+
+                for (binding, typ) in bindings.iter().zip(&func.params) {
+                    if binding.token != "_" {
+                        let symbol = Symbol::Var(typ.clone());
+                        arm_namespace.insert_namespaces(
+                            binding.token.clone(),
+                            vec![Namespace::from(symbol)],
+                        );
+                    }
+                }
+            }
         }
+
+        typecheck_expr(&mut arm.body, outer_ret, &scope.push(&arm_namespace), cache)?;
+    }
+
+    if !wildcard && covered.len() < variant_count {
+        let missing = missing_variants(enum_namespace, &covered);
+        return err!(non_exhaustive_match, match_expr.location, missing);
     }
+
+    Ok(())
 }
 
+/// Finds the enums with a variant named by the first variant arm and
+/// typechecks the matched expression against them, latest first.
+fn typecheck_match_expr<'a>(
+    match_expr: &mut Match,
+    scope: &Scope<'a>,
+    cache: &mut Cache,
+) -> Result<&'a Type, Error> {
+    let (token, location) = match_expr
+        .arms
+        .iter()
+        .find_map(|arm| match &arm.pattern {
+            Pattern::Variant(
+                NameId {
+                    token, location, ..
+                },
+                _,
+            ) => Some((token, *location)),
+            Pattern::Wildcard => None,
+        })
+        .ok_or_else(|| error!(expected_match_variant, match_expr.location))?;
+
+    let namespaces = scope
+        .get(token)
+        .ok_or_else(|| error!(expected_defined_symbol, location, token))?;
+
+    let candidates: Vec<_> = namespaces
+        .iter()
+        .filter_map(|n| match n.symbol() {
+            Symbol::Variant(func, _) => Some(&*func.ret),
+            _ => None,
+        })
+        .collect();
+
+    match &candidates[..] {
+        [] => err!(expected_variant_pattern, location, token),
+        [typ] => {
+            typecheck_expr(&mut match_expr.expr, typ, scope, cache)?;
+            Ok(*typ)
+        }
+        _ => candidates
+            .iter()
+            .rev()
+            .find(|typ| typecheck_expr(&mut match_expr.expr, typ, scope, cache).is_ok())
+            .copied()
+            .ok_or_else(|| error!(no_type_match, match_expr.expr.location())),
+    }
+}
+
+/// Lists the variants left out of a match, in the order they are defined.
+fn missing_variants(enum_namespace: &Namespace, covered: &[usize]) -> String {
+    let mut missing: Vec<_> = enum_namespace
+        .iter()
+        .filter_map(|(name, namespaces)| match namespaces[0].symbol() {
+            Symbol::Variant(_, variant) if !covered.contains(&variant.tag) => {
+                Some((variant.tag, format!("`{name}`")))
+            }
+            _ => None,
+        })
+        .collect();
+    missing.sort();
+    let missing: Vec<_> = missing.into_iter().map(|(_, name)| name).collect();
+    missing.join(", ")
+}
+
+/// Returns the id of the symbol the value refers to, which tells overloaded
+/// defs apart when one is used as a function value, and the type arguments of
+/// a generic def used as one.
 fn typecheck_val(
     token: &str,
     token_location: Location,
-    outer_ret: Terminal,
-    namespace: &Namespace,
-    def_namespace: &Namespace,
-) -> Result<(), Error> {
-    let namespaces = def_namespace
-        .get_or(namespace, token)
+    outer_ret: &Type,
+    scope: &Scope,
+) -> Result<(Id, Vec<Type>), Error> {
+    let namespaces = scope
+        .get(token)
         .ok_or_else(|| error!(expected_defined_symbol, token_location, &token))?;
 
-    for namespace in namespaces {
-        let terminal = match namespace.symbol() {
-            Symbol::Literal(terminal) | Symbol::Var(Type::Terminal(terminal)) => terminal,
+    let mut builtin = false;
+    for (id, namespace) in namespaces.iter().enumerate() {
+        let matches = match namespace.symbol() {
+            // Builtins are lowered in place, so there is no function to
+            // point to.
+            Symbol::Var(typ) if namespace.is_builtin() => {
+                builtin |= typ == outer_ret;
+                false
+            }
+            // Nor is there one for a method, only for its overloads.
+            Symbol::Method(..) => false,
+            Symbol::Extern(func) => matches!(outer_ret, Type::Func(outer) if outer == func),
+            Symbol::Generic(func, params, _) => {
+                let mut args = vec![None; params.len()];
+                let func = Type::Func(func.clone());
+                if unify(&func, outer_ret, params, &mut args) {
+                    if let Some(args) = args.into_iter().collect() {
+                        return Ok((id, args));
+                    }
+                }
+                false
+            }
+            Symbol::Literal(Type::Terminal(terminal))
+                if Type::Terminal(*terminal) == *outer_ret =>
+            {
+                if *terminal != Terminal::Bool && !literalise::fits(token, *terminal) {
+                    return err!(literal_out_of_range, token_location, token, terminal);
+                }
+                true
+            }
+            Symbol::Literal(typ) | Symbol::Var(typ) | Symbol::MutVar(typ) => typ == outer_ret,
             _ => return err!(expected_literal_or_var, token_location),
         };
 
-        if *terminal == outer_ret {
-            return Ok(());
+        if matches {
+            return Ok((id, Vec::new()));
         }
     }
+    if builtin {
+        return err!(builtin_value, token_location, token);
+    }
     err!(type_mismatch, token_location, outer_ret)
 }
 
 fn typecheck_exprs(
     exprs: &mut [Expr],
     exprs_location: Location,
-    outer_ret: Terminal,
-    namespace: &Namespace,
-    def_namespace: &Namespace,
+    outer_ret: &Type,
+    scope: &Scope,
+    cache: &mut Cache,
 ) -> Result<(), Error> {
     let parent_expr = if let Some(expr) = exprs.first() {
         expr
     } else {
-        if outer_ret != Terminal::Void {
-            return err!(type_mismatch, exprs_location, outer_ret, Terminal::Void);
-        }
-        return Ok(());
+        return typecheck_void(outer_ret, exprs_location);
     };
 
     let (parent_token, parent_location) = if let Expr::Val(NameId {
@@ -123,80 +509,548 @@ fn typecheck_exprs(
         return err!(expected_func, exprs_location);
     };
 
-    let namespaces = def_namespace
-        .get_or(namespace, parent_token)
+    let namespaces = scope
+        .get(parent_token)
         .ok_or_else(|| error!(expected_defined_symbol, parent_location, parent_token))?;
 
-    if let [n] = &namespaces[..] {
-        return typecheck_call(
-            n.symbol(),
-            outer_ret,
-            exprs,
-            namespace,
-            def_namespace,
-            parent_location,
-        );
-    }
+    // A struct name is both a type and its constructor, so only the callable
+    // symbols under a name are candidates.
+    let candidates: Vec<_> = namespaces
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| n.symbol().func().is_some())
+        .collect();
 
-    let mut called_id = None;
-    for (symbol_id, n) in namespaces.iter().enumerate().rev() {
-        if typecheck_call(
-            n.symbol(),
-            outer_ret,
-            exprs,
-            namespace,
-            def_namespace,
-            parent_location,
-        )
-        .is_ok()
-        {
-            called_id = Some(symbol_id);
-            break;
+    let key = (exprs.as_ptr(), outer_ret.clone());
+    let (called_id, called_type_args) = match cache.calls.get(&key) {
+        // Once the search is over, only the candidate it settled on is
+        // checked, to set the ids in the arguments.
+        Some((symbol_id, type_args, _)) if !cache.searching => {
+            let (symbol_id, type_args) = (*symbol_id, type_args.clone());
+            let symbol = namespaces[symbol_id].symbol();
+            typecheck_call(symbol, outer_ret, exprs, scope, parent_location, cache)?;
+            (symbol_id, type_args)
         }
-    }
-
-    let called_id = called_id.ok_or_else(|| error!(no_type_match, parent_location))?;
+        _ => {
+            let (symbol_id, type_args) = match &candidates[..] {
+                [] => return err!(expected_func, parent_location),
+                [(symbol_id, n)] => {
+                    let type_args = typecheck_call(
+                        n.symbol(),
+                        outer_ret,
+                        exprs,
+                        scope,
+                        parent_location,
+                        cache,
+                    )?;
+                    (*symbol_id, type_args)
+                }
+                _ => {
+                    resolve_overload(&candidates, outer_ret, exprs, scope, parent_location, cache)?
+                }
+            };
+            let symbol = namespaces[symbol_id].symbol();
+            let count = call_literal_count(symbol, &type_args, exprs, cache);
+            cache
+                .calls
+                .insert(key, (symbol_id, type_args.clone(), count));
+            (symbol_id, type_args)
+        }
+    };
 
-    if let Expr::Val(NameId { id, .. }) = exprs.first_mut().unwrap() {
+    if let Expr::Val(NameId { id, type_args, .. }) = exprs.first_mut().unwrap() {
         *id = called_id;
+        *type_args = called_type_args;
         Ok(())
     } else {
         panic!()
     }
 }
 
+/// Picks the candidate a call with several is made to. Each one the call
+/// checks against is ranked by how many literals in its arguments it takes at
+/// a type other than the one they are first inferred to have, and then by
+/// whether it is generic, so that an exact match beats one through a generic
+/// def or method. Candidates tying for the best rank make the call ambiguous.
+fn resolve_overload(
+    candidates: &[(Id, &Namespace)],
+    outer_ret: &Type,
+    exprs: &mut [Expr],
+    scope: &Scope,
+    location: Location,
+    cache: &mut Cache,
+) -> Result<(Id, Vec<Type>), Error> {
+    // A literal out of range is reported over a mismatch, since the
+    // candidate it came from matched everything up to it.
+    let mut out_of_range = None;
+    let mut viable = Vec::new();
+    for (symbol_id, namespace) in candidates {
+        match typecheck_call(namespace.symbol(), outer_ret, exprs, scope, location, cache) {
+            Ok(type_args) => {
+                let literals = call_literal_count(namespace.symbol(), &type_args, exprs, cache);
+                let generic =
+                    matches!(namespace.symbol(), Symbol::Generic(..) | Symbol::Method(..));
+                viable.push(((literals, generic), *symbol_id, *namespace, type_args));
+            }
+            Err(error) => {
+                if error.name() == "literal_out_of_range" && out_of_range.is_none() {
+                    out_of_range = Some(error);
+                }
+            }
+        }
+    }
+
+    let Some(best) = viable.iter().map(|(rank, ..)| *rank).min() else {
+        return Err(out_of_range.unwrap_or_else(|| error!(no_type_match, location)));
+    };
+    let mut tied: Vec<_> = viable
+        .into_iter()
+        .filter(|(rank, ..)| *rank == best)
+        .collect();
+    // An extern of a builtin's type shadows it, which `resolve` warns about,
+    // so a call to either is made to the extern.
+    tied.sort_by_key(|(_, symbol_id, ..)| *symbol_id);
+    let mut i = 0;
+    while i < tied.len() {
+        let func = tied[i].2.symbol().func();
+        if tied[i + 1..]
+            .iter()
+            .any(|later| later.2.symbol().func() == func)
+        {
+            tied.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    if tied.len() > 1 {
+        let Some(Expr::Val(NameId { token, .. })) = exprs.first() else {
+            panic!()
+        };
+        let mut error = error!(ambiguous_overload, location, token);
+        for (_, _, namespace, _) in tied {
+            error = error.with_note(namespace.location(), definition_note(token, namespace));
+        }
+        return Err(error);
+    }
+
+    let (_, symbol_id, _, type_args) = tied.pop().unwrap();
+    Ok((symbol_id, type_args))
+}
+
+/// How many literals in the arguments of a call to `func_symbol` take a type
+/// other than their default. Each argument has just been checked against its
+/// parameter, so the count is cached.
+fn call_literal_count(
+    func_symbol: &Symbol,
+    type_args: &[Type],
+    exprs: &[Expr],
+    cache: &Cache,
+) -> usize {
+    let func = func_symbol.func().unwrap();
+    let type_params = type_params(func_symbol);
+    func.params
+        .iter()
+        .zip(&exprs[1..])
+        .map(|(param, arg)| {
+            let param = param.substitute(type_params, type_args);
+            match cache.checked.get(&(ptr::from_ref(arg), param)) {
+                Some(Ok(count)) => *count,
+                _ => 0,
+            }
+        })
+        .sum()
+}
+
+/// How many literals an expression just checked against `typ` may take a
+/// type other than their default through: its own, if it is one, and those
+/// of whatever gives it its value.
+fn literal_count(expr: &Expr, typ: &Type, scope: &Scope, cache: &Cache) -> usize {
+    let count = |expr: &Expr| match cache.checked.get(&(ptr::from_ref(expr), typ.clone())) {
+        Some(Ok(count)) => *count,
+        _ => 0,
+    };
+    match expr {
+        Expr::Val(name_id) => usize::from(is_inexact_literal(name_id, scope)),
+        Expr::Call(Call { exprs, .. }) => cache
+            .calls
+            .get(&(exprs.as_ptr(), typ.clone()))
+            .map_or(0, |(_, _, count)| *count),
+        Expr::If(If { then, els, .. }) => count(then) + count(els),
+        Expr::Let(Let { body, .. }) => count(body),
+        Expr::Do(Do { exprs, .. }) => count(exprs.last().unwrap()),
+        Expr::Match(Match { arms, .. }) => arms.iter().map(|arm| count(&arm.body)).sum(),
+        Expr::Set(_) | Expr::While(_) | Expr::Lambda(_) => 0,
+    }
+}
+
+/// Whether a name is a literal given a type other than the `i32` or `f64` it
+/// would otherwise be taken to be.
+fn is_inexact_literal(name_id: &NameId, scope: &Scope) -> bool {
+    let Some(namespaces) = scope.get(&name_id.token) else {
+        return false;
+    };
+    // A literal with a suffix has only the one type.
+    namespaces.len() > 1
+        && matches!(
+            namespaces[name_id.id].symbol(),
+            Symbol::Literal(Type::Terminal(terminal))
+                if !matches!(terminal, Terminal::I32 | Terminal::F64 | Terminal::Bool)
+        )
+}
+
+/// Returns the type arguments of a call to a generic def or method, which are
+/// none for any other function. A method's only type argument is the instance
+/// it is called on.
 fn typecheck_call(
     func_symbol: &Symbol,
-    outer_ret: Terminal,
+    outer_ret: &Type,
     arg_exprs: &mut [Expr],
-    namespace: &Namespace,
-    def_namespace: &Namespace,
+    scope: &Scope,
     location: Location,
-) -> Result<(), Error> {
-    let func = if let Symbol::Var(Type::Func(func)) = func_symbol {
+    cache: &mut Cache,
+) -> Result<Vec<Type>, Error> {
+    let func = if let Some(func) = func_symbol.func() {
         func
     } else {
         return err!(expected_func, location);
     };
 
-    if func.ret != outer_ret {
+    let type_params = type_params(func_symbol);
+    let constraints = match func_symbol {
+        Symbol::Generic(_, _, constraints) => constraints.clone(),
+        Symbol::Method(_, class) => vec![(class.clone(), class.clone())],
+        _ => Vec::new(),
+    };
+    let mut type_args = vec![None; type_params.len()];
+
+    if !unify(&func.ret, outer_ret, type_params, &mut type_args) {
         return err!(func_type_mismatch, location, outer_ret, func.ret);
     }
+    check_constraints(&constraints, type_params, &type_args, scope, location)?;
 
     let mut params = func.params.iter();
     let mut args = arg_exprs.iter_mut().skip(1);
     loop {
         match (params.next(), args.next()) {
-            (None, None) => return Ok(()),
-            (None, Some(arg)) => match arg {
-                Expr::Val(NameId { location, .. }) | Expr::Call(Call { location, .. }) => {
-                    return err!(unexpected_argument, *location);
-                }
-            },
+            (None, None) => break,
+            (None, Some(arg)) => return err!(unexpected_argument, arg.location()),
             (Some(_), None) => return err!(expected_argument, location),
             (Some(param), Some(arg)) => {
-                typecheck_expr(arg, *param, namespace, def_namespace)?;
+                if let Some(param) = bind(param, type_params, &type_args) {
+                    typecheck_expr(arg, &param, scope, cache)?;
+                } else {
+                    typecheck_generic_arg(arg, param, type_params, &mut type_args, scope, cache)?;
+                }
+            }
+        }
+    }
+
+    check_constraints(&constraints, type_params, &type_args, scope, location)?;
+    type_params
+        .iter()
+        .zip(type_args)
+        .map(|(param, arg)| arg.ok_or_else(|| error!(unresolved_type_param, location, param)))
+        .collect()
+}
+
+/// The type parameters of a generic def, or the class of a method.
+fn type_params(func_symbol: &Symbol) -> &[String] {
+    match func_symbol {
+        Symbol::Generic(_, type_params, _) => type_params,
+        Symbol::Method(_, class) => slice::from_ref(class),
+        _ => &[],
+    }
+}
+
+/// Checks that each type argument bound so far is an instance of the classes
+/// its parameter is constrained by.
+fn check_constraints(
+    constraints: &[(String, String)],
+    type_params: &[String],
+    type_args: &[Option<Type>],
+    scope: &Scope,
+    location: Location,
+) -> Result<(), Error> {
+    for (param, class) in constraints {
+        let index = type_params.iter().position(|p| p == param).unwrap();
+        if let Some(arg) = &type_args[index] {
+            if !is_instance(arg, class, scope) {
+                return err!(missing_instance, location, arg, class);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `typ` is an instance of `class`. A type parameter is one if the def
+/// being checked constrains it to be.
+fn is_instance(typ: &Type, class: &str, scope: &Scope) -> bool {
+    if let Type::Param(param) = typ {
+        return scope
+            .constraints()
+            .iter()
+            .any(|(p, c)| p == param && c == class);
+    }
+    scope.root().get(class).into_iter().flatten().any(
+        |namespace| matches!(namespace.symbol(), Symbol::Class(c) if c.instances.contains(typ)),
+    )
+}
+
+/// Checks an argument whose parameter mentions a type parameter that is not
+/// bound yet, trying each type the argument could have in turn.
+fn typecheck_generic_arg(
+    arg: &mut Expr,
+    param: &Type,
+    type_params: &[String],
+    type_args: &mut Vec<Option<Type>>,
+    scope: &Scope,
+    cache: &mut Cache,
+) -> Result<(), Error> {
+    let mut first_error = None;
+    for typ in infer_types(arg, scope, cache) {
+        let mut attempt = type_args.clone();
+        if !unify(param, &typ, type_params, &mut attempt) {
+            continue;
+        }
+        let Some(bound) = bind(param, type_params, &attempt) else {
+            continue;
+        };
+        match typecheck_expr(arg, &bound, scope, cache) {
+            Ok(()) => {
+                *type_args = attempt;
+                return Ok(());
+            }
+            Err(error) => {
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+
+    let unbound = type_params
+        .iter()
+        .zip(type_args.iter())
+        .find(|(_, arg)| arg.is_none())
+        .map(|(param, _)| param)
+        .unwrap();
+    Err(first_error.unwrap_or_else(|| error!(unresolved_type_param, arg.location(), unbound)))
+}
+
+/// Matches `typ` against `pattern`, binding the type parameters `pattern`
+/// mentions. Fails if a parameter is already bound to a different type.
+fn unify(
+    pattern: &Type,
+    typ: &Type,
+    type_params: &[String],
+    type_args: &mut [Option<Type>],
+) -> bool {
+    match (pattern, typ) {
+        (Type::Param(name), _) if type_params.contains(name) => {
+            let index = type_params.iter().position(|param| param == name).unwrap();
+            type_args[index].get_or_insert_with(|| typ.clone()) == typ
+        }
+        (Type::Ptr(pattern), Type::Ptr(typ)) | (Type::Slice(pattern), Type::Slice(typ)) => {
+            unify(pattern, typ, type_params, type_args)
+        }
+        (Type::Array(pattern, pattern_len), Type::Array(typ, len)) => {
+            pattern_len == len && unify(pattern, typ, type_params, type_args)
+        }
+        (Type::Func(pattern), Type::Func(func)) => {
+            pattern.params.len() == func.params.len()
+                && pattern
+                    .params
+                    .iter()
+                    .zip(&func.params)
+                    .all(|(pattern, typ)| unify(pattern, typ, type_params, type_args))
+                && unify(&pattern.ret, &func.ret, type_params, type_args)
+        }
+        _ => pattern == typ,
+    }
+}
+
+/// Substitutes the bound type parameters into `typ`, unless it mentions one
+/// that is not bound yet.
+fn bind(typ: &Type, type_params: &[String], type_args: &[Option<Type>]) -> Option<Type> {
+    let unbound = |name: &str| {
+        type_params
+            .iter()
+            .zip(type_args)
+            .any(|(param, arg)| param == name && arg.is_none())
+    };
+    if typ.mentions(&unbound) {
+        return None;
+    }
+    let args: Vec<_> = type_args.iter().flatten().cloned().collect();
+    let params: Vec<_> = type_params
+        .iter()
+        .zip(type_args)
+        .filter(|(_, arg)| arg.is_some())
+        .map(|(param, _)| param.clone())
+        .collect();
+    Some(typ.substitute(&params, &args))
+}
+
+/// The types an expression could have, found without knowing the type
+/// expected of it. These are only candidates: the expression may still fail
+/// to check against one of them.
+fn infer_types(expr: &Expr, scope: &Scope, cache: &mut Cache) -> Vec<Type> {
+    let key = ptr::from_ref(expr);
+    if let Some(types) = cache.inferred.get(&key) {
+        return types.clone();
+    }
+
+    let mut types = Vec::new();
+    for typ in infer_types_uncached(expr, scope, cache) {
+        if !types.contains(&typ) {
+            types.push(typ);
+        }
+    }
+    cache.inferred.insert(key, types.clone());
+    types
+}
+
+fn infer_types_uncached(expr: &Expr, scope: &Scope, cache: &mut Cache) -> Vec<Type> {
+    match expr {
+        Expr::Val(NameId { token, .. }) => {
+            let mut types: Vec<_> = scope
+                .get(token)
+                .into_iter()
+                .flatten()
+                .filter_map(|namespace| match namespace.symbol() {
+                    Symbol::Var(_) if namespace.is_builtin() => None,
+                    Symbol::Literal(typ) | Symbol::Var(typ) | Symbol::MutVar(typ) => {
+                        Some(typ.clone())
+                    }
+                    Symbol::Extern(func) => Some(Type::Func(func.clone())),
+                    _ => None,
+                })
+                .collect();
+            // A numeric literal is taken to be an `i32` or `f64` first, as in C.
+            types.sort_by_key(|typ| !matches!(typ, Type::Terminal(Terminal::I32 | Terminal::F64)));
+            types
+        }
+        Expr::Call(Call { exprs, .. }) => match exprs.first() {
+            Some(Expr::Val(NameId { token, .. })) => scope
+                .get(token)
+                .into_iter()
+                .flatten()
+                .filter_map(|namespace| namespace.symbol().func())
+                .filter(|func| func.params.len() + 1 == exprs.len())
+                .flat_map(|func| {
+                    if !func.ret.is_generic() {
+                        return vec![(*func.ret).clone()];
+                    }
+                    // A generic def returning one of its parameters returns
+                    // the type of any argument given for that parameter.
+                    func.params
+                        .iter()
+                        .zip(&exprs[1..])
+                        .filter(|(param, _)| *param == &*func.ret)
+                        .flat_map(|(_, arg)| infer_types(arg, scope, cache))
+                        .collect()
+                })
+                .collect(),
+            _ => Vec::new(),
+        },
+        Expr::If(If { then, .. }) => infer_types(then, scope, cache),
+        Expr::Let(Let { bindings, body, .. }) => {
+            let mut let_namespace = Namespace::from(Symbol::Scope);
+            for binding in bindings {
+                if let Ok(typ) = get_generic_type(&binding.typ, scope.type_params(), scope.root()) {
+                    let namespaces = vec![Namespace::from(Symbol::Var(typ))];
+                    let_namespace.insert_namespaces(binding.name.token.clone(), namespaces);
+                }
+            }
+            infer_types(body, &scope.push(&let_namespace), cache)
+        }
+        Expr::Do(Do { exprs, .. }) => infer_types(exprs.last().unwrap(), scope, cache),
+        Expr::Match(Match { arms, .. }) => arms
+            .iter()
+            .flat_map(|arm| infer_types(&arm.body, scope, cache))
+            .collect(),
+        Expr::Lambda(Lambda { func, .. }) => resolve_func(func, scope.type_params(), scope.root())
+            .map(|(func, _)| vec![Type::Func(func)])
+            .unwrap_or_default(),
+        Expr::Set(_) | Expr::While(_) => vec![Type::Terminal(Terminal::Void)],
+    }
+}
+
+/// A call marked `tail` is lowered to a guaranteed tail call, which needs the
+/// callee to have the same prototype as the caller. A lambda takes its
+/// environment first, so it can only make one to another function value, and
+/// a def only to another def.
+fn typecheck_tail_call(exprs: &[Expr], location: Location, scope: &Scope) -> Result<(), Error> {
+    let def_symbol = scope.def_symbol();
+    if let Some(Expr::Val(NameId { token, id, .. })) = exprs.first() {
+        let symbol = scope
+            .get(token)
+            .and_then(|namespaces| namespaces.get(*id))
+            .map(Namespace::symbol);
+        let matches = match (symbol, def_symbol) {
+            (Some(symbol), Symbol::Lambda(func)) if scope.is_local(token) => {
+                symbol.func() == Some(func)
+            }
+            (_, Symbol::Lambda(_)) => false,
+            _ if scope.is_local(token) => false,
+            (Some(Symbol::Extern(func)), _) => def_symbol.func() == Some(func),
+            (symbol, _) => symbol == Some(def_symbol),
+        };
+        if !matches {
+            return err!(tail_type_mismatch, location);
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every call marked `tail` is in tail position. This runs before
+/// the types are checked, so that a misplaced call is reported as such.
+fn typecheck_tail(expr: &Expr, tail: bool) -> Result<(), Error> {
+    match expr {
+        Expr::Val(_) => Ok(()),
+        Expr::Call(Call {
+            exprs,
+            tail: marked,
+            location,
+        }) => {
+            if *marked && !tail {
+                return err!(unexpected_tail, *location);
+            }
+            for expr in exprs {
+                typecheck_tail(expr, false)?;
+            }
+            Ok(())
+        }
+        Expr::If(If {
+            cond, then, els, ..
+        }) => {
+            typecheck_tail(cond, false)?;
+            typecheck_tail(then, tail)?;
+            typecheck_tail(els, tail)
+        }
+        Expr::Let(Let { bindings, body, .. }) => {
+            for binding in bindings {
+                typecheck_tail(&binding.expr, false)?;
+            }
+            typecheck_tail(body, tail)
+        }
+        Expr::Set(Set { expr, .. }) => typecheck_tail(expr, false),
+        Expr::While(While { cond, body, .. }) => {
+            typecheck_tail(cond, false)?;
+            typecheck_tail(body, false)
+        }
+        Expr::Do(Do { exprs, .. }) => {
+            let (last, stmts) = exprs.split_last().unwrap();
+            for stmt in stmts {
+                typecheck_tail(stmt, false)?;
+            }
+            typecheck_tail(last, tail)
+        }
+        Expr::Match(Match { expr, arms, .. }) => {
+            typecheck_tail(expr, false)?;
+            for arm in arms {
+                typecheck_tail(&arm.body, tail)?;
             }
+            Ok(())
         }
+        Expr::Lambda(Lambda { body, .. }) => typecheck_tail(body, true),
     }
 }
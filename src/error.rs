@@ -1,5 +1,6 @@
 #[derive(Debug)]
 pub struct Error {
+    #[allow(dead_code)]
     name: &'static str,
     message: String,
     lamb_file: &'static str,
@@ -72,6 +73,10 @@ macro_rules! error_impl {
         )
     };
 
+    (header_write_failed, $name:tt, $path:expr) => {
+        error_new!($name, format!("Could not write header `{}`.", $path))
+    };
+
     (clang_spawn_failed, $name:tt) => {
         error_new!(
             $name,
@@ -2,6 +2,18 @@
 #![allow(clippy::collapsible_if)]
 #![allow(clippy::redundant_else)]
 #![allow(clippy::needless_pass_by_value)]
+// Lints newer than the code they fire on, left for a separate cleanup.
+#![allow(clippy::uninlined_format_args)]
+#![allow(clippy::equatable_if_let)]
+#![allow(clippy::collapsible_else_if)]
+#![allow(clippy::get_first)]
+#![allow(clippy::inconsistent_struct_constructor)]
+#![allow(clippy::missing_const_for_fn)]
+#![allow(clippy::manual_let_else)]
+#![allow(clippy::use_self)]
+#![allow(clippy::redundant_slicing)]
+#![allow(clippy::semicolon_if_nothing_returned)]
+#![allow(clippy::needless_borrows_for_generic_args)]
 
 #[macro_use]
 mod error;
@@ -12,11 +24,12 @@ use std::collections::HashSet;
 use std::env;
 use std::fs;
 use std::io::Write;
+use std::path::Path;
 use std::process::Command;
 use std::process::Stdio;
 
 fn main() {
-    let (text, args) = match read_text() {
+    let (filename, text, args) = match read_text() {
         Ok(text) => text,
         Err(error) => {
             error.print();
@@ -24,12 +37,37 @@ fn main() {
         }
     };
 
-    if dbg_pipeline(&text, args) {
+    let options = compiler::Options {
+        checked: args.contains("--checked"),
+    };
+
+    if dbg_pipeline(&text, &args, &options) {
+        return;
+    }
+
+    if args.contains("--emit-header") {
+        let path = Path::new(&filename).with_extension("h");
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let header = match compiler::header(&text, &name) {
+            Ok(header) => header,
+            Err(error) => {
+                error.print(&text);
+                return;
+            }
+        };
+        if fs::write(&path, header).is_err() {
+            error!(header_write_failed, path.display()).print();
+        }
         return;
     }
 
-    let code = match compiler::main(&text) {
-        Ok(code) => code,
+    let code = match compiler::main(&text, &options) {
+        Ok((code, warnings)) => {
+            for warning in warnings {
+                warning.print(&text);
+            }
+            code
+        }
         Err(error) => {
             error.print(&text);
             return;
@@ -41,7 +79,7 @@ fn main() {
     }
 }
 
-fn read_text() -> Result<(String, HashSet<String>), Error> {
+fn read_text() -> Result<(String, String, HashSet<String>), Error> {
     let mut filename = None;
     let mut args = HashSet::new();
     for arg in env::args().skip(1) {
@@ -53,7 +91,7 @@ fn read_text() -> Result<(String, HashSet<String>), Error> {
     }
     let filename = filename.ok_or_else(|| error!(expected_filename))?;
     let text = fs::read_to_string(&filename).map_err(|_| error!(file_error, filename))?;
-    Ok((text, args))
+    Ok((filename, text, args))
 }
 
 fn clang(code: String) -> Result<(), Error> {
@@ -90,20 +128,11 @@ fn clang(code: String) -> Result<(), Error> {
     }
 }
 
-fn dbg_pipeline(text: &str, args: HashSet<String>) -> bool {
-    macro_rules! dbg_pipeline {
-        ($text:ident, $args:ident, $arg:literal, $stage:path) => {
-            if $args.contains($arg) {
-                dbg!($stage($text));
-                return true;
-            }
-        };
-    }
-
+fn dbg_pipeline(text: &str, args: &HashSet<String>, options: &compiler::Options) -> bool {
     macro_rules! dbg_pipeline_err {
-        ($text:ident, $args:ident, $arg:literal, $stage:path) => {
+        ($text:ident, $args:ident, $arg:literal, $stage:path $(, $option:ident)?) => {
             if $args.contains($arg) {
-                match $stage($text) {
+                match $stage($text $(, $option)?) {
                     Ok(code) => {
                         dbg!(code);
                     }
@@ -116,14 +145,14 @@ fn dbg_pipeline(text: &str, args: HashSet<String>) -> bool {
         };
     }
 
-    dbg_pipeline!(text, args, "--lex", compiler::lex);
-    dbg_pipeline!(text, args, "--literalise", compiler::literalise);
-    dbg_pipeline!(text, args, "--treeify", compiler::treeify);
+    dbg_pipeline_err!(text, args, "--lex", compiler::lex);
+    dbg_pipeline_err!(text, args, "--literalise", compiler::literalise);
+    dbg_pipeline_err!(text, args, "--treeify", compiler::treeify);
     dbg_pipeline_err!(text, args, "--parse", compiler::parse);
     dbg_pipeline_err!(text, args, "--resolve", compiler::resolve);
     dbg_pipeline_err!(text, args, "--typecheck", compiler::typecheck);
-    dbg_pipeline_err!(text, args, "--generate", compiler::generate);
-    dbg_pipeline_err!(text, args, "--emit", compiler::emit);
+    dbg_pipeline_err!(text, args, "--generate", compiler::generate, options);
+    dbg_pipeline_err!(text, args, "--emit", compiler::emit, options);
 
     false
 }